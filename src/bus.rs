@@ -0,0 +1,321 @@
+use crate::consts::*;
+use crate::ppu::PPU;
+use crate::ram_memory::RamMemory;
+use crate::joypad::{Joypad, Button};
+use crate::cheats::Cheats;
+use crate::model::Model;
+
+// Everything the cpu sees through its address space. The cpu is handed one when stepping, so
+// a machine can own all of its parts (and tests can use something simpler than a whole Game Boy)
+pub trait Bus {
+    fn get_addr(&self, addr: u16) -> u8;
+    fn set_addr(&mut self, addr: u16, value: u8);
+
+    // Let the rest of the hardware catch up with the cpu
+    fn tick(&mut self, cycles: usize);
+
+    // STOP instruction
+    fn stop(&mut self) {}
+
+    // After a STOP, until a button is pressed
+    fn is_stopped(&self) -> bool {
+        false
+    }
+}
+
+// The Game Boy's memory map, routing addresses to the cartridge/ram, the ppu and the other io registers
+pub struct MemoryBus {
+    ram_memory: RamMemory,
+    ppu: PPU,
+    joypad: Joypad,
+    cheats: Cheats,
+    double_speed: bool, // CGB only, switched with KEY1 and STOP
+    speed_switch_armed: bool,
+    stopped: bool,
+    serial_output: Vec<u8>, // Every byte sent over the link cable, nothing is ever on the other end
+    cycle_counter: usize,
+    frame_counter: usize
+}
+
+impl MemoryBus {
+    pub fn new(ram_memory: RamMemory) -> MemoryBus {
+        MemoryBus {
+            ram_memory,
+            ppu: PPU::init(),
+            joypad: Joypad::new(),
+            cheats: Cheats::new(),
+            double_speed: false,
+            speed_switch_armed: false,
+            stopped: false,
+            serial_output: Vec::new(),
+            cycle_counter: 0,
+            frame_counter: 0
+        }
+    }
+
+    // The io registers as the boot rom leaves them. They go straight to ram, through set_addr they'd
+    // look like the program writing them (resetting DIV, starting a DMA...)
+    pub fn apply_post_boot_state(&mut self, model: Model) {
+        for (addr, value) in model.get_post_boot_io_registers(self.is_cgb_mode()) {
+            self.ram_memory.set_addr(addr, value);
+        }
+    }
+
+    // Called every time the ppu gets to VBlank
+    fn on_frame(&mut self) {
+        self.frame_counter += 1;
+        self.request_interrupt(INTERRUPT_BIT_VBLANK);
+
+        for (addr, value) in self.cheats.get_frame_writes() {
+            self.set_addr(addr, value);
+        }
+    }
+
+    // Set the bit in IF, the cpu takes it from there
+    pub fn request_interrupt(&mut self, bit: u8) {
+        let flags = self.ram_memory.get_addr(INTERRUPT_ADDR_FLAG);
+        self.ram_memory.set_addr(INTERRUPT_ADDR_FLAG, flags | (1 << bit));
+    }
+
+    // Transfers with the internal clock finish right away, with nothing connected 0xFF is shifted in.
+    // Test roms print their results this way
+    fn serial_control_set_handler(&mut self, value: u8) {
+        if bit_check(value, SERIAL_CONTROL_BIT_TRANSFER) && bit_check(value, SERIAL_CONTROL_BIT_INTERNAL_CLOCK) {
+            let data = self.ram_memory.get_addr(SERIAL_ADDR_DATA);
+            trace!("Serial: Sent 0x{:02X}", data);
+            self.serial_output.push(data);
+
+            self.ram_memory.set_addr(SERIAL_ADDR_DATA, 0xFF);
+            self.ram_memory.set_addr(SERIAL_ADDR_CONTROL, value & !(1 << SERIAL_CONTROL_BIT_TRANSFER));
+            self.request_interrupt(INTERRUPT_BIT_SERIAL);
+        } else {
+            self.ram_memory.set_addr(SERIAL_ADDR_CONTROL, value);
+        }
+    }
+
+    pub fn get_serial_output(&self) -> &[u8] {
+        &self.serial_output
+    }
+
+    pub fn render(&mut self) {
+        self.ppu.render(&self.ram_memory);
+    }
+
+    pub fn get_cycle_counter(&self) -> usize {
+        self.cycle_counter
+    }
+
+    pub fn get_frame_counter(&self) -> usize {
+        self.frame_counter
+    }
+
+    pub fn is_cgb_mode(&self) -> bool {
+        self.ram_memory.is_cgb_mode()
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    pub fn get_ram_memory(&self) -> &RamMemory {
+        &self.ram_memory
+    }
+
+    pub fn get_ppu(&self) -> &PPU {
+        &self.ppu
+    }
+
+    pub fn get_ppu_mut(&mut self) -> &mut PPU {
+        &mut self.ppu
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        // A new press wakes the cpu up from STOP
+        if pressed && !self.joypad.is_pressed(button) {
+            self.stopped = false;
+            self.request_interrupt(INTERRUPT_BIT_JOYPAD);
+        }
+
+        self.joypad.set_button(button, pressed);
+    }
+
+    pub fn get_joypad(&self) -> &Joypad {
+        &self.joypad
+    }
+
+    // Cheats stuff
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        self.cheats = cheats;
+    }
+
+    pub fn get_cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn get_cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cheats
+    }
+}
+
+impl Bus for MemoryBus {
+    fn get_addr(&self, addr: u16) -> u8 {
+        if addr < CARTRIDGE_ROM_SIZE_DEFAULT as u16 
+        { // 0x0000 -> 0x8000
+            let value = self.ram_memory.get_addr(addr);
+            return self.cheats.apply_rom_read(addr, value);
+        } 
+        else if addr >= CARTRIDGE_ROM_SIZE_DEFAULT as u16 && addr < RAM_ECHO_RANGE_START 
+        { // 0x8000 -> 0xE000
+            return self.ram_memory.get_addr(addr);
+        }
+        else if (RAM_ECHO_RANGE_START..RAM_SPRITE_ATTRIBUTE_TABLE_RANGE_START).contains(&addr)
+        { // 0xE000 -> 0xFE00
+            return self.ram_memory.get_addr(addr - 0x2000);
+        }
+        else if (RAM_SPRITE_ATTRIBUTE_TABLE_RANGE_START..RAM_IO_PORTS_RANGE_START).contains(&addr)
+        { // 0xFE00 -> 0xFF00
+            return self.ram_memory.get_addr(addr);
+        }
+        else if addr == JOYPAD_ADDR
+        { // 0xFF00 - Only the select bits are stored, the buttons come from the joypad
+            return self.joypad.read(self.ram_memory.get_addr(addr));
+        }
+        else if addr == INTERRUPT_ADDR_FLAG
+        { // 0xFF0F - Only the low 5 bits exist
+            return self.ram_memory.get_addr(addr) | INTERRUPT_FLAG_UNUSED_BITS;
+        }
+        else if (RAM_IO_PORTS_RANGE_START..RAM_EMPTY_RANGE_START).contains(&addr) 
+        { // 0xFF00 -> 0xFF4C
+            return self.ppu.get_addr(&self.ram_memory, addr);
+        } 
+        else if addr == CGB_ADDR_KEY1 && self.is_cgb_mode()
+        { // 0xFF4D - Speed switch
+            let mut value: u8 = 0x7E;
+            if self.double_speed {
+                value |= 1 << CGB_KEY1_BIT_CURRENT_SPEED;
+            }
+            if self.speed_switch_armed {
+                value |= 1 << CGB_KEY1_BIT_SWITCH_ARMED;
+            }
+            return value;
+        }
+        else if CGB_PALETTE_ADDR.contains(&addr) && self.is_cgb_mode()
+        { // 0xFF68 -> 0xFF6C - Color palettes
+            return self.ppu.get_addr(&self.ram_memory, addr);
+        }
+        else if CGB_HDMA_ADDR.contains(&addr) && self.is_cgb_mode()
+        { // 0xFF51 -> 0xFF56 - VRAM DMA
+            return self.ppu.get_addr(&self.ram_memory, addr);
+        }
+        else if (addr == CGB_ADDR_VBK || addr == CGB_ADDR_SVBK) && self.is_cgb_mode()
+        { // VRAM and WRAM banks
+            return self.ram_memory.get_addr(addr);
+        }
+        else if (RAM_EMPTY_RANGE_START..RAM_INTERNAL_RANGE_START).contains(&addr)
+        { // 0xFF4C -> 0xFF80
+            warn!("Requested addr at a memory addr that should not be used (0x{:04X})", addr);
+            return self.ram_memory.get_addr(addr);
+        } 
+        else
+        { // 0xFF80 -> END, the branches above cover everything below it
+            return self.ram_memory.get_addr(addr);
+        }
+    }
+
+    fn set_addr(&mut self, addr: u16, value: u8) {
+        if addr < CARTRIDGE_ROM_SIZE_DEFAULT as u16 
+        { // 0x0000 -> 0x8000
+            self.ram_memory.set_addr(addr, value);
+        } 
+        else if addr >= CARTRIDGE_ROM_SIZE_DEFAULT as u16 && addr < RAM_ECHO_RANGE_START 
+        { // 0x8000 -> 0xE000
+            debug!("VRAM: Write {} to addr 0x{:04X}", value, addr);
+            self.ram_memory.set_addr(addr, value);
+        }
+        else if (RAM_ECHO_RANGE_START..RAM_SPRITE_ATTRIBUTE_TABLE_RANGE_START).contains(&addr)
+        { // 0xE000 -> 0xFE00
+            self.ram_memory.set_addr(addr - 0x2000, value);
+        }
+        else if (RAM_SPRITE_ATTRIBUTE_TABLE_RANGE_START..RAM_IO_PORTS_RANGE_START).contains(&addr)
+        { // 0xFE00 -> 0xFF00
+            self.ram_memory.set_addr(addr, value);
+        }
+        else if addr == INTERRUPT_ADDR_FLAG
+        { // 0xFF0F - Only the low 5 bits exist
+            self.ram_memory.set_addr(addr, value & INTERRUPT_MASK);
+        }
+        else if addr == SERIAL_ADDR_CONTROL
+        { // 0xFF02 - Starts a transfer
+            self.serial_control_set_handler(value);
+        }
+        else if (RAM_IO_PORTS_RANGE_START..RAM_EMPTY_RANGE_START).contains(&addr)
+        { // 0xFF00 -> 0xFF4C
+            self.ppu.set_addr(&mut self.ram_memory, addr, value);
+        } 
+        else if addr == BOOT_ROM_DISABLE_ADDR
+        { // 0xFF50 - Unmaps the boot rom
+            self.ram_memory.set_addr(addr, value);
+        }
+        else if addr == CGB_ADDR_KEY1 && self.is_cgb_mode()
+        { // 0xFF4D - Speed switch, only the armed bit is writable
+            self.speed_switch_armed = bit_check(value, CGB_KEY1_BIT_SWITCH_ARMED);
+        }
+        else if CGB_PALETTE_ADDR.contains(&addr) && self.is_cgb_mode()
+        { // 0xFF68 -> 0xFF6C - Color palettes
+            self.ppu.set_addr(&mut self.ram_memory, addr, value);
+        }
+        else if CGB_HDMA_ADDR.contains(&addr) && self.is_cgb_mode()
+        { // 0xFF51 -> 0xFF56 - VRAM DMA
+            self.ppu.set_addr(&mut self.ram_memory, addr, value);
+        }
+        else if (addr == CGB_ADDR_VBK || addr == CGB_ADDR_SVBK) && self.is_cgb_mode()
+        { // VRAM and WRAM banks
+            self.ram_memory.set_addr(addr, value);
+        }
+        else if (RAM_EMPTY_RANGE_START..RAM_INTERNAL_RANGE_START).contains(&addr)
+        { // 0xFF4C -> 0xFF80
+            warn!("Requested write to addr at a memory addr that should not be used (0x{:04X})", addr);
+            self.ram_memory.set_addr(addr, value);
+        } 
+        else
+        { // 0xFF80 -> END, the branches above cover everything below it
+            self.ram_memory.set_addr(addr, value);
+        }
+    }
+
+    fn tick(&mut self, cycles: usize) {
+        // The ppu doesn't speed up in double speed mode
+        let speed_divider: usize = if self.double_speed { 2 } else { 1 };
+
+        self.ppu.tick(&mut self.ram_memory, cycles / speed_divider);
+
+        // VRAM DMA halts the cpu while the ppu keeps going
+        let dma_stall_cycles = self.ppu.take_dma_stall_cycles();
+        self.ppu.tick(&mut self.ram_memory, dma_stall_cycles);
+
+        self.cycle_counter += cycles + dma_stall_cycles * speed_divider;
+
+        if self.ppu.take_frame_ready() {
+            self.on_frame();
+        }
+    }
+
+    // Low power mode, or the speed switch on CGB
+    fn stop(&mut self) {
+        if self.is_cgb_mode() && self.speed_switch_armed {
+            self.double_speed = !self.double_speed;
+            self.speed_switch_armed = false;
+            info!("Switched to {} speed", if self.double_speed { "double" } else { "normal" });
+
+            // The cpu sits there until the clock settles
+            self.tick(CGB_SPEED_SWITCH_CYCLES);
+        } else {
+            debug!("Stopped until a button is pressed");
+            self.stopped = true;
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+}
@@ -0,0 +1,177 @@
+use crate::consts::*;
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatCode {
+    // Replaces what the cpu reads from the rom, only when the rom has compare there (if given)
+    GameGenie { addr: u16, value: u8, compare: Option<u8> },
+    // Writes value to ram every frame. The bank isn't used, the value goes to whatever bank is mapped
+    GameShark { bank: u8, addr: u16, value: u8 }
+}
+
+impl CheatCode {
+    // Game Genie codes are "ABC-DEF" or "ABC-DEF-GHI", GameShark codes are "ABCDEFGH"
+    pub fn parse(code: &str) -> Result<CheatCode, String> {
+        let digits: String = code.chars().filter(|c| *c != '-').collect();
+        let nibbles: Vec<u8> = digits.chars()
+            .map(|c| c.to_digit(16).map(|n| n as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(format!("Invalid cheat code \"{}\", not hex", code))?;
+
+        match nibbles.len() {
+            6 | 9 if code.contains('-') => Ok(Self::decode_game_genie(&nibbles)),
+            8 => Ok(Self::decode_game_shark(&nibbles)),
+            _ => Err(format!("Invalid cheat code \"{}\", expected a Game Genie (ABC-DEF[-GHI]) or GameShark (ABCDEFGH) code", code))
+        }
+    }
+
+    // AB is the value, FCDE the address (F is xored with 0xF), GI the compare value xored with 0xBA
+    // and then rotated left by 2, so it's rotated back right before the xor. H isn't used
+    fn decode_game_genie(nibbles: &[u8]) -> CheatCode {
+        let value = (nibbles[0] << 4) | nibbles[1];
+        let addr = (((nibbles[5] ^ 0xF) as u16) << 12) | ((nibbles[2] as u16) << 8) | ((nibbles[3] as u16) << 4) | nibbles[4] as u16;
+
+        let compare = if nibbles.len() == 9 {
+            let encoded = (nibbles[6] << 4) | nibbles[8];
+            Some(encoded.rotate_right(2) ^ GAME_GENIE_COMPARE_XOR)
+        } else {
+            None
+        };
+
+        CheatCode::GameGenie { addr, value, compare }
+    }
+
+    // AB is the ram bank, CD the value and GHEF the address (little endian)
+    fn decode_game_shark(nibbles: &[u8]) -> CheatCode {
+        let byte = |i: usize| (nibbles[i] << 4) | nibbles[i + 1];
+
+        CheatCode::GameShark {
+            bank: byte(0),
+            value: byte(2),
+            addr: ((byte(6) as u16) << 8) | byte(4) as u16
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cheat {
+    pub code: String,
+    pub name: String,
+    pub enabled: bool,
+    pub cheat_code: CheatCode
+}
+
+#[derive(Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats { cheats: Vec::new() }
+    }
+
+    // One code per line with an optional name after it, lines starting with '#' are comments
+    pub fn parse(content: &str) -> Result<Cheats, String> {
+        let mut cheats = Cheats::new();
+
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (code, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            cheats.add(code, name.trim()).map_err(|e| format!("Line {}: {}", line_number + 1, e))?;
+        }
+
+        return Ok(cheats);
+    }
+
+    pub fn load(path: &Path) -> io::Result<Cheats> {
+        let content = std::fs::read_to_string(path)?;
+        let cheats = Self::parse(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        info!("Loaded {} cheats from \"{}\"", cheats.len(), path.display());
+
+        Ok(cheats)
+    }
+
+    // Cheats file named like the rom (game.gb -> game.cht)
+    pub fn find_cheats_file(rom_path: &Path) -> Option<PathBuf> {
+        let cheats_path = rom_path.with_extension(CHEATS_FILE_EXTENSION);
+        if cheats_path.is_file() {
+            Some(cheats_path)
+        } else {
+            None
+        }
+    }
+
+    pub fn add(&mut self, code: &str, name: &str) -> Result<usize, String> {
+        let cheat_code = CheatCode::parse(code)?;
+        self.cheats.push(Cheat {
+            code: code.to_uppercase(),
+            name: name.to_string(),
+            enabled: true,
+            cheat_code
+        });
+
+        return Ok(self.cheats.len() - 1);
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> Result<(), String> {
+        let cheat = self.cheats.get_mut(index).ok_or(format!("No cheat #{}", index))?;
+        cheat.enabled = enabled;
+        info!("{} cheat #{} ({})", if enabled { "Enabled" } else { "Disabled" }, index, cheat.code);
+
+        Ok(())
+    }
+
+    pub fn get_cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn len(&self) -> usize {
+        self.cheats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    // Game Genie, value is what the rom has at addr
+    pub fn apply_rom_read(&self, addr: u16, value: u8) -> u8 {
+        for cheat in &self.cheats {
+            if let (true, CheatCode::GameGenie { addr: cheat_addr, value: cheat_value, compare }) = (cheat.enabled, cheat.cheat_code) {
+                if cheat_addr == addr && compare.is_none_or(|compare| compare == value) {
+                    return cheat_value;
+                }
+            }
+        }
+
+        return value;
+    }
+
+    // GameShark, the writes to do at the start of every frame
+    pub fn get_frame_writes(&self) -> Vec<(u16, u8)> {
+        self.cheats.iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.cheat_code {
+                CheatCode::GameShark { addr, value, .. } => Some((addr, value)),
+                _ => None
+            })
+            .collect()
+    }
+
+    // "#0 [x] 00A-17B-C49 Infinite lives"
+    pub fn get_lines(&self) -> Vec<String> {
+        if self.cheats.is_empty() {
+            return vec!["No cheats".to_string()];
+        }
+
+        self.cheats.iter().enumerate().map(|(i, cheat)| {
+            format!("#{} [{}] {} {}", i, if cheat.enabled { "x" } else { " " }, cheat.code, cheat.name).trim_end().to_string()
+        }).collect()
+    }
+}
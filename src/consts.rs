@@ -1,8 +1,12 @@
+use crate::registers::Reg16;
+
 // General
 // pub const SCREEN_WIDTH: usize = 160;
 // pub const SCREEN_HEIGHT: usize = 144;
 pub const SCREEN_WIDTH: usize = 256;
 pub const SCREEN_HEIGHT: usize = 256;
+pub const LCD_WIDTH: usize = 160; // What the Game Boy shows, the top left of the screen buffer
+pub const LCD_HEIGHT: usize = 144;
 pub const GBEMULATOR_ASCII_ART: &str = "\n   _____ ____                       _       _             \n  / ____|  _ \\                     | |     | |            \n | |  __| |_) | ___ _ __ ___  _   _| | __ _| |_ ___  _ __ \n | | |_ |  _ < / _ \\ \'_ ` _ \\| | | | |/ _` | __/ _ \\| \'__|\n | |__| | |_) |  __/ | | | | | |_| | | (_| | || (_) | |   \n  \\_____|____/ \\___|_| |_| |_|\\__,_|_|\\__,_|\\__\\___/|_|   \n                                                          \n                                                          \n";
 
 // PPU Debug flags
@@ -16,6 +20,56 @@ pub const RAM_IO_PORTS_RANGE_START: u16 = 0xFF00;
 pub const RAM_EMPTY_RANGE_START: u16 = 0xFF4C;
 pub const RAM_INTERNAL_RANGE_START: u16 = 0xFF80;
 
+// Boot rom
+pub const BOOT_ROM_SIZE: usize = 0x100;
+pub const BOOT_ROM_SIZE_CGB: usize = 0x900;
+pub const BOOT_ROM_CGB_UPPER_START: usize = 0x200;
+pub const BOOT_ROM_DISABLE_ADDR: u16 = 0xFF50;
+pub const BOOT_ROM_BUILTIN_NAME: &str = "builtin";
+
+// CGB
+pub const CGB_FLAG_SUPPORTED: u8 = 0x80; // 0xC0 (CGB only) has this bit set too
+pub const CGB_ADDR_KEY1: u16 = 0xFF4D; // Speed switch
+pub const CGB_ADDR_VBK: u16 = 0xFF4F; // VRAM bank
+pub const CGB_ADDR_SVBK: u16 = 0xFF70; // WRAM bank
+pub const CGB_ADDR_BCPS: u16 = 0xFF68; // BG palette index
+pub const CGB_ADDR_BCPD: u16 = 0xFF69; // BG palette data
+pub const CGB_ADDR_OCPS: u16 = 0xFF6A; // OBJ palette index
+pub const CGB_ADDR_OCPD: u16 = 0xFF6B; // OBJ palette data
+pub const CGB_PALETTE_ADDR: [u16; 4] = [CGB_ADDR_BCPS, CGB_ADDR_BCPD, CGB_ADDR_OCPS, CGB_ADDR_OCPD];
+
+pub const CGB_KEY1_BIT_CURRENT_SPEED: u8 = 7;
+pub const CGB_KEY1_BIT_SWITCH_ARMED: u8 = 0;
+pub const CGB_SPEED_SWITCH_CYCLES: usize = 8200; // The cpu is stopped for this long while switching speed
+
+pub const CGB_VRAM_START: u16 = 0x8000;
+pub const CGB_VRAM_BANK_SIZE: usize = 0x2000;
+pub const CGB_WRAM_BANKED_START: u16 = 0xD000;
+pub const CGB_WRAM_BANK_SIZE: usize = 0x1000;
+pub const CGB_WRAM_BANK_COUNT: usize = 8;
+
+pub const CGB_PALETTE_RAM_SIZE: usize = 64; // 8 palettes, 4 colors each, 2 bytes per color
+pub const CGB_PALETTE_INDEX_MASK: u8 = 0x3F;
+pub const CGB_PALETTE_BIT_AUTO_INCREMENT: u8 = 7;
+
+// BG map attributes (VRAM bank 1)
+pub const CGB_TILE_ATTR_PALETTE_MASK: u8 = 0b00000111;
+pub const CGB_TILE_ATTR_BIT_BANK: u8 = 3;
+pub const CGB_TILE_ATTR_BIT_X_FLIP: u8 = 5;
+pub const CGB_TILE_ATTR_BIT_Y_FLIP: u8 = 6;
+pub const CGB_TILE_ATTR_BIT_PRIORITY: u8 = 7;
+
+// VRAM DMA
+pub const CGB_ADDR_HDMA1: u16 = 0xFF51; // Source high
+pub const CGB_ADDR_HDMA2: u16 = 0xFF52; // Source low
+pub const CGB_ADDR_HDMA3: u16 = 0xFF53; // Destination high
+pub const CGB_ADDR_HDMA4: u16 = 0xFF54; // Destination low
+pub const CGB_ADDR_HDMA5: u16 = 0xFF55; // Length, mode and start
+pub const CGB_HDMA_ADDR: [u16; 5] = [CGB_ADDR_HDMA1, CGB_ADDR_HDMA2, CGB_ADDR_HDMA3, CGB_ADDR_HDMA4, CGB_ADDR_HDMA5];
+pub const CGB_HDMA5_BIT_HBLANK_MODE: u8 = 7;
+pub const CGB_HDMA_BLOCK_SIZE: u16 = 0x10;
+pub const CGB_HDMA_BLOCK_CYCLES: usize = 32; // PPU cycles the cpu is halted for each block, in both speeds
+
 pub const DMG_BOOT_ROM: [u8; 0x100] = [
     0x31, 0xfe, 0xff, 0xaf, 0x21, 0xff, 0x9f, 0x32, 0xcb, 0x7c, 0x20, 0xfb,
     0x21, 0x26, 0xff, 0x0e, 0x11, 0x3e, 0x80, 0x32, 0xe2, 0x0c, 0x3e, 0xf3,
@@ -42,11 +96,26 @@ pub const DMG_BOOT_ROM: [u8; 0x100] = [
   ];
   
 
+// Interrupts
+pub const INTERRUPT_ADDR_FLAG: u16 = 0xFF0F; // IF
+pub const INTERRUPT_ADDR_ENABLE: u16 = 0xFFFF; // IE
+pub const INTERRUPT_BIT_VBLANK: u8 = 0;
+pub const INTERRUPT_BIT_STAT: u8 = 1;
+pub const INTERRUPT_BIT_TIMER: u8 = 2;
+pub const INTERRUPT_BIT_SERIAL: u8 = 3;
+pub const INTERRUPT_BIT_JOYPAD: u8 = 4;
+pub const INTERRUPT_MASK: u8 = 0x1F;
+pub const INTERRUPT_FLAG_UNUSED_BITS: u8 = 0xE0;
+pub const INTERRUPT_VECTOR_START: u16 = 0x0040; // Each interrupt jumps 8 bytes after the previous one
+pub const INTERRUPT_DISPATCH_CYCLES: usize = 20;
+pub const CPU_IDLE_CYCLES: usize = 4; // Ticked every step while halted, stopped or locked up
+
 // Flag masks
 pub const FLAG_ZERO_MASK: u8 = 0b10000000;
 pub const FLAG_SUB_MASK: u8 = 0b01000000;
 pub const FLAG_HALF_CARRY_MASK: u8 = 0b00100000;
 pub const FLAG_CARRY_MASK: u8 = 0b00010000;
+pub const FLAG_REGISTER_MASK: u8 = 0b11110000; // The low nibble of F always reads 0
 
 // External ram size
 pub const CARTRIDGE_RAM_SIZE_NONE: u8 = 0x00;
@@ -113,16 +182,403 @@ pub const PPU_EXCLUDE_RAM_WRITE: [u16; 1]                           = [PPU_BG_CO
 
 pub const PPU_OAM_ADDR: u16                                         = 0xFE00;
 
+pub const PPU_ADDR_LCD_STATUS: u16                                  = 0xFF41;
+pub const PPU_ADDR_LY: u16                                          = 0xFF44;
+pub const PPU_ADDR_LYC: u16                                         = 0xFF45;
+pub const PPU_LCD_STATUS_BIT_LYC_EQUAL: u8                          = 2;
+pub const PPU_LCD_STATUS_MODE_MASK: u8                              = 0b00000011;
+
+// --- PPU timing (in dots, 4 per M-cycle at normal speed) ---
+pub const PPU_MODE_HBLANK: u8                                       = 0;
+pub const PPU_MODE_VBLANK: u8                                       = 1;
+pub const PPU_MODE_OAM_SCAN: u8                                     = 2;
+pub const PPU_MODE_DRAWING: u8                                      = 3;
+pub const PPU_CYCLES_OAM_SCAN: usize                                = 80;
+pub const PPU_CYCLES_DRAWING: usize                                 = 172; // Actually 172-289 depending on sprites and scrolling
+pub const PPU_CYCLES_PER_LINE: usize                                = 456;
+pub const PPU_VISIBLE_LINES: u8                                     = 144;
+pub const PPU_LINES_PER_FRAME: u8                                   = 154;
+pub const PPU_CYCLES_PER_FRAME: usize                               = 70224;
+pub const PPU_LY_VBLANK_STUB: u8                                    = 0x90; // What LY reads when we don't emulate it (Gameboy Doctor)
+
 // Colors
 pub const COLOR_WHITE: u32 = 0x00ffffff;
 pub const COLOR_LIGHT_GREY: u32 = 0x00aaaaaa;
 pub const COLOR_DARK_GREY: u32 = 0x00555555;
 pub const COLOR_BLACK: u32 = 0x00000000;
 
-pub const SPRITE_DUMP_PATH: &str = "sprites_dump.bmp";
-
 // Cartridge Type
 pub const CARTRIDGE_TYPE_ROM_ONLY: u8 = 0x00;
+pub const CARTRIDGE_TYPE_ROM_RAM: u8 = 0x08;
+pub const CARTRIDGE_TYPE_ROM_RAM_BATTERY: u8 = 0x09;
+// No mappers are emulated, anything banked can't run
+pub const SUPPORTED_CARTRIDGE_TYPES: [u8; 3] = [CARTRIDGE_TYPE_ROM_ONLY, CARTRIDGE_TYPE_ROM_RAM, CARTRIDGE_TYPE_ROM_RAM_BATTERY];
+pub const CARTRIDGE_TYPES: [(u8, &str); 28] = [
+  (0x00, "ROM ONLY"),
+  (0x01, "MBC1"),
+  (0x02, "MBC1+RAM"),
+  (0x03, "MBC1+RAM+BATTERY"),
+  (0x05, "MBC2"),
+  (0x06, "MBC2+BATTERY"),
+  (0x08, "ROM+RAM"),
+  (0x09, "ROM+RAM+BATTERY"),
+  (0x0B, "MMM01"),
+  (0x0C, "MMM01+RAM"),
+  (0x0D, "MMM01+RAM+BATTERY"),
+  (0x0F, "MBC3+TIMER+BATTERY"),
+  (0x10, "MBC3+TIMER+RAM+BATTERY"),
+  (0x11, "MBC3"),
+  (0x12, "MBC3+RAM"),
+  (0x13, "MBC3+RAM+BATTERY"),
+  (0x19, "MBC5"),
+  (0x1A, "MBC5+RAM"),
+  (0x1B, "MBC5+RAM+BATTERY"),
+  (0x1C, "MBC5+RUMBLE"),
+  (0x1D, "MBC5+RUMBLE+RAM"),
+  (0x1E, "MBC5+RUMBLE+RAM+BATTERY"),
+  (0x20, "MBC6"),
+  (0x22, "MBC7+SENSOR+RUMBLE+RAM+BATTERY"),
+  (0xFC, "POCKET CAMERA"),
+  (0xFD, "BANDAI TAMA5"),
+  (0xFE, "HuC3"),
+  (0xFF, "HuC1+RAM+BATTERY")
+];
+
+// Rom files
+pub const ROM_FILE_EXTENSIONS: [&str; 2] = [".gb", ".gbc"];
+pub const ZIP_MAGIC: [u8; 4] = [0x50, 0x4B, 0x03, 0x04]; // PK\x03\x04
+pub const ZIP_EMPTY_MAGIC: [u8; 4] = [0x50, 0x4B, 0x05, 0x06];
+pub const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+pub const ROM_MAX_SIZE: usize = CARTRIDGE_ROM_SIZE_DEFAULT << 8; // Rom size code 0x08, the largest a header can say
+
+// Patches
+pub const PATCH_FILE_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+pub const PATCH_IPS_MAGIC: &[u8] = b"PATCH";
+pub const PATCH_IPS_EOF: usize = 0x454F46; // "EOF"
+pub const PATCH_UPS_MAGIC: &[u8] = b"UPS1";
+pub const PATCH_BPS_MAGIC: &[u8] = b"BPS1";
+pub const PATCH_FOOTER_SIZE: usize = 12; // Source, target and patch CRC32
+pub const PATCH_MAX_TARGET_SIZE: usize = 8 * 1024 * 1024; // 512 banks, the largest rom size in a header
+
+// Cheats
+pub const CHEATS_FILE_EXTENSION: &str = "cht";
+pub const GAME_GENIE_COMPARE_XOR: u8 = 0xBA;
+
+// Joypad
+pub const JOYPAD_ADDR: u16 = 0xFF00; // P1
+pub const JOYPAD_BIT_SELECT_DIRECTIONS: u8 = 4;
+pub const JOYPAD_BIT_SELECT_ACTIONS: u8 = 5;
+pub const JOYPAD_SELECT_MASK: u8 = 0x30;
+pub const JOYPAD_UNUSED_BITS: u8 = 0xC0;
+
+// Serial
+pub const SERIAL_ADDR_DATA: u16 = 0xFF01; // SB
+pub const SERIAL_ADDR_CONTROL: u16 = 0xFF02; // SC
+pub const SERIAL_CONTROL_BIT_TRANSFER: u8 = 7;
+pub const SERIAL_CONTROL_BIT_INTERNAL_CLOCK: u8 = 0;
+
+// Screenshots
+pub const SCREENSHOT_DIFF_COLOR: u32 = 0x00ff0000; // Pixels that don't match the reference
+pub const SCREENSHOT_FILE_PREFIX: &str = "gbemulator";
+pub const SCREENSHOT_DEFAULT_KEY: &str = "F12";
+pub const SCREENSHOT_MAX_SCALE: usize = 8;
+
+// RAM search
+pub const RAM_SEARCH_RANGES: [(u16, u16); 3] = [
+  (0xA000, 0xBFFF), // Cartridge ram
+  (0xC000, 0xDFFF), // Work ram (the mapped bank)
+  (0xFF80, 0xFFFE)  // High ram
+];
+pub const RAM_SEARCH_MAX_LINES: usize = 20;
+
+// Header layout
+pub const ROM_HEADER_END: usize = 0x0150;
+pub const ROM_HEADER_CHECKSUM_START: usize = 0x0134;
+pub const ROM_HEADER_CHECKSUM_END: usize = 0x014C;
+pub const ROM_HEADER_CHECKSUM_ADDR: usize = 0x014D;
+pub const ROM_GLOBAL_CHECKSUM_ADDR: usize = 0x014E;
+pub const ROM_BANK_SIZE: usize = 16 * 1024;
+
+// Licensee codes, the old code 0x33 means the new (two ascii characters) code is used instead
+pub const OLD_LICENSEE_CODE_USE_NEW: u8 = 0x33;
+pub const OLD_LICENSEE_CODES: [(u8, &str); 146] = [
+  (0x00, "None"),
+  (0x01, "Nintendo"),
+  (0x08, "Capcom"),
+  (0x09, "Hot-B"),
+  (0x0A, "Jaleco"),
+  (0x0B, "Coconuts Japan"),
+  (0x0C, "Elite Systems"),
+  (0x13, "Electronic Arts"),
+  (0x18, "Hudson Soft"),
+  (0x19, "ITC Entertainment"),
+  (0x1A, "Yanoman"),
+  (0x1D, "Japan Clary"),
+  (0x1F, "Virgin Interactive"),
+  (0x24, "PCM Complete"),
+  (0x25, "San-X"),
+  (0x28, "Kotobuki Systems"),
+  (0x29, "Seta"),
+  (0x30, "Infogrames"),
+  (0x31, "Nintendo"),
+  (0x32, "Bandai"),
+  (0x34, "Konami"),
+  (0x35, "HectorSoft"),
+  (0x38, "Capcom"),
+  (0x39, "Banpresto"),
+  (0x3C, "Entertainment Interactive"),
+  (0x3E, "Gremlin"),
+  (0x41, "Ubi Soft"),
+  (0x42, "Atlus"),
+  (0x44, "Malibu"),
+  (0x46, "Angel"),
+  (0x47, "Spectrum HoloByte"),
+  (0x49, "Irem"),
+  (0x4A, "Virgin Interactive"),
+  (0x4D, "Malibu"),
+  (0x4F, "U.S. Gold"),
+  (0x50, "Absolute"),
+  (0x51, "Acclaim"),
+  (0x52, "Activision"),
+  (0x53, "American Sammy"),
+  (0x54, "GameTek"),
+  (0x55, "Park Place"),
+  (0x56, "LJN"),
+  (0x57, "Matchbox"),
+  (0x59, "Milton Bradley"),
+  (0x5A, "Mindscape"),
+  (0x5B, "Romstar"),
+  (0x5C, "Naxat Soft"),
+  (0x5D, "Tradewest"),
+  (0x60, "Titus"),
+  (0x61, "Virgin Interactive"),
+  (0x67, "Ocean"),
+  (0x69, "Electronic Arts"),
+  (0x6E, "Elite Systems"),
+  (0x6F, "Electro Brain"),
+  (0x70, "Infogrames"),
+  (0x71, "Interplay"),
+  (0x72, "Broderbund"),
+  (0x73, "Sculptured Soft"),
+  (0x75, "The Sales Curve"),
+  (0x78, "THQ"),
+  (0x79, "Accolade"),
+  (0x7A, "Triffix Entertainment"),
+  (0x7C, "MicroProse"),
+  (0x7F, "Kemco"),
+  (0x80, "Misawa Entertainment"),
+  (0x83, "LOZC"),
+  (0x86, "Tokuma Shoten"),
+  (0x8B, "Bullet-Proof Software"),
+  (0x8C, "Vic Tokai"),
+  (0x8E, "Ape"),
+  (0x8F, "I'Max"),
+  (0x91, "Chunsoft"),
+  (0x92, "Video System"),
+  (0x93, "Tsuburaya Productions"),
+  (0x95, "Varie"),
+  (0x96, "Yonezawa/S'Pal"),
+  (0x97, "Kaneko"),
+  (0x99, "Arc"),
+  (0x9A, "Nihon Bussan"),
+  (0x9B, "Tecmo"),
+  (0x9C, "Imagineer"),
+  (0x9D, "Banpresto"),
+  (0x9F, "Nova"),
+  (0xA1, "Hori Electric"),
+  (0xA2, "Bandai"),
+  (0xA4, "Konami"),
+  (0xA6, "Kawada"),
+  (0xA7, "Takara"),
+  (0xA9, "Technos Japan"),
+  (0xAA, "Broderbund"),
+  (0xAC, "Toei Animation"),
+  (0xAD, "Toho"),
+  (0xAF, "Namco"),
+  (0xB0, "Acclaim"),
+  (0xB1, "ASCII or Nexsoft"),
+  (0xB2, "Bandai"),
+  (0xB4, "Square Enix"),
+  (0xB6, "HAL Laboratory"),
+  (0xB7, "SNK"),
+  (0xB9, "Pony Canyon"),
+  (0xBA, "Culture Brain"),
+  (0xBB, "Sunsoft"),
+  (0xBD, "Sony Imagesoft"),
+  (0xBF, "Sammy"),
+  (0xC0, "Taito"),
+  (0xC2, "Kemco"),
+  (0xC3, "Square"),
+  (0xC4, "Tokuma Shoten"),
+  (0xC5, "Data East"),
+  (0xC6, "Tonkin House"),
+  (0xC8, "Koei"),
+  (0xC9, "UFL"),
+  (0xCA, "Ultra"),
+  (0xCB, "Vap"),
+  (0xCC, "Use Corporation"),
+  (0xCD, "Meldac"),
+  (0xCE, "Pony Canyon"),
+  (0xCF, "Angel"),
+  (0xD0, "Taito"),
+  (0xD1, "Sofel"),
+  (0xD2, "Quest"),
+  (0xD3, "Sigma Enterprises"),
+  (0xD4, "ASK Kodansha"),
+  (0xD6, "Naxat Soft"),
+  (0xD7, "Copya System"),
+  (0xD9, "Banpresto"),
+  (0xDA, "Tomy"),
+  (0xDB, "LJN"),
+  (0xDD, "NCS"),
+  (0xDE, "Human"),
+  (0xDF, "Altron"),
+  (0xE0, "Jaleco"),
+  (0xE1, "Towa Chiki"),
+  (0xE2, "Yutaka"),
+  (0xE3, "Varie"),
+  (0xE5, "Epoch"),
+  (0xE7, "Athena"),
+  (0xE8, "Asmik Ace Entertainment"),
+  (0xE9, "Natsume"),
+  (0xEA, "King Records"),
+  (0xEB, "Atlus"),
+  (0xEC, "Epic/Sony Records"),
+  (0xEE, "IGS"),
+  (0xF0, "A Wave"),
+  (0xF3, "Extreme Entertainment"),
+  (0xFF, "LJN")
+];
+
+pub const NEW_LICENSEE_CODES: [(&str, &str); 61] = [
+  ("00", "None"),
+  ("01", "Nintendo R&D1"),
+  ("08", "Capcom"),
+  ("13", "Electronic Arts"),
+  ("18", "Hudson Soft"),
+  ("19", "B-AI"),
+  ("20", "KSS"),
+  ("22", "Planning Office WADA"),
+  ("24", "PCM Complete"),
+  ("25", "San-X"),
+  ("28", "Kemco"),
+  ("29", "Seta"),
+  ("30", "Viacom"),
+  ("31", "Nintendo"),
+  ("32", "Bandai"),
+  ("33", "Ocean/Acclaim"),
+  ("34", "Konami"),
+  ("35", "HectorSoft"),
+  ("37", "Taito"),
+  ("38", "Hudson Soft"),
+  ("39", "Banpresto"),
+  ("41", "Ubi Soft"),
+  ("42", "Atlus"),
+  ("44", "Malibu"),
+  ("46", "Angel"),
+  ("47", "Bullet-Proof Software"),
+  ("49", "Irem"),
+  ("50", "Absolute"),
+  ("51", "Acclaim"),
+  ("52", "Activision"),
+  ("53", "American Sammy"),
+  ("54", "Konami"),
+  ("55", "Hi Tech Entertainment"),
+  ("56", "LJN"),
+  ("57", "Matchbox"),
+  ("58", "Mattel"),
+  ("59", "Milton Bradley"),
+  ("60", "Titus"),
+  ("61", "Virgin Interactive"),
+  ("64", "LucasArts"),
+  ("67", "Ocean"),
+  ("69", "Electronic Arts"),
+  ("70", "Infogrames"),
+  ("71", "Interplay"),
+  ("72", "Broderbund"),
+  ("73", "Sculptured Soft"),
+  ("75", "The Sales Curve"),
+  ("78", "THQ"),
+  ("79", "Accolade"),
+  ("80", "Misawa Entertainment"),
+  ("83", "LOZC"),
+  ("86", "Tokuma Shoten"),
+  ("87", "Tsukuda Original"),
+  ("91", "Chunsoft"),
+  ("92", "Video System"),
+  ("93", "Ocean/Acclaim"),
+  ("95", "Varie"),
+  ("96", "Yonezawa/S'Pal"),
+  ("97", "Kaneko"),
+  ("99", "Pack-In-Soft"),
+  ("A4", "Konami (Yu-Gi-Oh!)")
+];
+
+// Post boot state
+// IO registers as the boot rom leaves them, see "Power Up Sequence" in the pandocs
+pub const POST_BOOT_IO_REGISTERS: [(u16, u8); 36] = [
+  (0xFF00, 0xCF), // P1
+  (0xFF01, 0x00), // SB
+  (0xFF02, 0x7E), // SC
+  (0xFF05, 0x00), // TIMA
+  (0xFF06, 0x00), // TMA
+  (0xFF07, 0xF8), // TAC
+  (0xFF0F, 0xE1), // IF
+  (0xFF10, 0x80), // NR10
+  (0xFF11, 0xBF), // NR11
+  (0xFF12, 0xF3), // NR12
+  (0xFF13, 0xFF), // NR13
+  (0xFF14, 0xBF), // NR14
+  (0xFF16, 0x3F), // NR21
+  (0xFF17, 0x00), // NR22
+  (0xFF18, 0xFF), // NR23
+  (0xFF19, 0xBF), // NR24
+  (0xFF1A, 0x7F), // NR30
+  (0xFF1B, 0xFF), // NR31
+  (0xFF1C, 0x9F), // NR32
+  (0xFF1D, 0xFF), // NR33
+  (0xFF1E, 0xBF), // NR34
+  (0xFF20, 0xFF), // NR41
+  (0xFF21, 0x00), // NR42
+  (0xFF22, 0x00), // NR43
+  (0xFF23, 0xBF), // NR44
+  (0xFF24, 0x77), // NR50
+  (0xFF25, 0xF3), // NR51
+  (0xFF26, 0xF1), // NR52
+  (0xFF40, 0x91), // LCDC
+  (0xFF42, 0x00), // SCY
+  (0xFF43, 0x00), // SCX
+  (0xFF45, 0x00), // LYC
+  (0xFF47, 0xFC), // BGP
+  (0xFF4A, 0x00), // WY
+  (0xFF4B, 0x00), // WX
+  (0xFFFF, 0x00)  // IE
+];
+
+pub const POST_BOOT_IO_REGISTERS_DMG: [(u16, u8); 3] = [
+  (0xFF04, 0xAB), // DIV
+  (0xFF41, 0x85), // STAT
+  (0xFF46, 0xFF)  // DMA
+];
+
+pub const POST_BOOT_IO_REGISTERS_CGB: [(u16, u8); 3] = [
+  (0xFF4D, 0x7E), // KEY1
+  (0xFF4F, 0xFE), // VBK
+  (0xFF70, 0xF8)  // SVBK
+];
+
+// CPU Debugging
+pub const CPU_CALL_STACK_MAX_DEPTH: usize = 256;
+
+// GDB Stub
+// There is no SM83 target in gdb, registers are exposed as 16 bit pairs (like the z80 target)
+pub const GDB_REGISTERS: [Reg16; 6] = [Reg16::AF, Reg16::BC, Reg16::DE, Reg16::HL, Reg16::SP, Reg16::PC];
+pub const GDB_SIGNAL_INT: u8 = 2;
+pub const GDB_SIGNAL_ILL: u8 = 4;
+pub const GDB_SIGNAL_TRAP: u8 = 5;
+pub const GDB_INTERRUPT_BYTE: u8 = 0x03;
+pub const GDB_MAX_MEMORY_LENGTH: usize = 0x10000; // Longest m/M, the whole address space
+pub const GDB_INTERRUPT_POLL_INTERVAL: usize = 1000; // Instructions between checks for a ctrl-c from gdb
 
 pub fn bit_check(value: u8, bit: u8) -> bool {
   let bit_mask: u8 = 0x01 << bit;
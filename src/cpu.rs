@@ -1,22 +1,64 @@
 use crate::consts::*;
-use crate::ppu::PPU;
-use crate::ram_memory::RamMemory;
+use crate::bus::Bus;
 use crate::opcodes::OPCODES_JSON;
-use crate::param::{Param, MemValue};
+use crate::param::{Param, MemValue, parse_params};
+use crate::symbols::SymbolTable;
+use crate::registers::{Reg8, Reg16, Flag};
+use crate::model::Model;
 
 use serde_json::Value;
-use core::panic;
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write, LineWriter};
+use std::sync::OnceLock;
+
+// Parsed once and shared, so making a cpu is cheap
+pub fn get_opcodes() -> &'static Value {
+    static OPCODES: OnceLock<Value> = OnceLock::new();
+    OPCODES.get_or_init(|| serde_json::from_str(OPCODES_JSON).expect("Failed parsing opcodes json data"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CpuErrorReason {
+    UnknownOpcode,
+    Unimplemented(String),
+    InvalidParams(String),
+    UnknownRegister(String),
+    UnknownCondition(String),
+    FlagMismatch(String)
+}
+
+impl fmt::Display for CpuErrorReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuErrorReason::UnknownOpcode => write!(f, "Unknown opcode"),
+            CpuErrorReason::Unimplemented(what) => write!(f, "Unimplemented ({})", what),
+            CpuErrorReason::InvalidParams(what) => write!(f, "Invalid params ({})", what),
+            CpuErrorReason::UnknownRegister(reg) => write!(f, "Unknown register ({})", reg),
+            CpuErrorReason::UnknownCondition(cond) => write!(f, "Unknown condition ({})", cond),
+            CpuErrorReason::FlagMismatch(what) => write!(f, "Flag mismatch ({})", what)
+        }
+    }
+}
+
+// What stopped the cpu, and where
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuError {
+    pub pc: u16,
+    pub opcode: u8,
+    pub reason: CpuErrorReason
+}
 
-pub fn get_opcodes() -> Value {
-    serde_json::from_str(OPCODES_JSON).expect("Failed parsing opcodes json data")
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CPU error at 0x{:04X} (opcode 0x{:02X}): {}", self.pc, self.opcode, self.reason)
+    }
 }
 
+impl std::error::Error for CpuError {}
+
 #[readonly::make]
 pub struct CPU {
-    ram_memory_ref: Rc<RefCell<RamMemory>>,
-    ppu_ref: Rc<RefCell<PPU>>,
     a_reg: u8,
     b_reg: u8,
     c_reg: u8,
@@ -28,23 +70,28 @@ pub struct CPU {
     sp_reg: u16,
     pc_reg: u16,
     instruction_counter: usize,
-    opcodes: Value
+    opcodes: &'static Value,
+    symbols: Option<SymbolTable>,
+    call_stack: Vec<u16>, // Return addresses of the CALLs we are in, only used for debugging
+    trace_log: Option<LineWriter<File>>,
+    ime: bool, // Interrupt master enable
+    ime_delay: u8, // EI only takes effect after the next instruction
+    halted: bool,
+    halt_bug: bool, // The next opcode byte gets read twice
+    locked_up: bool // Executed an illegal opcode, nothing but a reset gets it out of this
 }
 
 impl CPU {
-    pub fn init_with_ram_ppu(ram_memory_ref: Rc<RefCell<RamMemory>>, ppu_ref: Rc<RefCell<PPU>>, boot_rom_enabled: bool) -> CPU {
+    pub fn init(boot_rom_enabled: bool) -> CPU {
         let opcodes = get_opcodes();
 
-        let initial_pc: u16;
-        if boot_rom_enabled {
-            initial_pc = 0x0000;
+        let initial_pc: u16 = if boot_rom_enabled {
+            0x0000
         } else {
-            initial_pc = 0x0100;
-        }
+            0x0100
+        };
         
         CPU {
-            ram_memory_ref: ram_memory_ref,
-            ppu_ref: ppu_ref,
             a_reg: 0,
             b_reg: 0,
             c_reg: 0,
@@ -57,14 +104,94 @@ impl CPU {
             pc_reg: initial_pc,
             sp_reg: 0xFFFE,
             instruction_counter: 0,
-            opcodes: opcodes
+            opcodes,
+            symbols: None,
+            call_stack: Vec::new(),
+            trace_log: None,
+            ime: false,
+            ime_delay: 0,
+            halted: false,
+            halt_bug: false,
+            locked_up: false
         }
     }
 
-    pub fn execute_instruction(&mut self) {
-        let mut opcode = self.get_addr(self.pc_reg);
+    // Set the registers like the boot rom would have left them, for when we start at 0x0100 without one.
+    // The io registers are the bus' job (MemoryBus::apply_post_boot_state)
+    pub fn apply_post_boot_state(&mut self, bus: &impl Bus, model: Model) {
+        let header_checksum_zero = bus.get_addr(0x014D) == 0x00;
+        let cgb_game = bit_check(bus.get_addr(0x0143), 7);
+
+        let [a, f, b, c, d, e, h, l] = model.get_post_boot_registers(header_checksum_zero, cgb_game);
+        self.a_reg = a;
+        self.f_reg = f;
+        self.b_reg = b;
+        self.c_reg = c;
+        self.d_reg = d;
+        self.e_reg = e;
+        self.h_reg = h;
+        self.l_reg = l;
+        self.sp_reg = 0xFFFE;
+        self.pc_reg = 0x0100;
+
+        debug!("Applied {:?} post boot state, {}", model, self.get_state_line(bus));
+    }
+
+    pub fn execute_instruction(&mut self, bus: &mut impl Bus) -> Result<(), CpuError> {
+        // A locked up or stopped cpu doesn't fetch anything, only time goes on
+        if self.locked_up || bus.is_stopped() {
+            bus.tick(CPU_IDLE_CYCLES);
+            return Ok(());
+        }
+
+        // HALT wakes up on any pending interrupt, even if IME doesn't let it be serviced
+        let pending_interrupts = Self::get_pending_interrupts(bus);
+        if self.halted {
+            if pending_interrupts == 0 {
+                bus.tick(CPU_IDLE_CYCLES);
+                return Ok(());
+            }
+
+            self.halted = false;
+        }
+
+        if self.ime && pending_interrupts != 0 {
+            self.service_interrupt(bus, pending_interrupts);
+            return Ok(());
+        }
+
+        let pc = self.pc_reg;
+        let opcode = bus.get_addr(pc);
+        self.run_instruction(bus).map_err(|reason| CpuError { pc, opcode, reason })?;
+
+        if self.ime_delay > 0 {
+            self.ime_delay -= 1;
+            if self.ime_delay == 0 {
+                self.ime = true;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Where the instruction after this one starts, after a halt bug the opcode byte was read twice
+    // so the pc is one byte short
+    fn get_next_pc(&self, opcode_data: &Value, halt_bug: bool) -> u16 {
+        let mut bytes = opcode_data["bytes"].as_u64().unwrap() as u16;
+        if halt_bug {
+            bytes -= 1;
+        }
+        return self.pc_reg.wrapping_add(bytes);
+    }
+
+    fn run_instruction(&mut self, bus: &mut impl Bus) -> Result<(), CpuErrorReason> {
+        self.write_trace_log(bus);
+
+        let halt_bug = std::mem::take(&mut self.halt_bug);
+        let mut opcode = bus.get_addr(self.pc_reg);
         let opcode_data: Value;
         let mut should_inc_pc = true;
+        let mut branch_taken = true; // Conditional instructions take less cycles when they don't branch
         let mut set_zero_flag: Option<bool> = Option::None;
         let mut set_carry_flag: Option<bool> = Option::None;
         let mut set_sub_flag: Option<bool> = Option::None;
@@ -73,15 +200,9 @@ impl CPU {
 
         self.instruction_counter += 1;
 
-        // Blargg test rom stuff
-        if self.pc_reg == 0xC005 {
-            info!("Entering infinite loop");
-            loop {}
-        }
-
         if opcode == 0xCB {
             is_opcode_cbprefixed = true;
-            opcode = self.get_addr(self.pc_reg + 1);
+            opcode = bus.get_addr(if halt_bug { self.pc_reg } else { self.pc_reg.wrapping_add(1) });
 
             opcode_data = self.opcodes["cbprefixed"][format!("0x{:02X}", opcode)].clone();
         } else {
@@ -90,15 +211,12 @@ impl CPU {
         }
             
         // Just some checks
-        assert_ne!(opcode_data["mnemonic"], Value::Null, "Opcode 0x{:02X} doesn't have a name", opcode);
-        assert!(opcode_data["mnemonic"].is_string(), "Opcode 0x{:02X} name is not a string (WTF)", opcode);
-
-        if opcode_data == Value::Null {
-            panic!("Opcode data for instruction 0x{:02X} is null", opcode);
+        if !opcode_data["mnemonic"].is_string() || !opcode_data["operands"].is_array() {
+            return Err(CpuErrorReason::UnknownOpcode);
         }
         
         // Parsing
-        let params: Vec<Param> = self.get_params(&opcode_data);
+        let params: Vec<Param> = self.get_params(bus, &opcode_data, halt_bug);
         
         // Debug Prints
         let opcode_name: &str = opcode_data["mnemonic"].as_str().unwrap();
@@ -109,8 +227,8 @@ impl CPU {
         }
         trace!("");
         trace!("");
-        debug!("CPU State, A:0x{:02X}, B:0x{:02X}, , C:0x{:02X}", self.a_reg, self.b_reg, self.c_reg);
-        debug!("0x{:04X} -> {} {}", self.pc_reg, opcode_name, param_data);
+        debug!("CPU State, {}", self.get_state_line(bus));
+        debug!("{} -> {} {}", self.format_addr(self.pc_reg), opcode_name, param_data);
         trace!("Instruction #{}", self.instruction_counter);
 
         trace!("OPCODE 0x{:02X}, IS_CB_PREFIXED: {}", opcode, is_opcode_cbprefixed);
@@ -124,47 +242,70 @@ impl CPU {
             "NOP" => { // NOTHING
                 // Nothing to do \:
             },
+            name if name.starts_with("ILLEGAL_") => { // Hangs the real hardware
+                warn!("CPU: Illegal opcode 0x{:02X} at {}, locking up", opcode, self.format_addr(self.pc_reg));
+                self.locked_up = true;
+                should_inc_pc = false;
+            },
+            "STOP" => { // Low power mode, or the speed switch on CGB
+                bus.stop();
+            },
             "DI" => { // DISABLE INTERRUPTS
-                info!("TODO: Disable instrupts");
+                self.ime = false;
+                self.ime_delay = 0;
             },
-            "JP" => { // JUMP
-                if params.len() == 1 {
-                    let target_addr: u16 = params.get(0).unwrap().get_double();
-
-                    // Blargg test rom stuff
-                    if target_addr == 0xC3C3 {
-                        self.dump_memory();
-                        panic!("JP: Blargg rom test fail");
-                    }
+            "EI" => { // ENABLE INTERRUPTS, after the next instruction
+                if !self.ime {
+                    self.ime_delay = 2;
+                }
+            },
+            "HALT" => { // Sleep until an interrupt is pending
+                if !self.ime && Self::get_pending_interrupts(bus) != 0 {
+                    // Halt bug, the cpu doesn't sleep and fails to increment PC after the next opcode
+                    debug!("CPU: Halt bug at {}", self.format_addr(self.pc_reg));
+                    self.halt_bug = true;
+                } else {
+                    self.halted = true;
+                }
+            },
+            "JP" => { // JUMP, maybe conditional
+                let target_addr: u16 = match params.len() {
+                    1 if params.first().unwrap().get_name() == "HL" => self.get_double_register(Reg16::HL),
+                    1 => params.first().unwrap().get_double().map_err(CpuErrorReason::InvalidParams)?,
+                    2 => {
+                        branch_taken = self.get_condition_value(params.first().unwrap().get_name())?;
+                        params.get(1).unwrap().get_double().map_err(CpuErrorReason::InvalidParams)?
+                    },
+                    _ => return Err(CpuErrorReason::InvalidParams("JP: Invalid param count".to_string()))
+                };
 
+                if branch_taken {
                     trace!("Jumping to addr 0x{:04X}", target_addr);
-                    
+
                     should_inc_pc = false;
                     self.pc_reg = target_addr;
-                } else {
-                    panic!("JP: Invalid param count")
                 }
             },
             "CP" => { // COMPARE
-                assert_eq!(params.len(), 1, "CP: Invalid params count");
+                Self::check_param_count(opcode_name, &params, 1)?;
 
                 set_sub_flag = Some(true);
 
                 
 
                 
-                let param = params.get(0).unwrap();
+                let param = params.first().unwrap();
                 let value = match param.get_value() {
                     MemValue::Byte(b) => b,
                     MemValue::Name(reg) => {
                         if param.is_immediate() {
-                            self.get_register(&reg)
+                            self.get_register(Self::reg8(&reg)?)
                         } else {
-                            let addr = self.get_double_register(&reg);
-                            self.get_addr(addr)
+                            let addr = self.get_double_register(Self::reg16(&reg)?);
+                            bus.get_addr(addr)
                         }
                     },
-                    _ => panic!("CP: Invalid source MemValue type")
+                    _ => return Err(CpuErrorReason::InvalidParams("CP: Invalid source MemValue type".to_string()))
                 };
 
                 let (sub_result, did_underflow) = u8::overflowing_sub(self.a_reg, value);
@@ -179,7 +320,7 @@ impl CPU {
                 // match params.len() {
                 //     1 => {
                 //         let jump_addr_param = params.get(0).unwrap();
-                //         self.pc_reg = self.pc_reg.wrapping_add_signed(jump_addr_param.get_signed_byte() as i16);
+                //         self.pc_reg = self.pc_reg.wrapping_add_signed(jump_addr_param.get_signed_byte().map_err(CpuErrorReason::InvalidParams)? as i16);
                 //         should_inc_pc = false;
                 //     },
                 //     2 => {
@@ -188,26 +329,27 @@ impl CPU {
                 //     _ => panic!("Unknown number of params to JR ({})", params.len())
                 // };
                 if params.len() == 1 {
-                    let jump_addr_param = params.get(0).unwrap();
-                    self.pc_reg = self.pc_reg.wrapping_add_signed(jump_addr_param.get_signed_byte() as i16);
+                    let jump_addr_param = params.first().unwrap();
+                    self.pc_reg = self.pc_reg.wrapping_add_signed(jump_addr_param.get_signed_byte().map_err(CpuErrorReason::InvalidParams)? as i16);
                     // should_inc_pc = false;
                 } else if params.len() == 2 {
-                    let condition_param = params.get(0).unwrap();
+                    let condition_param = params.first().unwrap();
 
-                    if self.get_condition_value(condition_param.get_name()) {
+                    branch_taken = self.get_condition_value(condition_param.get_name())?;
+                    if branch_taken {
                         let jump_addr_param = params.get(1).unwrap();
-                        self.pc_reg = self.pc_reg.wrapping_add_signed(jump_addr_param.get_signed_byte() as i16);
+                        self.pc_reg = self.pc_reg.wrapping_add_signed(jump_addr_param.get_signed_byte().map_err(CpuErrorReason::InvalidParams)? as i16);
                         // should_inc_pc = false;
                     }
 
                 } else {
-                    panic!("JR: Invalid param count")
+                    return Err(CpuErrorReason::InvalidParams("JR: Invalid param count".to_string()))
                 }
             },
             "LD" => { // LOAD
                 match params.len() {
                     2 => {
-                        let target_param = params.get(0).unwrap();
+                        let target_param = params.first().unwrap();
                         let read_param = params.get(1).unwrap();
     
                         let write_value: MemValue;
@@ -216,25 +358,25 @@ impl CPU {
                             MemValue::Name(reg_name) => {
                                 match reg_name.len() {
                                     1 => {
-                                        let reg_value = self.get_register(&reg_name);
+                                        let reg_value = self.get_register(Self::reg8(&reg_name)?);
                                         if read_param.is_immediate() {
                                             write_value = MemValue::Byte(reg_value);
                                         } else {
-                                            unimplemented!("Load from single register not immediate value")
+                                            return Err(CpuErrorReason::Unimplemented("Load from single register not immediate value".to_string()))
                                         }
                                     },
                                     2 => {
                                         let mut chars = reg_name.chars();
                                         let reg_name = chars.next().unwrap().to_string() + &chars.next().unwrap().to_string();
-                                        let reg_value = self.get_double_register(&reg_name);
+                                        let reg_value = self.get_double_register(Self::reg16(&reg_name)?);
                                         if read_param.is_immediate() {
                                             write_value = MemValue::Double(reg_value);
                                         } else {
-                                            write_value = MemValue::Byte(self.get_addr(reg_value));
+                                            write_value = MemValue::Byte(bus.get_addr(reg_value));
                                         }
 
                                     },
-                                    _ => panic!("Invalid register name length")
+                                    _ => return Err(CpuErrorReason::InvalidParams("Invalid register name length".to_string()))
                                 }
                             },
                             MemValue::Byte(_) => write_value = read_param.get_value(),
@@ -242,10 +384,10 @@ impl CPU {
                                 if read_param.is_immediate() {
                                     write_value = read_param.get_value();
                                 } else {
-                                    write_value = MemValue::Byte(self.get_addr(double_value));
+                                    write_value = MemValue::Byte(bus.get_addr(double_value));
                                 }
                             }
-                            _ => panic!("Tried running LD from unknown param type ({:?})", read_param)
+                            _ => return Err(CpuErrorReason::InvalidParams(format!("Tried running LD from unknown param type ({:?})", read_param)))
                         }
 
                         match target_param.get_value() {
@@ -255,117 +397,132 @@ impl CPU {
                                         // TODO: Refactor this shit, maybe with a macro?
                                         match write_value {
                                             MemValue::Byte(value) => {
-                                                self.set_register(&reg_name, value)
+                                                self.set_register(Self::reg8(&reg_name)?, value)
                                             },
-                                            _ => panic!("Invalid type to load to a single register")
+                                            _ => return Err(CpuErrorReason::InvalidParams("Invalid type to load to a single register".to_string()))
                                         }
                                     },
                                     2 => {
                                         match write_value {
                                             MemValue::Double(value) => {
-                                                self.set_double_register(&reg_name, value)
+                                                self.set_double_register(Self::reg16(&reg_name)?, value)
                                             },
                                             MemValue::Byte(value) => {
-                                                assert_eq!(target_param.is_immediate(), false);
-                                                let target_addr: u16 = self.get_double_register(&reg_name);
+                                                if target_param.is_immediate() {
+                                                    return Err(CpuErrorReason::InvalidParams(format!("{}: Invalid param addressing", opcode_name)));
+                                                }
+                                                let target_addr: u16 = self.get_double_register(Self::reg16(&reg_name)?);
 
-                                                self.set_addr(target_addr, value);
+                                                bus.set_addr(target_addr, value);
                                             }
-                                            _ => panic!("Invalid type to load to a double register ({:?})", write_value)
+                                            _ => return Err(CpuErrorReason::InvalidParams(format!("Invalid type to load to a double register ({:?})", write_value)))
                                         }
                                     }
-                                    _ => panic!("Invalid register name length")
+                                    _ => return Err(CpuErrorReason::InvalidParams("Invalid register name length".to_string()))
                                 }
 
                                 // For LDI and LDD (or LD (HL-) and LD (HL+))
                                 if target_param.is_decrement() {
-                                    self.set_double_register(
-                                        &reg_name,
-                                        self.get_double_register(&reg_name) - 1);
+                                    let value = self.get_double_register(Self::reg16(&reg_name)?);
+                                    self.set_double_register(Self::reg16(&reg_name)?, value.wrapping_sub(1));
                                 } else if target_param.is_increment() {
-                                    self.set_double_register(
-                                        &reg_name,
-                                        self.get_double_register(&reg_name) + 1);
+                                    let value = self.get_double_register(Self::reg16(&reg_name)?);
+                                    self.set_double_register(Self::reg16(&reg_name)?, value.wrapping_add(1));
                                 }
 
                                 if read_param.is_decrement() {
-                                    self.set_double_register(
-                                        &read_param.get_name(),
-                                        self.get_double_register(&read_param.get_name()) - 1);
+                                    let value = self.get_double_register(Self::reg16(&read_param.get_name())?);
+                                    self.set_double_register(Self::reg16(&read_param.get_name())?, value.wrapping_sub(1));
                                 } else if read_param.is_increment() {
-                                    self.set_double_register(
-                                        &read_param.get_name(),
-                                        self.get_double_register(&read_param.get_name()) + 1);
+                                    let value = self.get_double_register(Self::reg16(&read_param.get_name())?);
+                                    self.set_double_register(Self::reg16(&read_param.get_name())?, value.wrapping_add(1));
                                 }
 
-                                trace!("HL: {:?}", self.get_double_register(&"HL".to_string()));
+                                trace!("HL: {:?}", self.get_double_register(Reg16::HL));
                                 trace!("LD: From {:?} to {:?}", read_param, target_param);
 
                             },
                             MemValue::Double(addr) => {
                                 match write_value {
                                     MemValue::Byte(value) => {
-                                        self.set_addr(addr, value);
+                                        bus.set_addr(addr, value);
                                     },
                                     MemValue::Double(value) => {
                                         let msb = Self::msb(value);
                                         let lsb = Self::lsb(value);
                                         
-                                        self.set_addr(addr, lsb);
-                                        self.set_addr(addr+1, msb);
+                                        bus.set_addr(addr, lsb);
+                                        bus.set_addr(addr+1, msb);
                                     }
-                                    _ => panic!("Tried writing non byte value to a memory addr ({:?})", write_value)
+                                    _ => return Err(CpuErrorReason::InvalidParams(format!("Tried writing non byte value to a memory addr ({:?})", write_value)))
                                 }
                             }
-                            _ => panic!("Tried writing to unknown param type ({:?})", target_param)
+                            _ => return Err(CpuErrorReason::InvalidParams(format!("Tried writing to unknown param type ({:?})", target_param)))
                         }
                     },
+                    3 => { // LD HL, SP+r8
+                        let offset = params.get(2).unwrap().get_signed_byte().map_err(CpuErrorReason::InvalidParams)?;
+                        let (add_result, half_carry, carry) = self.add_sp_offset(offset);
+                        self.set_double_register(Self::reg16(&params.first().unwrap().get_name())?, add_result);
+
+                        set_zero_flag = Some(false);
+                        set_sub_flag = Some(false);
+                        set_half_carry_flag = Some(half_carry);
+                        set_carry_flag = Some(carry);
+                    },
                     _ => {
-                        unimplemented!("Load with {} params", params.len())
+                        return Err(CpuErrorReason::Unimplemented(format!("Load with {} params", params.len())))
                     }
                 }
             },
             "LDH" => { // LOAD
                 // unimplemented!("LDH: I think it is broken?");
-                assert_eq!(params.len(), 2, "Invalid param count to LDH");
+                Self::check_param_count(opcode_name, &params, 2)?;
 
-                let to_param = params.get(0).unwrap();
+                let to_param = params.first().unwrap();
                 let from_param = params.get(1).unwrap();
 
 
-                let from_value: u8;
-                match from_param.get_value() {
+                let from_value: u8 = match from_param.get_value() {
                     MemValue::Byte(value) => {
-                        assert_eq!(from_param.is_immediate(), false, "LDH: from immediate byte value");
+                        if from_param.is_immediate() {
+                            return Err(CpuErrorReason::InvalidParams("LDH: from immediate byte value".to_string()));
+                        }
                         let from_addr: u16 = 0xFF00 + value as u16;
-                        from_value = self.get_addr(from_addr);
+                        bus.get_addr(from_addr)
                     },
                     MemValue::Name(reg_name) => {
-                        assert_eq!(from_param.is_immediate(), true, "LDH: from not immediate register");
-                        from_value = self.get_register(&reg_name);
+                        if !from_param.is_immediate() {
+                            return Err(CpuErrorReason::InvalidParams("LDH: from not immediate register".to_string()));
+                        }
+                        self.get_register(Self::reg8(&reg_name)?)
                     },
-                    _ => panic!("LDH: from unknown type ({:?})", from_param.get_value())
-                }
+                    _ => return Err(CpuErrorReason::InvalidParams(format!("LDH: from unknown type ({:?})", from_param.get_value())))
+                };
 
                 match to_param.get_value() {
                     MemValue::Byte(value) => {
-                        assert_eq!(to_param.is_immediate(), false, "LDH: to immediate byte value");
+                        if to_param.is_immediate() {
+                            return Err(CpuErrorReason::InvalidParams("LDH: to immediate byte value".to_string()));
+                        }
 
                         let to_addr: u16 = 0xFF00 + value as u16;
-                        self.set_addr(to_addr, from_value);
+                        bus.set_addr(to_addr, from_value);
                     },
                     MemValue::Name(reg_name) => {
-                        assert_eq!(to_param.is_immediate(), true, "LDH: to not immediate register");
+                        if !to_param.is_immediate() {
+                            return Err(CpuErrorReason::InvalidParams("LDH: to not immediate register".to_string()));
+                        }
 
-                        self.set_register(&reg_name, from_value);
+                        self.set_register(Self::reg8(&reg_name)?, from_value);
                     },
-                    _ => panic!("LDH: to unknown type ({:?})", to_param.get_value())
+                    _ => return Err(CpuErrorReason::InvalidParams(format!("LDH: to unknown type ({:?})", to_param.get_value())))
                 }
             },
             "XOR" => { // XOR
-                assert_eq!(params.len(), 1, "Invalid param count to XOR");
+                Self::check_param_count(opcode_name, &params, 1)?;
 
-                let from_param = params.get(0).unwrap();
+                let from_param = params.first().unwrap();
 
                 let xor_value: u8;
                 match from_param.get_value() {
@@ -376,19 +533,21 @@ impl CPU {
                         let reg_name = from_param.get_name();
                         trace!("XOR: From reg \"{}\"", reg_name);
                         if from_param.is_immediate() {
-                            xor_value = self.get_register(&name);
+                            xor_value = self.get_register(Self::reg8(&name)?);
                         } else {
-                            xor_value = self.get_addr(self.get_double_register(&reg_name));
+                            xor_value = bus.get_addr(self.get_double_register(Self::reg16(&reg_name)?));
                         }
                     },
                     MemValue::Double(addr) => {
-                        assert_eq!(from_param.is_immediate(), true, "Tried running XOR with Double immediate value???");
-                        xor_value = self.get_addr(addr)
+                        if !from_param.is_immediate() {
+                            return Err(CpuErrorReason::InvalidParams("Tried running XOR with Double immediate value???".to_string()));
+                        }
+                        xor_value = bus.get_addr(addr)
                     }
-                    _ => panic!("XOR: Unknown type ({:?})", from_param.get_value())
+                    _ => return Err(CpuErrorReason::InvalidParams(format!("XOR: Unknown type ({:?})", from_param.get_value())))
                 };
 
-                self.a_reg = self.a_reg ^ xor_value;
+                self.a_reg ^= xor_value;
 
                 set_zero_flag = Some(self.a_reg == 0);
                 set_carry_flag = Some(false);
@@ -396,9 +555,10 @@ impl CPU {
                 set_half_carry_flag = Some(false);
             },
             "BIT" => { // Check if certain bit in byte is set
-                assert_eq!(params.len(), 2, "BIT: Invalid param count");
+                Self::check_param_count(opcode_name, &params, 2)?;
 
-                let bit_index = params.get(0).unwrap().get_name().parse::<u8>().unwrap();
+                let bit_index = params.first().unwrap().get_name().parse::<u8>()
+                    .map_err(|e| CpuErrorReason::InvalidParams(format!("BIT: Invalid bit index ({})", e)))?;
                 let reg_name: String = params.get(1).unwrap().get_name();
                 let reg_value;
 
@@ -408,40 +568,44 @@ impl CPU {
 
                 match reg_name.len() {
                     1 => {
-                        reg_value = self.get_register(&reg_name) ;
+                        reg_value = self.get_register(Self::reg8(&reg_name)?) ;
                     },
                     2 => {
-                        assert_eq!(params.get(1).unwrap().is_immediate(), false);
-                        reg_value = self.get_addr(self.get_double_register(&reg_name));
+                        if params.get(1).unwrap().is_immediate() {
+                            return Err(CpuErrorReason::InvalidParams(format!("{}: Invalid param addressing", opcode_name)));
+                        }
+                        reg_value = bus.get_addr(self.get_double_register(Self::reg16(&reg_name)?));
                     },
                     _ => {
-                        panic!("BIT: Tried running operation on invalid register")
+                        return Err(CpuErrorReason::InvalidParams("BIT: Tried running operation on invalid register".to_string()))
                     }
                 };
 
                 set_zero_flag = Some(((reg_value >> bit_index) % 2) == 0);
             },
             "RST" => { // Push PC to stack and jump to one of hardcoded values
-                assert_eq!(params.len(), 1, "RST: Invalid param count");
+                Self::check_param_count(opcode_name, &params, 1)?;
 
-                self.stack_push_double(self.pc_reg);
+                let return_addr = self.get_next_pc(&opcode_data, halt_bug);
+                self.stack_push_double(bus, return_addr);
+                self.push_call_stack(return_addr);
 
-                let new_addr_str = params.get(0).unwrap().get_name().replace("H", "");
+                let new_addr_str = params.first().unwrap().get_name().replace("H", "");
                 let new_addr_parse_result = u16::from_str_radix(new_addr_str.as_str(),16);
                 match new_addr_parse_result {
                     Ok(new_addr) => {
                         self.pc_reg = new_addr;
                     },
                     Err(e) => {
-                        panic!("RST: Failed getting addr to jump ({})", e);
+                        return Err(CpuErrorReason::InvalidParams(format!("RST: Failed getting addr to jump ({})", e)));
                     }
                 }
 
                 should_inc_pc = false;
             },
             "INC" => { // Increment value
-                assert_eq!(params.len(), 1, "INC: Invalid param count");
-                let param = params.get(0).unwrap();
+                Self::check_param_count(opcode_name, &params, 1)?;
+                let param = params.first().unwrap();
                 let reg_name = param.get_name();
 
                 set_sub_flag = Some(false);
@@ -449,42 +613,43 @@ impl CPU {
                 if param.is_immediate() {
                     match reg_name.len() {
                         1 => {
-                            let value = self.get_register(&reg_name);
+                            let value = self.get_register(Self::reg8(&reg_name)?);
                             let new_value = u8::overflowing_add(value, 1).0;
 
                             set_half_carry_flag = Some(((u8::overflowing_add(value & 0x0f, 1).0) & 0x10) == 0x10);
                             set_zero_flag = Some(new_value == 0);
 
-                            self.set_register(&reg_name, new_value);
+                            self.set_register(Self::reg8(&reg_name)?, new_value);
                         },
                         2 => {
-                            let value = self.get_double_register(&reg_name);
+                            let value = self.get_double_register(Self::reg16(&reg_name)?);
                             let new_value = u16::overflowing_add(value, 1).0;
 
                             set_sub_flag = Option::None;
 
-                            self.set_double_register(&reg_name, new_value);
+                            self.set_double_register(Self::reg16(&reg_name)?, new_value);
                         },
-                        _ => panic!("INC: Invalid reg_name")
+                        _ => return Err(CpuErrorReason::InvalidParams("INC: Invalid reg_name".to_string()))
                     }
                 } else {
                     match reg_name.len() {
-                        2 => {
-                            let value = self.get_double_register(&reg_name);
-                            let new_value = u16::overflowing_add(value, 1).0;
+                        2 => { // (HL)
+                            let addr = self.get_double_register(Self::reg16(&reg_name)?);
+                            let value = bus.get_addr(addr);
+                            let new_value = value.wrapping_add(1);
 
-                            set_half_carry_flag = Some(((u16::overflowing_add(value & 0x0f, 1).0) & 0x10) == 0x10);
+                            set_half_carry_flag = Some((value & 0x0F) == 0x0F);
                             set_zero_flag = Some(new_value == 0);
 
-                            self.set_double_register(&reg_name, new_value);
+                            bus.set_addr(addr, new_value);
                         },
-                        _ => panic!("INC: Invalid reg_name")
+                        _ => return Err(CpuErrorReason::InvalidParams("INC: Invalid reg_name".to_string()))
                     }
                 }
             },
             "DEC" => { // Decrement value
-                assert_eq!(params.len(), 1, "DEC: Invalid param count");
-                let param = params.get(0).unwrap();
+                Self::check_param_count(opcode_name, &params, 1)?;
+                let param = params.first().unwrap();
                 let reg_name = param.get_name();
 
                 set_sub_flag = Some(true);
@@ -492,98 +657,83 @@ impl CPU {
                 if param.is_immediate() {
                     match reg_name.len() {
                         1 => {
-                            let value = self.get_register(&reg_name);
+                            let value = self.get_register(Self::reg8(&reg_name)?);
                             let new_value = u8::overflowing_sub(value, 1).0;
 
                             set_half_carry_flag = Some(((u8::overflowing_sub(value & 0x0f, 1).0) & 0x10) == 0x10);
                             set_zero_flag = Some(new_value == 0);
 
-                            self.set_register(&reg_name, new_value);
+                            self.set_register(Self::reg8(&reg_name)?, new_value);
                         },
                         2 => {
-                            let value = self.get_double_register(&reg_name);
+                            let value = self.get_double_register(Self::reg16(&reg_name)?);
                             let new_value = u16::overflowing_sub(value, 1).0;
 
                             set_sub_flag = Option::None;
 
-                            self.set_double_register(&reg_name, new_value);
+                            self.set_double_register(Self::reg16(&reg_name)?, new_value);
                         },
-                        _ => panic!("DEC: Invalid reg_name")
+                        _ => return Err(CpuErrorReason::InvalidParams("DEC: Invalid reg_name".to_string()))
                     }
                 } else {
                     match reg_name.len() {
-                        2 => {
-                            let value = self.get_double_register(&reg_name);
-                            let new_value = u16::overflowing_sub(value, 1).0;
+                        2 => { // (HL)
+                            let addr = self.get_double_register(Self::reg16(&reg_name)?);
+                            let value = bus.get_addr(addr);
+                            let new_value = value.wrapping_sub(1);
 
-                            set_half_carry_flag = Some(((u16::overflowing_sub(value & 0x0f, 1).0) & 0x10) == 0x10);
+                            set_half_carry_flag = Some((value & 0x0F) == 0x00);
                             set_zero_flag = Some(new_value == 0);
 
-                            self.set_double_register(&reg_name, new_value);
+                            bus.set_addr(addr, new_value);
                         },
-                        _ => panic!("DEC: Invalid reg_name")
+                        _ => return Err(CpuErrorReason::InvalidParams("DEC: Invalid reg_name".to_string()))
                     }
                 }
             },
-            "CALL" => { // JUMP to addr and push current pc to stack
-                
+            "CALL" => { // JUMP to addr and push current pc to stack, maybe conditional
                 match params.len() {
-                    1 => {
-                        let target_addr = params.get(0).unwrap().get_double();
-                        self.stack_push_double(
-                            self.pc_reg + opcode_data["bytes"].as_u64().unwrap() as u16);
+                    1 => (),
+                    2 => branch_taken = self.get_condition_value(params.first().unwrap().get_name())?,
+                    _ => return Err(CpuErrorReason::InvalidParams("CALL: Invalid param count".to_string()))
+                }
 
-                        self.pc_reg = target_addr;
-                        should_inc_pc = false;
-                    },
-                    2 => {
-                        let condition = params.get(0).unwrap().get_name();
-                        let target_addr = params.get(1).unwrap().get_double();
-
-                        let should_jump: bool = match condition.as_str() {
-                            "NZ"    => !self.get_zero_flag(),
-                            "Z"     => self.get_zero_flag(),
-                            "NC"    => !self.get_carry_flag(),
-                            "C"     => self.get_carry_flag(),
-                            _ => panic!("Jump: Unknown condition : {}", condition)
-                        };
+                if branch_taken {
+                    let target_addr = params.last().unwrap().get_double().map_err(CpuErrorReason::InvalidParams)?;
+                    let return_addr = self.get_next_pc(&opcode_data, halt_bug);
+                    self.stack_push_double(bus, return_addr);
+                    self.push_call_stack(return_addr);
 
-                        if should_jump {
-                            self.stack_push_double(
-                                self.pc_reg + opcode_data["bytes"].as_u64().unwrap() as u16);
-    
-                            self.pc_reg = target_addr;
-                            should_inc_pc = false;
-                        }
-                    },
-                    _ => panic!("CALL: Invalid param count")
+                    self.pc_reg = target_addr;
+                    should_inc_pc = false;
                 }
             },
             "PUSH" => { // Push value to stack
-                assert_eq!(params.len(), 1);
+                Self::check_param_count(opcode_name, &params, 1)?;
 
-                let reg_name = params.get(0).unwrap().get_name();
-                self.stack_push_double(self.get_double_register(&reg_name));
+                let reg_name = params.first().unwrap().get_name();
+                let value = self.get_double_register(Self::reg16(&reg_name)?);
+                self.stack_push_double(bus, value);
             },
             "POP" => { // Pop value from the stack to the corresponding register
-                assert_eq!(params.len(), 1);
+                Self::check_param_count(opcode_name, &params, 1)?;
 
-                let reg_name = params.get(0).unwrap().get_name();
-                let popped_value = self.stack_pop_double();
-                self.set_double_register(&reg_name, popped_value);
+                let reg_name = params.first().unwrap().get_name();
+                let popped_value = self.stack_pop_double(bus);
+                self.set_double_register(Self::reg16(&reg_name)?, popped_value);
 
                 
             },
             "RL" => { // Rotate left through the carry flag
-                assert_eq!(params.len(), 1);
-                let param = params.get(0).unwrap();
+                Self::check_param_count(opcode_name, &params, 1)?;
+                let param = params.first().unwrap();
                 let reg_name = param.get_name();
 
                 set_half_carry_flag = Some(false);
                 set_sub_flag = Some(false);
 
                 if param.is_immediate() { // Register
-                    let old_value = self.get_register(&reg_name);
+                    let old_value = self.get_register(Self::reg8(&reg_name)?);
                     set_carry_flag = Some((old_value & 0b10000000) == 0b10000000);
 
                     let mut new_value = old_value << 1;
@@ -592,10 +742,10 @@ impl CPU {
                     }
 
                     set_zero_flag = Some(new_value == 0);
-                    self.set_register(&reg_name, new_value);
+                    self.set_register(Self::reg8(&reg_name)?, new_value);
                 } else { // (HL)
-                    let addr = self.get_double_register(&reg_name);
-                    let old_value = self.get_addr(addr);
+                    let addr = self.get_double_register(Self::reg16(&reg_name)?);
+                    let old_value = bus.get_addr(addr);
                     set_carry_flag = Some((old_value & 0b10000000) == 0b10000000);
 
                     let mut new_value = old_value << 1;
@@ -604,11 +754,11 @@ impl CPU {
                     }
 
                     set_zero_flag = Some(new_value == 0);
-                    self.set_addr(addr, new_value);
+                    bus.set_addr(addr, new_value);
                 }
             },
             "RLA" => { // Rotate left A register through the carry flag
-                assert_eq!(params.len(), 0);
+                Self::check_param_count(opcode_name, &params, 0)?;
 
                 let old_value = self.a_reg;
 
@@ -626,34 +776,41 @@ impl CPU {
             },
             "RET" => { // Return, maybe conditional
                 match params.len() {
-                    0 => { // Just return
-                        let addr = self.stack_pop_double();
-                        self.pc_reg = addr;
-                        should_inc_pc = false;
-                    },
-                    1 => { // Conditional return
-                        unimplemented!("RET: Conditional return")
-                    },
-                    _ => panic!("RET: Inavlid param count")
+                    0 => (),
+                    1 => branch_taken = self.get_condition_value(params.first().unwrap().get_name())?,
+                    _ => return Err(CpuErrorReason::InvalidParams("RET: Inavlid param count".to_string()))
+                }
+
+                if branch_taken {
+                    self.return_from_call(bus);
+                    should_inc_pc = false;
                 }
             },
+            "RETI" => { // Return and enable interrupts, right away unlike EI
+                Self::check_param_count(opcode_name, &params, 0)?;
+
+                self.return_from_call(bus);
+                self.ime = true;
+                self.ime_delay = 0;
+                should_inc_pc = false;
+            },
             "SUB" => {
-                assert_eq!(params.len(), 1, "SUB: Too much params");
-                let param = params.get(0).unwrap();
+                Self::check_param_count(opcode_name, &params, 1)?;
+                let param = params.first().unwrap();
 
                 let value: u8 = match param.get_value() {
                     MemValue::Name(reg_name) => {
                         if param.is_immediate() {
-                            self.get_register(&reg_name)
+                            self.get_register(Self::reg8(&reg_name)?)
                         } else {
-                            let addr = self.get_double_register(&reg_name);
-                            self.get_addr(addr)
+                            let addr = self.get_double_register(Self::reg16(&reg_name)?);
+                            bus.get_addr(addr)
                         }
                     },
                     MemValue::Byte(param_value) => {
                         param_value
                     },
-                    _ => panic!("SUB: Invalid param type")
+                    _ => return Err(CpuErrorReason::InvalidParams("SUB: Invalid param type".to_string()))
                 };
 
                 let (sub_result, did_underflow) = u8::overflowing_sub(self.a_reg, value);
@@ -666,28 +823,28 @@ impl CPU {
                 set_half_carry_flag = Some((((self.a_reg & 0xf).wrapping_sub(value & 0xf)) & 0x10) != 0);
             },
             "OR" => {
-                assert_eq!(params.len(), 1, "OR: Too much params");
-                let param = params.get(0).unwrap();
+                Self::check_param_count(opcode_name, &params, 1)?;
+                let param = params.first().unwrap();
 
                 let value: u8 = match param.get_value() {
                     MemValue::Name(reg_name) => {
                         if param.is_immediate() {
-                            self.get_register(&reg_name)
+                            self.get_register(Self::reg8(&reg_name)?)
                         } else {
-                            let addr = self.get_double_register(&reg_name);
-                            self.get_addr(addr)
+                            let addr = self.get_double_register(Self::reg16(&reg_name)?);
+                            bus.get_addr(addr)
                         }
                     },
                     MemValue::Byte(param_value) => {
                         param_value
                     },
-                    _ => panic!("SUB: Invalid param type")
+                    _ => return Err(CpuErrorReason::InvalidParams("SUB: Invalid param type".to_string()))
                 };
 
-                let a_reg_value: u8 = self.get_register(&"A".to_string());
+                let a_reg_value: u8 = self.get_register(Reg8::A);
                 let or_result = value | a_reg_value;
 
-                self.set_register(&"A".to_string(), or_result);
+                self.set_register(Reg8::A, or_result);
 
                 set_sub_flag = Some(false);
                 set_zero_flag = Some(or_result == 0);
@@ -696,28 +853,28 @@ impl CPU {
 
             },
             "AND" => {
-                assert_eq!(params.len(), 1, "AND: Too much params");
-                let param = params.get(0).unwrap();
+                Self::check_param_count(opcode_name, &params, 1)?;
+                let param = params.first().unwrap();
 
                 let value: u8 = match param.get_value() {
                     MemValue::Name(reg_name) => {
                         if param.is_immediate() {
-                            self.get_register(&reg_name)
+                            self.get_register(Self::reg8(&reg_name)?)
                         } else {
-                            let addr = self.get_double_register(&reg_name);
-                            self.get_addr(addr)
+                            let addr = self.get_double_register(Self::reg16(&reg_name)?);
+                            bus.get_addr(addr)
                         }
                     },
                     MemValue::Byte(param_value) => {
                         param_value
                     },
-                    _ => panic!("SUB: Invalid param type")
+                    _ => return Err(CpuErrorReason::InvalidParams("SUB: Invalid param type".to_string()))
                 };
 
-                let a_reg_value: u8 = self.get_register(&"A".to_string());
+                let a_reg_value: u8 = self.get_register(Reg8::A);
                 let and_result = value & a_reg_value;
 
-                self.set_register(&"A".to_string(), and_result);
+                self.set_register(Reg8::A, and_result);
 
                 set_sub_flag = Some(false);
                 set_zero_flag = Some(and_result == 0);
@@ -725,30 +882,27 @@ impl CPU {
                 set_half_carry_flag = Some(true);
             },
             "ADD" => {
-                assert_eq!(params.len(), 2, "ADD: Invalid amount of params");
+                Self::check_param_count(opcode_name, &params, 2)?;
 
-                // Parse source value
                 let from_param = params.get(1).unwrap();
-                let from_value: u8 = match from_param.get_value() {
-                    MemValue::Name(reg_name) => {
-                        if from_param.is_immediate() {
-                            self.get_register(&reg_name)
-                        } else {
-                            let addr = self.get_double_register(&reg_name);
-                            self.get_addr(addr)
-                        }
-                    },
-                    MemValue::Byte(param_value) => {
-                        param_value
-                    },
-                    _ => panic!("ADD: Invalid param type")
-                };
-
-                // Parse destination value
-                let dest_reg = params.get(0).unwrap().get_name();
-                match dest_reg.len() {
-                    1 => { // A register
-                        assert_eq!(dest_reg, "A", "ADD: Attemp to add to a register of length 1 which isn't A!");
+                let dest_reg = params.first().unwrap().get_name();
+                match dest_reg.as_str() {
+                    "A" => {
+                        // Parse source value
+                        let from_value: u8 = match from_param.get_value() {
+                            MemValue::Name(reg_name) => {
+                                if from_param.is_immediate() {
+                                    self.get_register(Self::reg8(&reg_name)?)
+                                } else {
+                                    let addr = self.get_double_register(Self::reg16(&reg_name)?);
+                                    bus.get_addr(addr)
+                                }
+                            },
+                            MemValue::Byte(param_value) => {
+                                param_value
+                            },
+                            _ => return Err(CpuErrorReason::InvalidParams("ADD: Invalid param type".to_string()))
+                        };
 
                         // trace!("ADD: Before {}", self.a_reg);
                         let (add_result, did_overflow) = u8::overflowing_add(self.a_reg, from_value);
@@ -761,60 +915,70 @@ impl CPU {
                         set_carry_flag = Some(did_overflow);
                         set_half_carry_flag = Some((((self.a_reg & 0xf).wrapping_add(from_value & 0xf)) & 0x10) != 0);
                     },
-                    2 => { // SP or HL
-                        // let (add_result, did_underflow) = u16::overflowing_add(self.get_double_register(&dest_reg), from_value);
+                    "HL" => { // ADD HL, rr - Carries out of bits 11 and 15, Z is untouched
+                        let hl_value = self.get_double_register(Reg16::HL);
+                        let from_value = self.get_double_register(Self::reg16(&from_param.get_name())?);
+                        let (add_result, did_overflow) = u16::overflowing_add(hl_value, from_value);
+                        self.set_double_register(Reg16::HL, add_result);
 
-                        // set_sub_flag = Some(true);
-                        // set_zero_flag = Some(add_result == 0);
-                        // set_carry_flag = Some(did_underflow);
-                        // set_half_carry_flag = Some((((self.a_reg & 0xf).wrapping_add(from_value & 0xf)) & 0x100) != 0);
+                        set_sub_flag = Some(false);
+                        set_carry_flag = Some(did_overflow);
+                        set_half_carry_flag = Some((hl_value & 0x0FFF) + (from_value & 0x0FFF) > 0x0FFF);
+                    },
+                    "SP" => { // ADD SP, r8
+                        let offset = from_param.get_signed_byte().map_err(CpuErrorReason::InvalidParams)?;
+                        let (add_result, half_carry, carry) = self.add_sp_offset(offset);
+                        self.sp_reg = add_result;
 
-                        unimplemented!("ADD: 16-bit arithmetics");
+                        set_zero_flag = Some(false);
+                        set_sub_flag = Some(false);
+                        set_half_carry_flag = Some(half_carry);
+                        set_carry_flag = Some(carry);
                     },
-                    _ => panic!("Attemp to add to a register of unrecognized len")
+                    _ => return Err(CpuErrorReason::InvalidParams(format!("ADD: Invalid destination ({})", dest_reg)))
                 }
             },
             "SRL" => {
-                assert_eq!(params.len(), 1, "SRL: Invalid amount of params");
-                let param = params.get(0).unwrap();
+                Self::check_param_count(opcode_name, &params, 1)?;
+                let param = params.first().unwrap();
 
                 let reg_name = param.get_name();
                 trace!("SRL: Opearting on \"{}\"", reg_name);
 
                 match reg_name.len() {
                     2 => { // (HL)
-                        let addr = self.get_double_register(&reg_name);
-                        let value = self.get_addr(addr);
+                        let addr = self.get_double_register(Self::reg16(&reg_name)?);
+                        let value = bus.get_addr(addr);
 
                         set_carry_flag = Some(Self::lsb(value.into()) == 1);
                         let new_value = value >> 1;
                         set_zero_flag = Some(new_value == 0);
-                        self.set_addr(addr, new_value);
+                        bus.set_addr(addr, new_value);
                     },
                     1 => { // All other registers
-                        let value = self.get_register(&reg_name);
+                        let value = self.get_register(Self::reg8(&reg_name)?);
 
                         set_carry_flag = Some(Self::lsb(value.into()) == 1);
                         let new_value = value >> 1;
                         set_zero_flag = Some(new_value == 0);
-                        self.set_register(&reg_name, new_value);
+                        self.set_register(Self::reg8(&reg_name)?, new_value);
                     },
-                    _ => panic!("SRL: Invalid register name")
+                    _ => return Err(CpuErrorReason::InvalidParams("SRL: Invalid register name".to_string()))
                 }
 
                 set_sub_flag = Some(false);
                 set_half_carry_flag = Some(false);
             },
             "RR" => { // Rotate right through the carry flag
-                assert_eq!(params.len(), 1);
-                let param = params.get(0).unwrap();
+                Self::check_param_count(opcode_name, &params, 1)?;
+                let param = params.first().unwrap();
                 let reg_name = param.get_name();
 
                 set_half_carry_flag = Some(false);
                 set_sub_flag = Some(false);
 
                 if param.is_immediate() { // Register
-                    let old_value = self.get_register(&reg_name);
+                    let old_value = self.get_register(Self::reg8(&reg_name)?);
                     set_carry_flag = Some(Self::lsb(old_value.into()) == 1);
 
                     let mut new_value = old_value >> 1;
@@ -823,10 +987,10 @@ impl CPU {
                     }
 
                     set_zero_flag = Some(new_value == 0);
-                    self.set_register(&reg_name, new_value);
+                    self.set_register(Self::reg8(&reg_name)?, new_value);
                 } else { // (HL)
-                    let addr = self.get_double_register(&reg_name);
-                    let old_value = self.get_addr(addr);
+                    let addr = self.get_double_register(Self::reg16(&reg_name)?);
+                    let old_value = bus.get_addr(addr);
                     set_carry_flag = Some(Self::lsb(old_value.into()) == 1);
 
                     let mut new_value = old_value >> 1;
@@ -835,17 +999,16 @@ impl CPU {
                     }
 
                     set_zero_flag = Some(new_value == 0);
-                    self.set_addr(addr, new_value);
+                    bus.set_addr(addr, new_value);
                 }
             },
             "RRA" => {
-                assert_eq!(params.len(), 0);
-                let reg_name = &"A".to_string();
+                Self::check_param_count(opcode_name, &params, 0)?;
 
                 set_half_carry_flag = Some(false);
                 set_sub_flag = Some(false);
 
-                let old_value = self.get_register(&reg_name);
+                let old_value = self.get_register(Reg8::A);
                 set_carry_flag = Some(Self::lsb(old_value.into()) == 1);
 
                 let mut new_value = old_value >> 1;
@@ -854,62 +1017,55 @@ impl CPU {
                 }
 
                 set_zero_flag = Some(new_value == 0);
-                self.set_register(&reg_name, new_value);
+                self.set_register(Reg8::A, new_value);
             },
             _ => {
-                self.dump_memory();
-                unimplemented!("Opcode name ({})", opcode_data["mnemonic"]);
+                self.dump_memory(bus);
+                return Err(CpuErrorReason::Unimplemented(format!("Opcode name ({})", opcode_data["mnemonic"])));
             }
         }
 
         if should_inc_pc {
             trace!("Increasing PC");
-            self.pc_reg += opcode_data["bytes"].as_u64().unwrap() as u16;
+            self.pc_reg = self.get_next_pc(&opcode_data, halt_bug);
         } else {
             trace!("NOT Increasing PC");
         }
 
-        self.verify_flag(opcode_data["flags"]["Z"].as_str().unwrap(), set_zero_flag, "Zero");
-        match set_zero_flag {
-            Some(value) => {
-                self.set_zero_flag(value);
-            },
-            None => ()
+        self.verify_flag(opcode_data["flags"]["Z"].as_str().unwrap(), set_zero_flag, "Zero")?;
+        if let Some(value) = set_zero_flag {
+            self.set_zero_flag(value);
         }
 
-        self.verify_flag(opcode_data["flags"]["N"].as_str().unwrap(), set_sub_flag, "Sub");
-        match set_sub_flag {
-            Some(value) => {
-                self.set_sub_flag(value);
-            },
-            None => ()
+        self.verify_flag(opcode_data["flags"]["N"].as_str().unwrap(), set_sub_flag, "Sub")?;
+        if let Some(value) = set_sub_flag {
+            self.set_sub_flag(value);
         }
 
-        self.verify_flag(opcode_data["flags"]["H"].as_str().unwrap(), set_half_carry_flag, "Half Carry");
-        match set_half_carry_flag {
-            Some(value) => {
-                self.set_half_carry_flag(value);
-            },
-            None => ()
+        self.verify_flag(opcode_data["flags"]["H"].as_str().unwrap(), set_half_carry_flag, "Half Carry")?;
+        if let Some(value) = set_half_carry_flag {
+            self.set_half_carry_flag(value);
         }
 
-        self.verify_flag(opcode_data["flags"]["C"].as_str().unwrap(), set_carry_flag, "Carry");
-        match set_carry_flag {
-            Some(value) => {
-                self.set_carry_flag(value);
-            },
-            None => ()
+        self.verify_flag(opcode_data["flags"]["C"].as_str().unwrap(), set_carry_flag, "Carry")?;
+        if let Some(value) = set_carry_flag {
+            self.set_carry_flag(value);
         }
 
+        // Conditional instructions have the cycles when branching first, then when not
+        let cycles = opcode_data["cycles"].as_array().unwrap();
+        let cycles_index = if !branch_taken && cycles.len() > 1 { 1 } else { 0 };
+        bus.tick(cycles[cycles_index].as_u64().unwrap() as usize);
+
+        return Ok(());
     }
     
-    pub fn dump_memory(&self) {
+    pub fn dump_memory(&self, bus: &impl Bus) {
         let mut i: usize = 0x00;
-        let ram = self.ram_memory_ref.borrow_mut();
         while i < 0xFFFF {
             let mut data_string: String = "".to_string();
             for j in 0..8 {
-                data_string += &format!("0x{:02X} ", ram.get_addr(i as u16 + j));
+                data_string += &format!("0x{:02X} ", bus.get_addr(i as u16 + j));
                 // temp_vec.push(ram.get_addr(i as u16 + j));
             }
             trace!("0x{:04X} -> {}", i, data_string);
@@ -917,288 +1073,316 @@ impl CPU {
         }
     }
 
-    // Register stuff
-    pub fn get_register(&self, reg: &String) -> u8 {
-        match reg.to_lowercase().as_str() {
-            "a" => self.a_reg,
-            "b" => self.b_reg,
-            "c" => self.c_reg,
-            "d" => self.d_reg,
-            "e" => self.e_reg,
-            "f" => self.f_reg,
-            "h" => self.h_reg,
-            "l" => self.l_reg,
-            _ => panic!("Requested value of unknown register ({})", reg)
-        }
+    // Trace log stuff
+    // Registers and the next 4 bytes at PC in the format used by Gameboy Doctor, so runs can be diffed
+    // line by line against reference logs
+    pub fn get_state_line(&self, bus: &impl Bus) -> String {
+        format!("A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            self.a_reg, self.f_reg, self.b_reg, self.c_reg, self.d_reg, self.e_reg, self.h_reg, self.l_reg,
+            self.sp_reg, self.pc_reg,
+            bus.get_addr(self.pc_reg),
+            bus.get_addr(self.pc_reg.wrapping_add(1)),
+            bus.get_addr(self.pc_reg.wrapping_add(2)),
+            bus.get_addr(self.pc_reg.wrapping_add(3)))
     }
 
-    fn set_register(&mut self, reg: &String, value: u8) {
-        match reg.to_lowercase().as_str() {
-            "a" => self.a_reg = value,
-            "b" => self.b_reg = value,
-            "c" => self.c_reg = value,
-            "d" => self.d_reg = value,
-            "e" => self.e_reg = value,
-            "f" => self.f_reg = value,
-            "h" => self.h_reg = value,
-            "l" => self.l_reg = value,
-            _ => panic!("Requested writing to unknown register ({})", reg)
+    // Write the state before every instruction to a file
+    pub fn enable_trace_log(&mut self, path: &str) -> io::Result<()> {
+        self.trace_log = Some(LineWriter::new(File::create(path)?));
+        Ok(())
+    }
+
+    fn write_trace_log(&mut self, bus: &impl Bus) {
+        if self.trace_log.is_none() {
+            return;
         }
+
+        // A full disk shouldn't take the emulator down with it, just stop tracing
+        let state_line = self.get_state_line(bus);
+        if let Some(trace_log) = &mut self.trace_log {
+            if let Err(e) = writeln!(trace_log, "{}", state_line) {
+                error!("CPU: Failed writing to trace log, disabling it ({})", e);
+                self.trace_log = None;
+            }
+        }
+    }
+
+    pub fn is_trace_log_enabled(&self) -> bool {
+        self.trace_log.is_some()
+    }
+
+    // Symbols stuff
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = Some(symbols);
+    }
+
+    pub fn get_symbols(&self) -> Option<&SymbolTable> {
+        self.symbols.as_ref()
     }
 
-    fn get_double_register(&self, reg: &String) -> u16 {
-        if reg.to_uppercase() == "SP" {
-            return self.pc_reg;
+    fn format_addr(&self, addr: u16) -> String {
+        match &self.symbols {
+            Some(symbols) => symbols.format_addr(addr),
+            None => format!("0x{:04X}", addr)
         }
+    }
 
-        assert_eq!(reg.len(), 2, "CPU: Tried getting double register from single reg");
+    fn push_call_stack(&mut self, return_addr: u16) {
+        // Code that never returns (or messes with the stack) shouldn't make this grow forever
+        if self.call_stack.len() >= CPU_CALL_STACK_MAX_DEPTH {
+            self.call_stack.remove(0);
+        }
 
-        let first_reg: String = reg[0..1].to_string();
-        let second_reg: String = reg[1..2].to_string();
+        self.call_stack.push(return_addr);
+    }
 
-        let mut value: u16 = (self.get_register(&first_reg) as u16) << 8; // msb
-        value += self.get_register(&second_reg) as u16; // lsb
+    // Current location followed by the location each active CALL returns to
+    pub fn get_backtrace(&self) -> Vec<String> {
+        let mut backtrace: Vec<String> = vec![format!("#0 {}", self.format_addr(self.pc_reg))];
+        for (i, return_addr) in self.call_stack.iter().rev().enumerate() {
+            backtrace.push(format!("#{} {}", i + 1, self.format_addr(*return_addr)));
+        }
 
-        return value;
+        return backtrace;
     }
 
-    fn set_double_register(&mut self, reg: &String, value: u16) {
-        assert_eq!(reg.len(), 2, "CPU: Tried setting double register from single reg");
+    // Register stuff
+    pub fn get_register(&self, reg: Reg8) -> u8 {
+        match reg {
+            Reg8::A => self.a_reg,
+            Reg8::F => self.f_reg,
+            Reg8::B => self.b_reg,
+            Reg8::C => self.c_reg,
+            Reg8::D => self.d_reg,
+            Reg8::E => self.e_reg,
+            Reg8::H => self.h_reg,
+            Reg8::L => self.l_reg
+        }
+    }
 
-        match reg.to_uppercase().as_str() {
-            "SP" => self.sp_reg = value,
-            _ => {
-                let first_reg: String = reg[0..1].to_string();
-                let second_reg: String = reg[1..2].to_string();
+    pub fn set_register(&mut self, reg: Reg8, value: u8) {
+        match reg {
+            Reg8::A => self.a_reg = value,
+            Reg8::F => self.f_reg = value & FLAG_REGISTER_MASK,
+            Reg8::B => self.b_reg = value,
+            Reg8::C => self.c_reg = value,
+            Reg8::D => self.d_reg = value,
+            Reg8::E => self.e_reg = value,
+            Reg8::H => self.h_reg = value,
+            Reg8::L => self.l_reg = value
+        }
+    }
 
-                let msb: u8 = Self::msb(value);
-                let lsb: u8 = Self::lsb(value);
+    pub fn get_double_register(&self, reg: Reg16) -> u16 {
+        match reg.get_halves() {
+            Some((msb_reg, lsb_reg)) => ((self.get_register(msb_reg) as u16) << 8) | self.get_register(lsb_reg) as u16,
+            None if reg == Reg16::SP => self.sp_reg,
+            None => self.pc_reg
+        }
+    }
 
-                self.set_register(&first_reg, msb);
-                self.set_register(&second_reg, lsb);
-            }
+    pub fn set_double_register(&mut self, reg: Reg16, value: u16) {
+        match reg.get_halves() {
+            Some((msb_reg, lsb_reg)) => {
+                self.set_register(msb_reg, Self::msb(value));
+                self.set_register(lsb_reg, Self::lsb(value));
+            },
+            None if reg == Reg16::SP => self.sp_reg = value,
+            None => self.pc_reg = value
         }
     }
 
+    // Register names from the opcode table
+    fn reg8(name: &str) -> Result<Reg8, CpuErrorReason> {
+        Reg8::from_name(name).ok_or_else(|| CpuErrorReason::UnknownRegister(name.to_string()))
+    }
+
+    fn reg16(name: &str) -> Result<Reg16, CpuErrorReason> {
+        Reg16::from_name(name).ok_or_else(|| CpuErrorReason::UnknownRegister(name.to_string()))
+    }
+
+    // SP plus a signed offset (ADD SP, r8 and LD HL, SP+r8). The carries come from adding the offset
+    // to the low byte as an unsigned value, whatever its sign
+    fn add_sp_offset(&self, offset: i8) -> (u16, bool, bool) {
+        let unsigned_offset = offset as u8 as u16;
+        let half_carry = (self.sp_reg & 0x0F) + (unsigned_offset & 0x0F) > 0x0F;
+        let carry = (self.sp_reg & 0xFF) + unsigned_offset > 0xFF;
+
+        return (self.sp_reg.wrapping_add_signed(offset as i16), half_carry, carry);
+    }
+
+    fn return_from_call(&mut self, bus: &mut impl Bus) {
+        self.call_stack.pop();
+        self.pc_reg = self.stack_pop_double(bus);
+    }
+
     // Stack stuff
-    fn stack_push(&mut self, value: u8) {
-         self.sp_reg -= 1;
-         self.set_addr(self.sp_reg, value);
+    fn stack_push(&mut self, bus: &mut impl Bus, value: u8) {
+         self.sp_reg = self.sp_reg.wrapping_sub(1);
+         bus.set_addr(self.sp_reg, value);
     }
 
-    fn stack_pop(&mut self) -> u8 {
-        let ret_value = self.get_addr(self.sp_reg);
-        self.sp_reg += 1;
+    fn stack_pop(&mut self, bus: &mut impl Bus) -> u8 {
+        let ret_value = bus.get_addr(self.sp_reg);
+        self.sp_reg = self.sp_reg.wrapping_add(1);
         return ret_value;
     }
 
-    fn stack_push_double(&mut self, value: u16) {
+    fn stack_push_double(&mut self, bus: &mut impl Bus, value: u16) {
         let lsb = Self::lsb(value);
         let msb = Self::msb(value);
         
-        self.stack_push(msb);
-        self.stack_push(lsb);
+        self.stack_push(bus, msb);
+        self.stack_push(bus, lsb);
     }
 
-    fn stack_pop_double(&mut self) -> u16 {
-        let lsb = self.stack_pop() as u16;
-        let msb = self.stack_pop() as u16;
+    fn stack_pop_double(&mut self, bus: &mut impl Bus) -> u16 {
+        let lsb = self.stack_pop(bus) as u16;
+        let msb = self.stack_pop(bus) as u16;
     
         return lsb + (msb << 8);
     }
 
-    // Memory stuff
-    fn get_addr(&self, addr: u16) -> u8 {
-        if addr < CARTRIDGE_ROM_SIZE_DEFAULT as u16 
-        { // 0x0000 -> 0x8000
-            return self.ram_memory_ref.borrow_mut().get_addr(addr);
-        } 
-        else if addr >= CARTRIDGE_ROM_SIZE_DEFAULT as u16 && addr < RAM_ECHO_RANGE_START 
-        { // 0x8000 -> 0xE000
-            return self.ram_memory_ref.borrow_mut().get_addr(addr);
-        }
-        else if addr >= RAM_ECHO_RANGE_START && addr < RAM_SPRITE_ATTRIBUTE_TABLE_RANGE_START
-        { // 0xE000 -> 0xFE00
-            return self.ram_memory_ref.borrow_mut().get_addr(addr - 0x2000);
-        }
-        else if addr >= RAM_SPRITE_ATTRIBUTE_TABLE_RANGE_START && addr < RAM_IO_PORTS_RANGE_START
-        { // 0xFE00 -> 0xFF00
-            return self.ram_memory_ref.borrow_mut().get_addr(addr);
-        }
-        else if addr >= RAM_IO_PORTS_RANGE_START && addr < RAM_EMPTY_RANGE_START 
-        { // 0xFF00 -> 0xFF4C
-            return self.ppu_ref.borrow().get_addr(addr);
-        } 
-        else if addr >= RAM_EMPTY_RANGE_START && addr < RAM_INTERNAL_RANGE_START
-        { // 0xFF4C -> 0xFF80
-            warn!("Requested addr at a memory addr that should not be used (0x{:04X})", addr);
-            return self.ram_memory_ref.borrow_mut().get_addr(addr);
-        } 
-        else if addr >= RAM_INTERNAL_RANGE_START
-        { // 0xFF80 -> END
-            return self.ram_memory_ref.borrow_mut().get_addr(addr);
-        } 
-        else 
-        { // DAFUK
-            panic!("Dafuk? (0x{:04X})", addr);
-        }
+    pub fn is_locked_up(&self) -> bool {
+        self.locked_up
     }
 
-    fn set_addr(&mut self, addr: u16, value: u8) {
-        if addr < CARTRIDGE_ROM_SIZE_DEFAULT as u16 
-        { // 0x0000 -> 0x8000
-            self.ram_memory_ref.borrow_mut().set_addr(addr, value);
-        } 
-        else if addr >= CARTRIDGE_ROM_SIZE_DEFAULT as u16 && addr < RAM_ECHO_RANGE_START 
-        { // 0x8000 -> 0xE000
-            debug!("VRAM: Write {} to addr 0x{:04X}", value, addr);
-            self.ram_memory_ref.borrow_mut().set_addr(addr, value);
-        }
-        else if addr >= RAM_ECHO_RANGE_START && addr < RAM_SPRITE_ATTRIBUTE_TABLE_RANGE_START
-        { // 0xE000 -> 0xFE00
-            self.ram_memory_ref.borrow_mut().set_addr(addr - 0x2000, value);
-        }
-        else if addr >= RAM_SPRITE_ATTRIBUTE_TABLE_RANGE_START && addr < RAM_IO_PORTS_RANGE_START
-        { // 0xFE00 -> 0xFF00
-            self.ram_memory_ref.borrow_mut().set_addr(addr, value);
-        }
-        else if addr >= RAM_IO_PORTS_RANGE_START && addr < RAM_EMPTY_RANGE_START
-        { // 0xFF00 -> 0xFF4C
-            return self.ppu_ref.borrow_mut().set_addr(addr, value);
-        } 
-        else if addr >= RAM_EMPTY_RANGE_START && addr < RAM_INTERNAL_RANGE_START
-        { // 0xFF4C -> 0xFF80
-            warn!("Requested write to addr at a memory addr that should not be used (0x{:04X})", addr);
-            self.ram_memory_ref.borrow_mut().set_addr(addr, value);
-        } 
-        else if addr >= RAM_INTERNAL_RANGE_START
-        { // 0xFF80 -> END
-            self.ram_memory_ref.borrow_mut().set_addr(addr, value);
-        } 
-        else 
-        { // DAFUK
-            panic!("Dafuk? (0x{:04X})", addr);
-        }
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn is_ime_enabled(&self) -> bool {
+        self.ime
+    }
+
+    pub fn set_ime(&mut self, value: bool) {
+        self.ime = value;
+        self.ime_delay = 0;
+    }
+
+    // Interrupts stuff
+    fn get_pending_interrupts(bus: &impl Bus) -> u8 {
+        bus.get_addr(INTERRUPT_ADDR_ENABLE) & bus.get_addr(INTERRUPT_ADDR_FLAG) & INTERRUPT_MASK
+    }
+
+    // Call the handler of the highest priority pending interrupt (the lowest bit)
+    fn service_interrupt(&mut self, bus: &mut impl Bus, pending_interrupts: u8) {
+        let bit = pending_interrupts.trailing_zeros() as u16;
+        let flags = bus.get_addr(INTERRUPT_ADDR_FLAG);
+        bus.set_addr(INTERRUPT_ADDR_FLAG, flags & !(1 << bit));
+
+        self.ime = false;
+        self.stack_push_double(bus, self.pc_reg);
+        self.push_call_stack(self.pc_reg);
+        self.pc_reg = INTERRUPT_VECTOR_START + bit * 8;
+        trace!("Servicing interrupt {} at 0x{:04X}", bit, self.pc_reg);
+
+        bus.tick(INTERRUPT_DISPATCH_CYCLES);
     }
 
     pub fn get_program_counter(&self) -> u16 {
         self.pc_reg
     }
 
-    // Params stuff
-    fn get_params(&self, opcode_data: &Value) -> Vec<Param> {
-        if !opcode_data["operands"].is_array() {
-            panic!("Operands value is not array");
-        }
+    pub fn set_program_counter(&mut self, value: u16) {
+        self.pc_reg = value;
+    }
 
-        let mut return_value = Vec::<Param>::new();
+    pub fn get_stack_pointer(&self) -> u16 {
+        self.sp_reg
+    }
 
-        for operand in opcode_data["operands"].as_array().unwrap() {           
-            let mut param = Param::new(operand.clone());
-            
-            let value: MemValue = match param.get_name().as_str() {
-                "a16" => {
-                    let mut value: u16= self.get_addr(self.pc_reg + 1) as u16;
-                    value += (self.get_addr(self.pc_reg + 2) as u16) << 8;
-                    MemValue::Double(value)
-                },
-                "d16" => {
-                    let mut value: u16= self.get_addr(self.pc_reg + 1) as u16;
-                    value += (self.get_addr(self.pc_reg + 2) as u16) << 8;
-                    MemValue::Double(value)
-                },
-                "d8" => {
-                    MemValue::Byte(self.get_addr(self.pc_reg + 1))
-                },
-                "r8" => {
-                    MemValue::SignedByte(self.get_addr(self.pc_reg + 1) as i8)
-                },
-                "a8" => {
-                    MemValue::Byte(self.get_addr(self.pc_reg + 1))
-                },
-                _ => {
-                    // let chars = param.get_name().clone();
-                    // for c in chars.chars() {
-                    //     if !c.is_alphabetic() {
-                    //         panic!("Invalid char ({})", c)
-                    //     }
-                    // }
-
-                    MemValue::Name(param.get_name())
-                }                
-            };
+    pub fn set_stack_pointer(&mut self, value: u16) {
+        self.sp_reg = value;
+    }
 
-            param.set_param_value(value);
+    // Params stuff
+    fn get_params(&self, bus: &impl Bus, opcode_data: &Value, halt_bug: bool) -> Vec<Param> {
+        // With the halt bug the opcode byte is read again as the first operand
+        parse_params(opcode_data, self.pc_reg, |addr| {
+            bus.get_addr(if halt_bug { addr.wrapping_sub(1) } else { addr })
+        })
+    }
 
-            return_value.push(
-                param
-            );
+    fn check_param_count(opcode_name: &str, params: &[Param], count: usize) -> Result<(), CpuErrorReason> {
+        if params.len() != count {
+            return Err(CpuErrorReason::InvalidParams(format!("{}: Expected {} params, got {}", opcode_name, count, params.len())));
         }
-        
-        return_value
+
+        return Ok(());
     }
 
-    fn get_condition_value(&self, cond: String) -> bool {
+    fn get_condition_value(&self, cond: String) -> Result<bool, CpuErrorReason> {
         match cond.to_uppercase().as_str() {
-            "Z" => self.get_zero_flag(),
-            "NZ" => !self.get_zero_flag(),
-            "C" => self.get_carry_flag(),
-            "NC" => !self.get_carry_flag(),
-            _ => panic!("Unknown condition ({})", cond)
+            "Z" => Ok(self.get_zero_flag()),
+            "NZ" => Ok(!self.get_zero_flag()),
+            "C" => Ok(self.get_carry_flag()),
+            "NC" => Ok(!self.get_carry_flag()),
+            _ => Err(CpuErrorReason::UnknownCondition(cond))
         }
     }
 
     // Flags stuff
-    fn set_flag(&mut self, value: bool, mask: u8) {
+    pub fn set_flag(&mut self, flag: Flag, value: bool) {
+        let mask = flag.get_mask();
         if value {
-            self.f_reg = self.f_reg | mask
+            self.f_reg |= mask
         } else {
-            self.f_reg = self.f_reg & (mask ^ 0b11111111)
+            self.f_reg &= mask ^ 0b11111111 
         }
     }
 
-    fn verify_flag(&self, doc: &str, value: Option<bool>, name: &str) {
+    // The opcode table says what every instruction does to the flags, catch the ones that disagree with it
+    fn verify_flag(&self, doc: &str, value: Option<bool>, name: &str) -> Result<(), CpuErrorReason> {
         // trace!("Checking Flag {}, with doc \"{}\" and value \"{:?}\"", name, doc, value);
-        if doc == "-" { assert!(value == Option::None, "{} Flag should be empty", name);}
-        if doc == "1" { assert!(value == Option::Some(true), "{} Flag has to be true", name); }
-        if doc == "0" { assert!(value == Option::Some(false), "{} Flag has to be false", name); }
-        if doc != "-" { assert!(value != Option::None, "{} Flag cannot be empty", name); }
+        let error = match doc {
+            "-" if value.is_some() => Some("should be empty"),
+            "1" if value != Some(true) => Some("has to be true"),
+            "0" if value != Some(false) => Some("has to be false"),
+            _ if doc != "-" && value.is_none() => Some("cannot be empty"),
+            _ => None
+        };
+
+        match error {
+            Some(error) => Err(CpuErrorReason::FlagMismatch(format!("{} Flag {}", name, error))),
+            None => Ok(())
+        }
     }
 
-    fn get_flag(&self, mask: u8) -> bool {
-        self.f_reg & mask != 0
+    pub fn get_flag(&self, flag: Flag) -> bool {
+        self.f_reg & flag.get_mask() != 0
     }
 
     fn set_zero_flag(&mut self, value: bool) {
-        self.set_flag(value, FLAG_ZERO_MASK)
+        self.set_flag(Flag::Zero, value)
     }
 
     pub fn get_zero_flag(&self) -> bool {
-        self.get_flag(FLAG_ZERO_MASK)
+        self.get_flag(Flag::Zero)
     }
 
     fn set_sub_flag(&mut self, value: bool) {
-        self.set_flag(value, FLAG_SUB_MASK)
+        self.set_flag(Flag::Sub, value)
     }
 
     pub fn get_sub_flag(&self) -> bool {
-        self.get_flag(FLAG_SUB_MASK)
+        self.get_flag(Flag::Sub)
     }
 
     fn set_half_carry_flag(&mut self, value: bool) {
-        self.set_flag(value, FLAG_HALF_CARRY_MASK)
+        self.set_flag(Flag::HalfCarry, value)
     }
 
     pub fn get_half_carry_flag(&self) -> bool {
-        self.get_flag(FLAG_HALF_CARRY_MASK)
+        self.get_flag(Flag::HalfCarry)
     }
 
     fn set_carry_flag(&mut self, value: bool) {
-        self.set_flag(value, FLAG_CARRY_MASK)
+        self.set_flag(Flag::Carry, value)
     }
 
     pub fn get_carry_flag(&self) -> bool {
-        self.get_flag(FLAG_CARRY_MASK)
+        self.get_flag(Flag::Carry)
     }
 
     fn msb(value: u16) -> u8 {
@@ -1219,7 +1403,7 @@ impl CPU {
         ));
 
         // Flag
-        for flag in vec!["C", "H", "N", "Z"] {
+        for flag in ["C", "H", "N", "Z"] {
             let flag_name = match flag {
                 "C" => "Carry",
                 "H" => "Half-Carry",
@@ -1238,7 +1422,7 @@ impl CPU {
 
             opcode_string.push(
                 format!("    {} -> {}",
-                flag_name.to_string(),
+                flag_name,
                 flag_status_pretty
             ))            
         }
@@ -1267,7 +1451,7 @@ impl CPU {
                 options.push("increment".to_string());
             }
 
-            if options.len() != 0 {
+            if !options.is_empty() {
                 param_string += " ";
                 param_string += &options.join(", ");
             }
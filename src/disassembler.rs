@@ -0,0 +1,128 @@
+use crate::cpu::get_opcodes;
+use crate::param::{Param, MemValue, parse_params};
+use crate::symbols::SymbolTable;
+
+use serde_json::Value;
+
+pub struct DisassembledInstruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    pub params: Vec<Param>,
+    pub label: Option<String>, // Label placed on this instruction
+    pub target_label: Option<String> // Label of the address this instruction uses (jump target, memory addr)
+}
+
+impl DisassembledInstruction {
+    pub fn get_text(&self) -> String {
+        let mut text = self.mnemonic.clone();
+
+        let mut params_text: String = "".to_string();
+        for (i, param) in self.params.iter().enumerate() {
+            if i == 0 {
+                params_text = param.get_printable();
+            } else if self.params[i - 1].is_immediate() && self.params[i - 1].is_increment() {
+                // LD HL, SP+r8 - the offset belongs to the previous param
+                params_text += &param.get_printable();
+            } else {
+                params_text += ", ";
+                params_text += &param.get_printable();
+            }
+        }
+
+        if !params_text.is_empty() {
+            text = format!("{} {}", text, params_text);
+        }
+
+        return text;
+    }
+
+    // Address, raw bytes and the instruction itself, for example "0x0150: C3 50 01  JP 0x0150"
+    pub fn get_line(&self) -> String {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let line = format!("0x{:04X}: {:<9} {}", self.addr, bytes.join(" "), self.get_text());
+
+        match &self.target_label {
+            Some(label) => format!("{} ; {}", line, label),
+            None => line
+        }
+    }
+}
+
+pub struct Disassembler {
+    opcodes: &'static Value,
+    symbols: Option<SymbolTable>
+}
+
+impl Default for Disassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Disassembler {
+    pub fn new() -> Disassembler {
+        Disassembler {
+            opcodes: get_opcodes(),
+            symbols: None
+        }
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = Some(symbols);
+    }
+
+    fn get_label(&self, addr: u16) -> Option<String> {
+        self.symbols.as_ref()?.get_label(addr).map(|label| label.to_string())
+    }
+
+    pub fn disassemble_instruction(&self, addr: u16, read_addr: impl Fn(u16) -> u8) -> DisassembledInstruction {
+        let opcode = read_addr(addr);
+        let opcode_data: &Value = if opcode == 0xCB {
+            &self.opcodes["cbprefixed"][format!("0x{:02X}", read_addr(addr.wrapping_add(1)))]
+        } else {
+            &self.opcodes["unprefixed"][format!("0x{:02X}", opcode)]
+        };
+
+        let length = opcode_data["bytes"].as_u64().unwrap() as u16;
+        let bytes: Vec<u8> = (0..length).map(|offset| read_addr(addr.wrapping_add(offset))).collect();
+
+        let params = parse_params(opcode_data, addr, &read_addr);
+        let target_label = params.iter().find_map(|param| {
+            match (param.get_jump_target(), param.get_value()) {
+                (Some(target), _) => self.get_label(target),
+                (None, MemValue::Double(target)) => self.get_label(target),
+                _ => None
+            }
+        });
+
+        DisassembledInstruction {
+            addr,
+            bytes,
+            mnemonic: opcode_data["mnemonic"].as_str().unwrap().to_string(),
+            params,
+            label: self.get_label(addr),
+            target_label
+        }
+    }
+
+    // Linearly disassemble every instruction starting in the range start..=end.
+    // Data is decoded as if it were code, there is no attempt to follow the control flow
+    pub fn disassemble(&self, start: u16, end: u16, read_addr: impl Fn(u16) -> u8) -> Vec<DisassembledInstruction> {
+        let mut instructions: Vec<DisassembledInstruction> = Vec::new();
+        let mut addr: u32 = start as u32;
+
+        while addr <= end as u32 {
+            let instruction = self.disassemble_instruction(addr as u16, &read_addr);
+            addr += instruction.bytes.len() as u32;
+            instructions.push(instruction);
+        }
+
+        return instructions;
+    }
+
+    // Disassemble a rom image, addresses are offsets in the rom (which is how bank 0 and 1 are mapped)
+    pub fn disassemble_rom(&self, rom_content: &[u8], start: u16, end: u16) -> Vec<DisassembledInstruction> {
+        self.disassemble(start, end, |addr| *rom_content.get(addr as usize).unwrap_or(&0x00))
+    }
+}
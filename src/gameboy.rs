@@ -0,0 +1,179 @@
+use crate::consts::*;
+use crate::bus::{Bus, MemoryBus};
+use crate::cpu::{CPU, CpuError};
+use crate::ram_memory::RamMemory;
+use crate::rom_parser::{Rom, RomError};
+use crate::model::Model;
+use crate::joypad::{Button, InputEvent};
+use crate::screenshot::{self, Screenshot, ScreenshotError};
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The whole machine, what frontends and tools should use instead of wiring the cpu and the bus themselves.
+// It owns all of its parts, so separate instances can run on separate threads
+pub struct GameBoy {
+    cpu: CPU,
+    bus: MemoryBus,
+    model: Model
+}
+
+impl GameBoy {
+    // Without a boot rom the cpu starts at 0x0100 in the post boot state
+    pub fn init(rom: &Rom, model: Model, boot_rom: Option<Vec<u8>>) -> Result<GameBoy, String> {
+        let mut ram_memory = RamMemory::init_from_rom(rom);
+        ram_memory.set_cgb_mode(model.is_cgb_mode(rom.cgb_flag));
+
+        // Boot rom is mapped over the cartridge until the program writes to 0xFF50
+        let boot_rom_enabled = boot_rom.is_some();
+        if let Some(boot_rom) = boot_rom {
+            ram_memory.set_boot_rom(boot_rom)?;
+        }
+
+        let mut bus = MemoryBus::new(ram_memory);
+        let mut cpu: CPU = CPU::init(boot_rom_enabled);
+
+        if !boot_rom_enabled {
+            cpu.apply_post_boot_state(&bus, model);
+            bus.apply_post_boot_state(model);
+        }
+
+        Ok(GameBoy {
+            cpu,
+            bus,
+            model
+        })
+    }
+
+    // For when the cpu and the bus need to be set up by hand (tests, tools)
+    pub fn from_parts(cpu: CPU, bus: MemoryBus, model: Model) -> GameBoy {
+        GameBoy {
+            cpu,
+            bus,
+            model
+        }
+    }
+
+    // With the model detected from the rom and no boot rom
+    pub fn load(rom_content: Vec<u8>) -> Result<GameBoy, RomError> {
+        let rom: Rom = Rom::parse(rom_content)?;
+        rom.verify_cartridge_type()?;
+        let model: Model = Model::detect(rom.cgb_flag);
+
+        return Ok(Self::init(&rom, model, None).expect("Failed creating gameboy without a boot rom"));
+    }
+
+    // Execute a single instruction
+    pub fn step(&mut self) -> Result<(), CpuError> {
+        self.cpu.execute_instruction(&mut self.bus)
+    }
+
+    // Run until the ppu gets to the next VBlank and render the screen. With the LCD off there are no
+    // frames, so give up after a frame worth of cycles
+    pub fn run_frame(&mut self) -> Result<(), CpuError> {
+        let frame_counter = self.bus.get_frame_counter();
+        let start_cycles = self.bus.get_cycle_counter();
+
+        while self.bus.get_frame_counter() == frame_counter {
+            self.step()?;
+
+            let max_cycles = if self.bus.is_double_speed() { PPU_CYCLES_PER_FRAME * 2 } else { PPU_CYCLES_PER_FRAME };
+            if self.bus.get_cycle_counter() - start_cycles >= max_cycles {
+                break;
+            }
+        }
+
+        self.render();
+        return Ok(());
+    }
+
+    // Frames are counted from 0 when this is called, each event is applied before its frame runs
+    pub fn run_frames(&mut self, frames: usize, inputs: &[InputEvent]) -> Result<(), CpuError> {
+        for frame in 0..frames {
+            for input in inputs.iter().filter(|input| input.frame == frame) {
+                self.set_button(input.button, input.pressed);
+            }
+            self.run_frame()?;
+        }
+
+        return Ok(());
+    }
+
+    pub fn render(&mut self) {
+        self.bus.render();
+    }
+
+    // What's on the LCD, LCD_WIDTH * LCD_HEIGHT 0xRRGGBB pixels row by row
+    pub fn get_framebuffer(&self) -> Vec<u32> {
+        self.get_screenshot().pixels
+    }
+
+    // The 160x144 LCD, the ppu draws it in the top left corner of its 256x256 buffer
+    pub fn get_screenshot(&self) -> Screenshot {
+        Screenshot::from_framebuffer(self.bus.get_ppu().get_buffer()).crop(LCD_WIDTH, LCD_HEIGHT)
+    }
+
+    // Saves the LCD as a png with a timestamped name in the directory, scaled up by an integer factor
+    pub fn save_screenshot(&self, dir: &Path, scale: usize) -> Result<PathBuf, ScreenshotError> {
+        let path = screenshot::get_timestamped_path(dir, SCREENSHOT_FILE_PREFIX);
+        self.get_screenshot().upscale(scale).save_png(&path)?;
+        return Ok(path);
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.bus.set_button(button, pressed);
+    }
+
+    // Reads and writes go through the memory map, like the program's own would
+    pub fn read_memory(&self, addr: u16) -> u8 {
+        self.bus.get_addr(addr)
+    }
+
+    pub fn write_memory(&mut self, addr: u16, value: u8) {
+        self.bus.set_addr(addr, value);
+    }
+
+    // Bytes the program sent over the serial port
+    pub fn get_serial_output(&self) -> &[u8] {
+        self.bus.get_serial_output()
+    }
+
+    pub fn get_frame_counter(&self) -> usize {
+        self.bus.get_frame_counter()
+    }
+
+    pub fn get_cycle_counter(&self) -> usize {
+        self.bus.get_cycle_counter()
+    }
+
+    pub fn get_model(&self) -> Model {
+        self.model
+    }
+
+    pub fn get_cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn get_cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    pub fn get_bus(&self) -> &MemoryBus {
+        &self.bus
+    }
+
+    pub fn get_bus_mut(&mut self) -> &mut MemoryBus {
+        &mut self.bus
+    }
+
+    pub fn get_state_line(&self) -> String {
+        self.cpu.get_state_line(&self.bus)
+    }
+
+    // Gameboy Doctor logs expect LY to always read 0x90
+    pub fn enable_trace_log(&mut self, path: &str) -> io::Result<()> {
+        self.cpu.enable_trace_log(path)?;
+        self.bus.get_ppu_mut().set_ly_stub(true);
+        Ok(())
+    }
+}
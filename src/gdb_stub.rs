@@ -0,0 +1,508 @@
+use crate::consts::*;
+use crate::cpu::{CPU, CpuError};
+use crate::gameboy::GameBoy;
+use crate::ram_search::{RamSearch, SearchFilter};
+use crate::symbols::SymbolTable;
+
+use std::collections::HashSet;
+use std::io::{self, Read, Write, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+
+// Minimal GDB remote serial protocol server, enough for gdb (or any other tool speaking the protocol)
+// to inspect registers and memory, place breakpoints and step through the emulated program
+pub struct GdbStub {
+    stream: TcpStream,
+    breakpoints: HashSet<u16>,
+    ram_search: Option<RamSearch>,
+    no_ack_mode: bool
+}
+
+enum Action {
+    Reply(String),
+    EnableNoAck,
+    Detach,
+    Kill
+}
+
+impl GdbStub {
+    // Blocks until a debugger connects to the listener
+    pub fn accept(listener: &TcpListener) -> io::Result<GdbStub> {
+        let (stream, peer) = listener.accept()?;
+        info!("GDB: Debugger connected from {}", peer);
+        stream.set_nodelay(true)?;
+
+        Ok(GdbStub {
+            stream,
+            breakpoints: HashSet::new(),
+            ram_search: None,
+            no_ack_mode: false
+        })
+    }
+
+    // Handle debugger commands until it detaches, kills the session or disconnects.
+    // on_step is called after every executed instruction (used for rendering)
+    pub fn serve(&mut self, gameboy: &mut GameBoy, mut on_step: impl FnMut(&mut GameBoy)) -> io::Result<()> {
+        loop {
+            let packet = match self.read_packet()? {
+                Some(packet) => packet,
+                None => {
+                    info!("GDB: Debugger disconnected");
+                    return Ok(());
+                }
+            };
+
+            trace!("GDB: <- {}", packet);
+
+            match self.handle_packet(gameboy, &packet, &mut on_step)? {
+                Action::Reply(reply) => self.send_packet(&reply)?,
+                Action::EnableNoAck => {
+                    self.send_packet("OK")?;
+                    self.no_ack_mode = true;
+                },
+                Action::Detach => {
+                    info!("GDB: Debugger detached");
+                    self.send_packet("OK")?;
+                    return Ok(());
+                },
+                Action::Kill => {
+                    info!("GDB: Debugger killed the session");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, gameboy: &mut GameBoy, packet: &str, on_step: &mut impl FnMut(&mut GameBoy)) -> io::Result<Action> {
+        let (command, args) = match packet.chars().next() {
+            Some(command) => (command, packet.split_at(command.len_utf8()).1),
+            None => return Ok(Action::Reply("".to_string()))
+        };
+
+        let reply: String = match command {
+            '?' => Self::stop_reply(GDB_SIGNAL_TRAP),
+            'g' => Self::read_registers(gameboy.get_cpu()),
+            'G' => Self::write_registers(gameboy.get_cpu_mut(), args),
+            'p' => Self::read_register(gameboy.get_cpu(), args),
+            'P' => Self::write_register(gameboy.get_cpu_mut(), args),
+            'm' => Self::read_memory(gameboy, args),
+            'M' => Self::write_memory(gameboy, args),
+            'Z' => self.update_breakpoint(args, true),
+            'z' => self.update_breakpoint(args, false),
+            's' => {
+                if !Self::resume_at(gameboy.get_cpu_mut(), args) {
+                    return Ok(Action::Reply("E01".to_string()));
+                }
+                match gameboy.step() {
+                    Ok(()) => {
+                        on_step(gameboy);
+                        Self::stop_reply(GDB_SIGNAL_TRAP)
+                    },
+                    Err(e) => Self::cpu_error_reply(e)
+                }
+            },
+            'c' => {
+                if !Self::resume_at(gameboy.get_cpu_mut(), args) {
+                    return Ok(Action::Reply("E01".to_string()));
+                }
+                self.continue_execution(gameboy, on_step)?
+            },
+            'H' => "OK".to_string(),
+            'D' => return Ok(Action::Detach),
+            'k' => return Ok(Action::Kill),
+            'q' | 'Q' => {
+                match packet.split([':', ',']).next().unwrap() {
+                    "qSupported" => "PacketSize=1000;QStartNoAckMode+".to_string(),
+                    "qRcmd" => self.monitor_command(gameboy, &packet["qRcmd,".len().min(packet.len())..])?,
+                    "QStartNoAckMode" => return Ok(Action::EnableNoAck),
+                    "qAttached" => "1".to_string(),
+                    "qC" => "QC1".to_string(),
+                    "qfThreadInfo" => "m1".to_string(),
+                    "qsThreadInfo" => "l".to_string(),
+                    _ => "".to_string()
+                }
+            },
+            _ => {
+                debug!("GDB: Unsupported packet \"{}\"", packet);
+                "".to_string()
+            }
+        };
+
+        Ok(Action::Reply(reply))
+    }
+
+    // gdb's "monitor <command>", the output is sent as console output packets
+    fn monitor_command(&mut self, gameboy: &mut GameBoy, hex_command: &str) -> io::Result<String> {
+        let command = match Self::decode_hex_bytes(hex_command) {
+            Some(bytes) => String::from_utf8_lossy(&bytes).to_string(),
+            None => return Ok("E01".to_string())
+        };
+
+        debug!("GDB: Monitor command \"{}\"", command);
+
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let output: Vec<String> = match words.as_slice() {
+            ["backtrace"] | ["bt"] => gameboy.get_cpu().get_backtrace(),
+            ["break", target] => {
+                match SymbolTable::resolve(gameboy.get_cpu().get_symbols(), target) {
+                    Some(addr) => {
+                        self.breakpoints.insert(addr);
+                        vec![format!("Breakpoint at 0x{:04X}", addr)]
+                    },
+                    None => vec![format!("Unknown label or address \"{}\"", target)]
+                }
+            },
+            ["delete", target] => {
+                match SymbolTable::resolve(gameboy.get_cpu().get_symbols(), target) {
+                    Some(addr) if self.breakpoints.remove(&addr) => vec![format!("Deleted breakpoint at 0x{:04X}", addr)],
+                    _ => vec![format!("No breakpoint at \"{}\"", target)]
+                }
+            },
+            ["cheat", "list"] | ["cheats"] => gameboy.get_bus().get_cheats().get_lines(),
+            ["cheat", "add", code, name @ ..] => {
+                match gameboy.get_bus_mut().get_cheats_mut().add(code, &name.join(" ")) {
+                    Ok(index) => vec![format!("Added cheat #{}", index)],
+                    Err(e) => vec![e]
+                }
+            },
+            ["cheat", action @ ("enable" | "disable"), index] => {
+                let result = index.parse::<usize>()
+                    .map_err(|_| format!("Invalid cheat number \"{}\"", index))
+                    .and_then(|index| gameboy.get_bus_mut().get_cheats_mut().set_enabled(index, *action == "enable"));
+
+                match result {
+                    Ok(()) => gameboy.get_bus().get_cheats().get_lines(),
+                    Err(e) => vec![e]
+                }
+            },
+            ["search", "start"] => {
+                let ram_search = RamSearch::new(gameboy.get_bus().get_ram_memory());
+                let lines = vec![format!("Started RAM search with {} candidates", ram_search.len())];
+                self.ram_search = Some(ram_search);
+                lines
+            },
+            ["search", "list"] => match &self.ram_search {
+                Some(ram_search) => ram_search.get_lines(RAM_SEARCH_MAX_LINES),
+                None => vec!["No RAM search, use \"search start\" first".to_string()]
+            },
+            ["search", name, value @ ..] if value.len() <= 1 => {
+                match (&mut self.ram_search, SearchFilter::parse(name, value.first().copied())) {
+                    (Some(ram_search), Some(filter)) => {
+                        ram_search.filter(gameboy.get_bus().get_ram_memory(), filter);
+                        ram_search.get_lines(RAM_SEARCH_MAX_LINES)
+                    },
+                    (None, _) => vec!["No RAM search, use \"search start\" first".to_string()],
+                    (_, None) => vec![format!("Invalid search filter \"{}\"", words[1..].join(" "))]
+                }
+            },
+            _ => vec![
+                "Commands:".to_string(),
+                "    backtrace                  Show the CALLs the cpu is currently in".to_string(),
+                "    break <label|addr>         Add a breakpoint".to_string(),
+                "    delete <label|addr>        Remove a breakpoint".to_string(),
+                "    cheat list                 Show the cheats".to_string(),
+                "    cheat add <code> [name]    Add a Game Genie or GameShark code".to_string(),
+                "    cheat enable|disable <n>   Toggle a cheat".to_string(),
+                "    search start               Snapshot WRAM, HRAM and cartridge RAM".to_string(),
+                "    search eq|changed|inc|dec  Keep the addresses compared to the last snapshot".to_string(),
+                "    search value <n>           Keep the addresses holding a value".to_string(),
+                "    search list                Show the remaining addresses".to_string()
+            ]
+        };
+
+        for line in output {
+            let hex_line: String = (line + "\n").bytes().map(|b| format!("{:02x}", b)).collect();
+            self.send_packet(&format!("O{}", hex_line))?;
+        }
+
+        Ok("OK".to_string())
+    }
+
+    fn continue_execution(&mut self, gameboy: &mut GameBoy, on_step: &mut impl FnMut(&mut GameBoy)) -> io::Result<String> {
+        let mut instructions: usize = 0;
+        loop {
+            if let Err(e) = gameboy.step() {
+                return Ok(Self::cpu_error_reply(e));
+            }
+            on_step(gameboy);
+
+            if self.breakpoints.contains(&gameboy.get_cpu().get_program_counter()) {
+                debug!("GDB: Hit breakpoint at 0x{:04X}", gameboy.get_cpu().get_program_counter());
+                return Ok(Self::stop_reply(GDB_SIGNAL_TRAP));
+            }
+
+            instructions += 1;
+            if instructions.is_multiple_of(GDB_INTERRUPT_POLL_INTERVAL) && self.interrupt_requested()? {
+                debug!("GDB: Interrupted at 0x{:04X}", gameboy.get_cpu().get_program_counter());
+                return Ok(Self::stop_reply(GDB_SIGNAL_INT));
+            }
+        }
+    }
+
+    // "s" and "c" can optionally contain the address to resume from, false if it isn't a valid one
+    fn resume_at(cpu: &mut CPU, args: &str) -> bool {
+        if args.is_empty() {
+            return true;
+        }
+
+        match Self::parse_hex(args).and_then(|addr| u16::try_from(addr).ok()) {
+            Some(addr) => {
+                cpu.set_program_counter(addr);
+                true
+            },
+            None => false
+        }
+    }
+
+    fn stop_reply(signal: u8) -> String {
+        format!("S{:02x}", signal)
+    }
+
+    // The cpu can't go on, stop like the program got an illegal instruction so the debugger can look around
+    fn cpu_error_reply(error: CpuError) -> String {
+        error!("GDB: {}", error);
+        Self::stop_reply(GDB_SIGNAL_ILL)
+    }
+
+    // Registers stuff
+    fn get_gdb_register(cpu: &CPU, index: usize) -> u16 {
+        cpu.get_double_register(GDB_REGISTERS[index])
+    }
+
+    fn set_gdb_register(cpu: &mut CPU, index: usize, value: u16) {
+        cpu.set_double_register(GDB_REGISTERS[index], value)
+    }
+
+    fn read_registers(cpu: &CPU) -> String {
+        let mut reply: String = "".to_string();
+        for index in 0..GDB_REGISTERS.len() {
+            reply += &Self::encode_register(Self::get_gdb_register(cpu, index));
+        }
+
+        return reply;
+    }
+
+    fn write_registers(cpu: &mut CPU, args: &str) -> String {
+        if args.len() < GDB_REGISTERS.len() * 4 || !args.is_ascii() {
+            return "E01".to_string();
+        }
+
+        for index in 0..GDB_REGISTERS.len() {
+            match Self::decode_register(&args[index * 4..index * 4 + 4]) {
+                Some(value) => Self::set_gdb_register(cpu, index, value),
+                None => return "E01".to_string()
+            }
+        }
+
+        return "OK".to_string();
+    }
+
+    fn read_register(cpu: &CPU, args: &str) -> String {
+        match Self::parse_hex(args) {
+            Some(index) if (index as usize) < GDB_REGISTERS.len() => {
+                Self::encode_register(Self::get_gdb_register(cpu, index as usize))
+            },
+            _ => "E01".to_string()
+        }
+    }
+
+    fn write_register(cpu: &mut CPU, args: &str) -> String {
+        let (index, value) = match args.split_once('=') {
+            Some((index, value)) => (Self::parse_hex(index), Self::decode_register(value)),
+            None => return "E01".to_string()
+        };
+
+        match (index, value) {
+            (Some(index), Some(value)) if (index as usize) < GDB_REGISTERS.len() => {
+                Self::set_gdb_register(cpu, index as usize, value);
+                "OK".to_string()
+            },
+            _ => "E01".to_string()
+        }
+    }
+
+    // Registers are sent in target byte order (little endian)
+    fn encode_register(value: u16) -> String {
+        format!("{:02x}{:02x}", value & 0xff, value >> 8)
+    }
+
+    fn decode_register(data: &str) -> Option<u16> {
+        let bytes = Self::decode_hex_bytes(data)?;
+        if bytes.len() != 2 {
+            return None;
+        }
+
+        Some(bytes[0] as u16 | (bytes[1] as u16) << 8)
+    }
+
+    // Memory stuff
+    fn read_memory(gameboy: &GameBoy, args: &str) -> String {
+        let (addr, length) = match Self::parse_addr_length(args) {
+            Some(value) => value,
+            None => return "E01".to_string()
+        };
+
+        let mut reply: String = "".to_string();
+        for offset in 0..length {
+            reply += &format!("{:02x}", gameboy.read_memory(addr.wrapping_add(offset as u16)));
+        }
+
+        return reply;
+    }
+
+    fn write_memory(gameboy: &mut GameBoy, args: &str) -> String {
+        let (location, data) = match args.split_once(':') {
+            Some(value) => value,
+            None => return "E01".to_string()
+        };
+
+        let (addr, length) = match Self::parse_addr_length(location) {
+            Some(value) => value,
+            None => return "E01".to_string()
+        };
+
+        let bytes = match Self::decode_hex_bytes(data) {
+            Some(bytes) if bytes.len() == length => bytes,
+            _ => return "E01".to_string()
+        };
+
+        for (offset, value) in bytes.iter().enumerate() {
+            gameboy.write_memory(addr.wrapping_add(offset as u16), *value);
+        }
+
+        return "OK".to_string();
+    }
+
+    // Breakpoints stuff
+    fn update_breakpoint(&mut self, args: &str, insert: bool) -> String {
+        // Format is "type,addr,kind", software and hardware breakpoints are handled the same way
+        let mut parts = args.split(',');
+        let breakpoint_type = parts.next();
+        let addr = parts.next().and_then(Self::parse_hex);
+
+        match (breakpoint_type, addr) {
+            (Some("0") | Some("1"), Some(addr)) => {
+                if insert {
+                    debug!("GDB: Adding breakpoint at 0x{:04X}", addr);
+                    self.breakpoints.insert(addr as u16);
+                } else {
+                    debug!("GDB: Removing breakpoint at 0x{:04X}", addr);
+                    self.breakpoints.remove(&(addr as u16));
+                }
+                "OK".to_string()
+            },
+            (Some(_), Some(_)) => "".to_string(), // Watchpoints are not supported
+            _ => "E01".to_string()
+        }
+    }
+
+    // Parsing stuff
+    fn parse_hex(value: &str) -> Option<u32> {
+        u32::from_str_radix(value, 16).ok()
+    }
+
+    // Neither can go past the 64 KiB address space
+    fn parse_addr_length(args: &str) -> Option<(u16, usize)> {
+        let (addr, length) = args.split_once(',')?;
+        let addr = u16::try_from(Self::parse_hex(addr)?).ok()?;
+        let length = Self::parse_hex(length)? as usize;
+        if length > GDB_MAX_MEMORY_LENGTH {
+            return None;
+        }
+
+        Some((addr, length))
+    }
+
+    fn decode_hex_bytes(data: &str) -> Option<Vec<u8>> {
+        if !data.len().is_multiple_of(2) || !data.is_ascii() {
+            return None;
+        }
+
+        (0..data.len()).step_by(2).map(|i| u8::from_str_radix(&data[i..i + 2], 16).ok()).collect()
+    }
+
+    fn checksum(data: &str) -> u8 {
+        data.bytes().fold(0, |sum, b| sum.wrapping_add(b))
+    }
+
+    // Connection stuff
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte: [u8; 1] = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0]))
+        }
+    }
+
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // Skip everything until the start of a packet (acks, ctrl-c while already stopped)
+            match self.read_byte()? {
+                Some(b'$') => (),
+                Some(_) => continue,
+                None => return Ok(None)
+            }
+
+            let mut data: Vec<u8> = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                    None => return Ok(None)
+                }
+            }
+
+            let mut checksum: [u8; 2] = [0; 2];
+            self.stream.read_exact(&mut checksum)?;
+
+            let data = String::from_utf8_lossy(&data).to_string();
+            let expected_checksum = std::str::from_utf8(&checksum).ok().and_then(|c| u8::from_str_radix(c, 16).ok());
+
+            if expected_checksum != Some(Self::checksum(&data)) {
+                warn!("GDB: Bad checksum for packet \"{}\"", data);
+                if !self.no_ack_mode {
+                    self.stream.write_all(b"-")?;
+                }
+                continue;
+            }
+
+            if !self.no_ack_mode {
+                self.stream.write_all(b"+")?;
+            }
+
+            return Ok(Some(data));
+        }
+    }
+
+    fn send_packet(&mut self, data: &str) -> io::Result<()> {
+        trace!("GDB: -> {}", data);
+        let packet = format!("${}#{:02x}", data, Self::checksum(data));
+
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+
+            if self.no_ack_mode {
+                return Ok(());
+            }
+
+            // Resend the packet until the debugger acknowledges it
+            match self.read_byte()? {
+                Some(b'+') => return Ok(()),
+                Some(_) => continue,
+                None => return Err(io::Error::new(ErrorKind::UnexpectedEof, "GDB: Connection closed"))
+            }
+        }
+    }
+
+    fn interrupt_requested(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let result = self.read_byte();
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(Some(b)) => Ok(b == GDB_INTERRUPT_BYTE),
+            Ok(None) => Ok(true), // Connection closed, stop and let the serve loop notice
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e)
+        }
+    }
+}
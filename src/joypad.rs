@@ -0,0 +1,118 @@
+use crate::consts::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start
+}
+
+impl Button {
+    pub fn from_name(name: &str) -> Option<Button> {
+        match name.to_lowercase().as_str() {
+            "right" => Some(Button::Right),
+            "left" => Some(Button::Left),
+            "up" => Some(Button::Up),
+            "down" => Some(Button::Down),
+            "a" => Some(Button::A),
+            "b" => Some(Button::B),
+            "select" => Some(Button::Select),
+            "start" => Some(Button::Start),
+            _ => None
+        }
+    }
+
+    // Directions are the low nibble and actions the high one, in the order P1 reports them
+    fn get_mask(&self) -> u8 {
+        match self {
+            Button::Right => 0x01,
+            Button::Left => 0x02,
+            Button::Up => 0x04,
+            Button::Down => 0x08,
+            Button::A => 0x10,
+            Button::B => 0x20,
+            Button::Select => 0x40,
+            Button::Start => 0x80
+        }
+    }
+}
+
+// A button press or release at the start of a frame, for running roms with scripted input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: usize,
+    pub button: Button,
+    pub pressed: bool
+}
+
+impl InputEvent {
+    // "<frame>:<button>:<press|release>", like "120:start:press"
+    pub fn parse(value: &str) -> Result<InputEvent, String> {
+        let parts: Vec<&str> = value.split(':').collect();
+        if parts.len() != 3 {
+            return Err(format!("Invalid input \"{}\", expected <frame>:<button>:<press|release>", value));
+        }
+
+        let frame: usize = parts[0].parse().map_err(|e| format!("Invalid input frame \"{}\" ({})", parts[0], e))?;
+        let button: Button = Button::from_name(parts[1]).ok_or(format!("Unknown button \"{}\"", parts[1]))?;
+        let pressed: bool = match parts[2].to_lowercase().as_str() {
+            "press" => true,
+            "release" => false,
+            action => return Err(format!("Unknown input action \"{}\"", action))
+        };
+
+        return Ok(InputEvent {
+            frame,
+            button,
+            pressed
+        });
+    }
+}
+
+// State of the buttons as seen through P1 (0xFF00)
+#[derive(Default)]
+pub struct Joypad {
+    pressed: u8
+}
+
+impl Joypad {
+    pub fn new() -> Joypad {
+        Joypad { pressed: 0 }
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            self.pressed |= button.get_mask();
+        } else {
+            self.pressed &= !button.get_mask();
+        }
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & button.get_mask() != 0
+    }
+
+    pub fn any_pressed(&self) -> bool {
+        self.pressed != 0
+    }
+
+    // The program selects the directions and/or the actions (active low) and reads the buttons of
+    // the selected groups in the low nibble, 0 meaning pressed
+    pub fn read(&self, select: u8) -> u8 {
+        let mut buttons: u8 = 0x0F;
+
+        if !bit_check(select, JOYPAD_BIT_SELECT_DIRECTIONS) {
+            buttons &= !(self.pressed & 0x0F);
+        }
+        if !bit_check(select, JOYPAD_BIT_SELECT_ACTIONS) {
+            buttons &= !(self.pressed >> 4);
+        }
+
+        return JOYPAD_UNUSED_BITS | (select & JOYPAD_SELECT_MASK) | buttons;
+    }
+}
@@ -0,0 +1,33 @@
+// Explicit returns are how this codebase is written, the rest of clippy's defaults are enforced
+#![allow(clippy::needless_return)]
+
+#[macro_use] extern crate log;
+
+pub mod consts;
+pub mod ram_memory;
+pub mod rom_parser;
+pub mod bus;
+pub mod cpu;
+pub mod ppu;
+pub mod gdb_stub;
+pub mod disassembler;
+pub mod symbols;
+pub mod model;
+pub mod rom_info;
+pub mod rom_loader;
+pub mod patch;
+pub mod cheats;
+pub mod ram_search;
+pub mod joypad;
+pub mod registers;
+pub mod gameboy;
+pub mod screenshot;
+mod opcodes;
+mod param;
+mod tests;
+
+pub use gameboy::GameBoy;
+pub use joypad::{Button, InputEvent};
+pub use model::Model;
+pub use registers::{Reg8, Reg16, Flag};
+pub use rom_parser::Rom;
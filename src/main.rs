@@ -1,28 +1,42 @@
+// The binary keeps the explicit returns of the library
+#![allow(clippy::needless_return)]
+
 #[macro_use] extern crate log;
 extern crate simplelog;
 
-use std::rc::Rc;
-use std::cell::RefCell;
-use std::{io::Read};
 use std::fs::File;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
 use simplelog::*;
 use clap::{Command, Arg, ArgAction};
+use minifb::{Window, WindowOptions, Scale, Key, KeyRepeat};
 
-mod ram_memory;
-mod rom_parser;
-mod consts;
-mod param;
-mod tests;
-mod cpu;
-mod opcodes;
-mod ppu;
+use gbemulator::consts::*;
+use gbemulator::{GameBoy, Button, InputEvent, Model, Rom};
+use gbemulator::gdb_stub::GdbStub;
+use gbemulator::disassembler::Disassembler;
+use gbemulator::symbols::SymbolTable;
+use gbemulator::rom_info::RomInfo;
+use gbemulator::cheats::Cheats;
+use gbemulator::{rom_loader, patch};
 
-use consts::*;
-use rom_parser::Rom;
-use ram_memory::RamMemory;
-use cpu::CPU;
+const KEY_BINDINGS: [(Key, Button); 8] = [
+    (Key::Right, Button::Right),
+    (Key::Left, Button::Left),
+    (Key::Up, Button::Up),
+    (Key::Down, Button::Down),
+    (Key::X, Button::A),
+    (Key::Z, Button::B),
+    (Key::Backspace, Button::Select),
+    (Key::Enter, Button::Start)
+];
 
-use crate::{ppu::PPU, consts::DMG_BOOT_ROM};
+// Keys the screenshot hotkey can be bound to
+const FUNCTION_KEYS: [(&str, Key); 12] = [
+    ("F1", Key::F1), ("F2", Key::F2), ("F3", Key::F3), ("F4", Key::F4),
+    ("F5", Key::F5), ("F6", Key::F6), ("F7", Key::F7), ("F8", Key::F8),
+    ("F9", Key::F9), ("F10", Key::F10), ("F11", Key::F11), ("F12", Key::F12)
+];
 
 fn main() {
     let args = Command::new("gbemulator")
@@ -30,6 +44,21 @@ fn main() {
         .short('f')
         .long("rom-file")
         .required(true))
+    .arg(Arg::new("rom_entry")
+        .long("entry")
+        .help("File to load from a zip archive, defaults to the first .gb/.gbc file"))
+    .arg(Arg::new("patch")
+        .short('p')
+        .long("patch")
+        .action(ArgAction::Append)
+        .help("IPS/UPS/BPS patch to apply, can be given more than once. Defaults to a patch named like the rom"))
+    .arg(Arg::new("cheats_file")
+        .long("cheats")
+        .help("Cheats file (one Game Genie/GameShark code per line), defaults to the rom path with a .cht extension"))
+    .arg(Arg::new("cheat")
+        .long("cheat")
+        .action(ArgAction::Append)
+        .help("Game Genie or GameShark code to enable, can be given more than once"))
     .arg(Arg::new("verbose")
         .short('v')
         .long("verbose")
@@ -37,15 +66,106 @@ fn main() {
     .arg(Arg::new("boot_rom")
         .short('b')
         .long("boot-rom")
-        .action(ArgAction::SetTrue))
+        .num_args(0..=1)
+        .default_missing_value(BOOT_ROM_BUILTIN_NAME)
+        .help("Run a boot rom first, either a DMG/MGB/SGB/CGB boot rom file or the built in DMG one when no file is given"))
+    .arg(Arg::new("model")
+        .short('m')
+        .long("model")
+        .value_parser(["dmg", "mgb", "cgb"])
+        .help("Hardware to emulate, defaults to cgb for color games and dmg for the rest"))
+    .arg(Arg::new("gdb_port")
+        .short('g')
+        .long("gdb")
+        .value_parser(clap::value_parser!(u16)))
+    .arg(Arg::new("sym_file")
+        .long("sym")
+        .help("RGBDS symbol file, defaults to the rom path with a .sym extension"))
+    .arg(Arg::new("trace_log")
+        .long("trace-log")
+        .help("Write the cpu state before every instruction to a file (Gameboy Doctor format)"))
+    .arg(Arg::new("screenshot_key")
+        .long("screenshot-key")
+        .value_parser(parse_key)
+        .default_value(SCREENSHOT_DEFAULT_KEY)
+        .help("Key (F1-F12) that saves a png of the screen"))
+    .arg(Arg::new("screenshot_dir")
+        .long("screenshot-dir")
+        .default_value(".")
+        .help("Directory screenshots are saved to, with timestamped names"))
+    .arg(Arg::new("screenshot_scale")
+        .long("screenshot-scale")
+        .value_parser(clap::value_parser!(u8).range(1..=SCREENSHOT_MAX_SCALE as i64))
+        .default_value("1")
+        .help("Integer factor screenshots are scaled up by"))
     // .arg(Arg::new("ppu_logs_only")
     //     .long("ppu-logs-only")
     //     .action(ArgAction::SetTrue))
     // .arg(Arg::new("cpu_logs_only")
     //     .long("cpu-logs-only")
     //     .action(ArgAction::SetTrue))
+    .subcommand(Command::new("disasm")
+        .about("Linearly disassemble a rom")
+        .arg(Arg::new("rom_file")
+            .required(true))
+        .arg(Arg::new("start")
+            .long("start")
+            .value_parser(parse_addr)
+            .default_value("0x0000"))
+        .arg(Arg::new("end")
+            .long("end")
+            .value_parser(parse_addr)
+            .default_value("0x7FFF"))
+        .arg(Arg::new("sym_file")
+            .long("sym"))
+        .arg(Arg::new("rom_entry")
+            .long("entry")))
+    .subcommand(Command::new("info")
+        .about("Print the decoded cartridge header of a rom")
+        .arg(Arg::new("rom_file")
+            .required(true))
+        .arg(Arg::new("json")
+            .long("json")
+            .action(ArgAction::SetTrue))
+        .arg(Arg::new("rom_entry")
+            .long("entry")))
+    .subcommand(Command::new("run")
+        .about("Run a rom without a window for a number of frames and print the hash of the last LCD frame")
+        .arg(Arg::new("rom_file")
+            .required(true))
+        .arg(Arg::new("frames")
+            .long("frames")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("60"))
+        .arg(Arg::new("screenshot")
+            .long("screenshot")
+            .help("Save the last LCD frame (160x144) as a png"))
+        .arg(Arg::new("input")
+            .long("input")
+            .action(ArgAction::Append)
+            .value_parser(InputEvent::parse)
+            .help("Button to press or release before a frame (<frame>:<button>:<press|release>), can be given more than once"))
+        .arg(Arg::new("rom_entry")
+            .long("entry")))
+    .subcommand_negates_reqs(true)
     .get_matches();
 
+    match args.subcommand() {
+        Some(("disasm", disasm_args)) => {
+            disasm(disasm_args);
+            return;
+        },
+        Some(("info", info_args)) => {
+            rom_info(info_args);
+            return;
+        },
+        Some(("run", run_args)) => {
+            run_headless(run_args);
+            return;
+        },
+        _ => ()
+    }
+
     let log_level: LevelFilter = match args.get_count("verbose") {
         1 => LevelFilter::Debug,
         2 => LevelFilter::Trace,
@@ -80,47 +200,280 @@ fn main() {
     // Parse rom file
     let rom_file_path: &String = args.get_one("rom_file").expect("Failed getting rom_file_path");
     debug!("Loading rom from \"{}\"", rom_file_path);
-    let rom_file: File = File::open(rom_file_path).expect("Failed opening rom file");
+    let rom_content: Vec<u8> = apply_patches(&args, rom_file_path, read_rom_file(&args, rom_file_path));
 
-    let rom_content: Vec<u8> = rom_file.bytes().map(|value| {
-        value.expect("Failed reading rom file")
-    }).collect();
-
-    let rom: Rom = Rom::create_from_bytes(rom_content);
+    let rom: Rom = match Rom::parse(rom_content).and_then(|rom| rom.verify_cartridge_type().map(|_| rom)) {
+        Ok(rom) => rom,
+        Err(e) => {
+            error!("Failed loading rom \"{}\" ({})", rom_file_path, e);
+            std::process::exit(1);
+        }
+    };
     info!("Loading rom \"{}\"", rom.title);
 
-    let orig_ram_memory = RamMemory::init_from_rom(&rom);
-    let ram_memory_ref: Rc<RefCell<RamMemory>> = Rc::new(RefCell::new(orig_ram_memory));
-
-    
-    let orig_ppu: PPU = PPU::init(ram_memory_ref.clone());
-    let ppu_ref: Rc<RefCell<PPU>> = Rc::new(RefCell::new(orig_ppu));
-    
-    let mut cpu: CPU = CPU::init_with_ram_ppu(ram_memory_ref.clone(), ppu_ref.clone(), args.get_flag("boot_rom"));
-    
-    // Init boot rom
-    if args.get_flag("boot_rom") {
-        for (i,x) in DMG_BOOT_ROM.iter().enumerate() {
-            ram_memory_ref.borrow_mut().set_addr(i as u16, *x);
+    // Only the boot rom checks the header, so a bad checksum is only worth a warning
+    if let Err(e) = rom.verify_checksums() {
+        warn!("{}", e);
+    }
+
+    let model: Model = match args.get_one::<String>("model") {
+        Some(model_name) => Model::from_name(model_name).expect("Unknown model"),
+        None => Model::detect(rom.cgb_flag)
+    };
+    info!("Emulating {:?}", model);
+
+    // Boot rom is mapped over the cartridge until the program writes to 0xFF50
+    let boot_rom: Option<Vec<u8>> = args.get_one::<String>("boot_rom").map(|boot_rom_path| {
+        if boot_rom_path == BOOT_ROM_BUILTIN_NAME {
+            DMG_BOOT_ROM.to_vec()
+        } else {
+            std::fs::read(boot_rom_path).expect("Failed reading boot rom file")
         }
+    });
+
+    let mut gameboy: GameBoy = GameBoy::init(&rom, model, boot_rom).expect("Failed loading boot rom");
+
+    if let Some(symbols) = load_symbols(&args, rom_file_path) {
+        gameboy.get_cpu_mut().set_symbols(symbols);
     }
 
-    loop {
-        // Only run boot rom for now
-        if args.get_flag("boot_rom") {
-            if cpu.get_program_counter() == 0x0100 {
-                panic!("No more boot rom");
+    gameboy.get_bus_mut().set_cheats(load_cheats(&args, rom_file_path));
+
+    if let Some(trace_log_path) = args.get_one::<String>("trace_log") {
+        gameboy.enable_trace_log(trace_log_path).expect("Failed creating trace log");
+    }
+
+    let mut window: Window = create_window();
+
+    // Let a debugger drive the emulator until it detaches
+    if let Some(gdb_port) = args.get_one::<u16>("gdb_port") {
+        let listener = TcpListener::bind(("127.0.0.1", *gdb_port)).expect("Failed binding gdb port");
+        info!("Waiting for gdb connection on 127.0.0.1:{}", gdb_port);
+
+        let mut gdb_stub = GdbStub::accept(&listener).expect("Failed accepting gdb connection");
+        let mut instructions: usize = 0;
+        gdb_stub.serve(&mut gameboy, |gameboy| {
+            instructions += 1;
+            if instructions.is_multiple_of(4) {
+                gameboy.render();
+                update_window(&mut window, gameboy);
             }
+        }).expect("GDB connection failed");
+    }
+
+    let screenshot_key: Key = *args.get_one("screenshot_key").unwrap();
+    let screenshot_dir: &String = args.get_one("screenshot_dir").unwrap();
+    let screenshot_scale: u8 = *args.get_one("screenshot_scale").unwrap();
+
+    // After a cpu error keep showing the last frame, with the error in the title
+    let mut crashed: bool = false;
+    while window.is_open() {
+        let keys: Vec<Key> = window.get_keys();
+        for (key, button) in KEY_BINDINGS {
+            gameboy.set_button(button, keys.contains(&key));
         }
 
-        // Execute a single cpu instruction
-        cpu.execute_instruction();
-        cpu.execute_instruction();
-        cpu.execute_instruction();
-        cpu.execute_instruction();
+        if window.is_key_pressed(screenshot_key, KeyRepeat::No) {
+            match gameboy.save_screenshot(Path::new(screenshot_dir), screenshot_scale as usize) {
+                Ok(path) => info!("Saved screenshot to \"{}\"", path.display()),
+                Err(e) => error!("Failed saving screenshot ({})", e)
+            }
+        }
 
-        // Render screen (if needed)
-        ppu_ref.borrow_mut().render();
+        if !crashed {
+            if let Err(e) = gameboy.run_frame() {
+                error!("{}", e);
+                window.set_title(&format!("GBEmulator - {}", e));
+                crashed = true;
+            }
+        }
+        update_window(&mut window, &gameboy);
     }
 }
 
+fn create_window() -> Window {
+    // Configure scale
+    let window_options: WindowOptions = WindowOptions {
+        scale: Scale::X2,
+        ..WindowOptions::default()
+    };
+
+    let mut window = Window::new(
+        "GBEmulator",
+        LCD_WIDTH,
+        LCD_HEIGHT,
+        window_options,
+    ).unwrap_or_else(|e| {
+        panic!("Failed creating minifb window ({})", e);
+    });
+
+    // Limit FPS to about 60FPS, we render once per frame
+    window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
+
+    return window;
+}
+
+fn update_window(window: &mut Window, gameboy: &GameBoy) {
+    window.update_with_buffer(&gameboy.get_framebuffer(), LCD_WIDTH, LCD_HEIGHT).unwrap_or_else(|e| {
+        panic!("Failed rendering window due to error ({})", e);
+    });
+}
+
+fn disasm(args: &clap::ArgMatches) {
+    let rom_file_path: &String = args.get_one("rom_file").expect("Failed getting rom_file_path");
+    let rom_content: Vec<u8> = read_rom_file(args, rom_file_path);
+
+    if rom_content.is_empty() {
+        return;
+    }
+
+    let start: u16 = *args.get_one("start").unwrap();
+    let end: u16 = u16::min(*args.get_one("end").unwrap(), (rom_content.len() - 1).min(0xFFFF) as u16);
+
+    let mut disassembler = Disassembler::new();
+    if let Some(symbols) = load_symbols(args, rom_file_path) {
+        disassembler.set_symbols(symbols);
+    }
+
+    for instruction in disassembler.disassemble_rom(&rom_content, start, end) {
+        if let Some(label) = &instruction.label {
+            println!("{}:", label);
+        }
+        println!("{}", instruction.get_line());
+    }
+}
+
+fn rom_info(args: &clap::ArgMatches) {
+    let rom_file_path: &String = args.get_one("rom_file").expect("Failed getting rom_file_path");
+    let rom_content: Vec<u8> = read_rom_file(args, rom_file_path);
+
+    let rom: Rom = match Rom::parse(rom_content) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Failed parsing rom \"{}\" ({})", rom_file_path, e);
+            std::process::exit(1);
+        }
+    };
+
+    let info = RomInfo::from_rom(&rom);
+    if args.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&info.to_json()).unwrap());
+    } else {
+        for line in info.get_lines() {
+            println!("{}", line);
+        }
+    }
+}
+
+fn run_headless(args: &clap::ArgMatches) {
+    let rom_file_path: &String = args.get_one("rom_file").expect("Failed getting rom_file_path");
+    let frames: usize = *args.get_one("frames").unwrap();
+    let inputs: Vec<InputEvent> = args.get_many::<InputEvent>("input").into_iter().flatten().copied().collect();
+
+    let mut gameboy: GameBoy = match GameBoy::load(read_rom_file(args, rom_file_path)) {
+        Ok(gameboy) => gameboy,
+        Err(e) => {
+            eprintln!("Failed loading rom \"{}\" ({})", rom_file_path, e);
+            std::process::exit(1);
+        }
+    };
+
+    // The screen as it was when the cpu failed is still worth saving
+    let result = gameboy.run_frames(frames, &inputs);
+    let screenshot = gameboy.get_screenshot();
+    println!("{}", screenshot.get_hash());
+
+    if let Some(screenshot_path) = args.get_one::<String>("screenshot") {
+        if let Err(e) = screenshot.save_png(Path::new(screenshot_path)) {
+            eprintln!("Failed saving screenshot \"{}\" ({})", screenshot_path, e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+// Roms can be plain files or zip/gzip archives
+fn read_rom_file(args: &clap::ArgMatches, rom_file_path: &str) -> Vec<u8> {
+    let entry_name: Option<&String> = args.get_one("rom_entry");
+
+    match rom_loader::load_rom_file(Path::new(rom_file_path), entry_name.map(|name| name.as_str())) {
+        Ok(rom_content) => rom_content,
+        Err(e) => {
+            eprintln!("Failed reading rom file \"{}\" ({})", rom_file_path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Apply the patches from the arguments in order, or the one next to the rom if there is one
+fn apply_patches(args: &clap::ArgMatches, rom_file_path: &str, mut rom_content: Vec<u8>) -> Vec<u8> {
+    let patch_paths: Vec<PathBuf> = match args.get_many::<String>("patch") {
+        Some(paths) => paths.map(PathBuf::from).collect(),
+        None => patch::find_patch_file(Path::new(rom_file_path)).into_iter().collect()
+    };
+
+    for patch_path in patch_paths {
+        let patch_content = std::fs::read(&patch_path).unwrap_or_else(|e| {
+            error!("Failed reading patch \"{}\" ({})", patch_path.display(), e);
+            std::process::exit(1);
+        });
+
+        rom_content = match patch::apply_patch(&rom_content, &patch_content) {
+            Ok(patched) => patched,
+            Err(e) => {
+                error!("Failed applying patch \"{}\" ({})", patch_path.display(), e);
+                std::process::exit(1);
+            }
+        };
+        info!("Applied patch \"{}\"", patch_path.display());
+    }
+
+    return rom_content;
+}
+
+// Cheats file from the arguments (or next to the rom) and codes given with --cheat
+fn load_cheats(args: &clap::ArgMatches, rom_file_path: &str) -> Cheats {
+    let cheats_file_path = match args.get_one::<String>("cheats_file") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Cheats::find_cheats_file(Path::new(rom_file_path))
+    };
+
+    let mut cheats = match cheats_file_path {
+        Some(path) => Cheats::load(&path).expect("Failed reading cheats file"),
+        None => Cheats::new()
+    };
+
+    for code in args.get_many::<String>("cheat").into_iter().flatten() {
+        if let Err(e) = cheats.add(code, "") {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    return cheats;
+}
+
+// Use the symbol file from the arguments, or the one next to the rom if there is one
+fn load_symbols(args: &clap::ArgMatches, rom_file_path: &str) -> Option<SymbolTable> {
+    let sym_file_path = match args.get_one::<String>("sym_file") {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => SymbolTable::find_sym_file(Path::new(rom_file_path))?
+    };
+
+    Some(SymbolTable::load(&sym_file_path).expect("Failed reading symbol file"))
+}
+
+fn parse_key(value: &str) -> Result<Key, String> {
+    FUNCTION_KEYS.iter().find(|(name, _)| name.eq_ignore_ascii_case(value)).map(|(_, key)| *key)
+        .ok_or(format!("Unknown key \"{}\", expected F1-F12", value))
+}
+
+// Addresses are given in hex, with or without the 0x prefix
+fn parse_addr(value: &str) -> Result<u16, String> {
+    let value = value.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(value, 16).map_err(|e| format!("Invalid address \"{}\" ({})", value, e))
+}
@@ -0,0 +1,71 @@
+use crate::consts::*;
+
+// Hardware revision being emulated, this affects the state the boot rom leaves behind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    DMG, // Original Game Boy
+    MGB, // Game Boy Pocket
+    CGB  // Game Boy Color
+}
+
+impl Model {
+    pub fn from_name(name: &str) -> Option<Model> {
+        match name.to_lowercase().as_str() {
+            "dmg" => Some(Model::DMG),
+            "mgb" => Some(Model::MGB),
+            "cgb" => Some(Model::CGB),
+            _ => None
+        }
+    }
+
+    // Color games run on a CGB unless told otherwise
+    pub fn detect(cgb_flag: u8) -> Model {
+        if cgb_flag & CGB_FLAG_SUPPORTED != 0 {
+            return Model::CGB;
+        }
+        return Model::DMG;
+    }
+
+    // CGB features (banking, color palletes, double speed) are only enabled for games that support them
+    pub fn is_cgb_mode(&self, cgb_flag: u8) -> bool {
+        *self == Model::CGB && cgb_flag & CGB_FLAG_SUPPORTED != 0
+    }
+
+    // Registers (A, F, B, C, D, E, H, L) at 0x0100 after the boot rom finished.
+    // header_checksum_zero and cgb_game come from the cartridge header, the boot rom sets some registers by them
+    pub fn get_post_boot_registers(&self, header_checksum_zero: bool, cgb_game: bool) -> [u8; 8] {
+        // H and C are only set when the header checksum isn't 0
+        let dmg_flags: u8 = if header_checksum_zero { 0x80 } else { 0xB0 };
+
+        match self {
+            Model::DMG => [0x01, dmg_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::MGB => [0xFF, dmg_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::CGB => {
+                if cgb_game {
+                    [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D]
+                } else { // Running a DMG game in compatibility mode
+                    [0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C]
+                }
+            }
+        }
+    }
+
+    // IO registers after the boot rom finished, registers with an unknown value are left alone.
+    // A CGB running a DMG game locks the CGB registers, so they're only there in cgb mode
+    pub fn get_post_boot_io_registers(&self, cgb_mode: bool) -> Vec<(u16, u8)> {
+        let mut io_registers: Vec<(u16, u8)> = POST_BOOT_IO_REGISTERS.to_vec();
+
+        match self {
+            Model::DMG | Model::MGB => {
+                io_registers.extend_from_slice(&POST_BOOT_IO_REGISTERS_DMG);
+            },
+            Model::CGB => {
+                if cgb_mode {
+                    io_registers.extend_from_slice(&POST_BOOT_IO_REGISTERS_CGB);
+                }
+            }
+        }
+
+        return io_registers;
+    }
+}
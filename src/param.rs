@@ -13,7 +13,51 @@ pub enum MemValue {
 pub struct Param {
     name: String,
     value: MemValue,
-    json_value: Value
+    json_value: Value,
+    jump_target: Option<u16>
+}
+
+// Parse the operands of the instruction at addr, immediate values are read using read_addr
+pub fn parse_params(opcode_data: &Value, addr: u16, read_addr: impl Fn(u16) -> u8) -> Vec<Param> {
+    if !opcode_data["operands"].is_array() {
+        panic!("Operands value is not array");
+    }
+
+    let mut return_value = Vec::<Param>::new();
+
+    for operand in opcode_data["operands"].as_array().unwrap() {
+        let mut param = Param::new(operand.clone());
+
+        let value: MemValue = match param.get_name().as_str() {
+            "a16" | "d16" => {
+                let mut value: u16 = read_addr(addr.wrapping_add(1)) as u16;
+                value += (read_addr(addr.wrapping_add(2)) as u16) << 8;
+                MemValue::Double(value)
+            },
+            "d8" | "a8" => {
+                MemValue::Byte(read_addr(addr.wrapping_add(1)))
+            },
+            "r8" => {
+                let offset = read_addr(addr.wrapping_add(1)) as i8;
+
+                // Relative jumps are relative to the address of the next instruction
+                if opcode_data["mnemonic"] == "JR" {
+                    let next_addr = addr.wrapping_add(opcode_data["bytes"].as_u64().unwrap() as u16);
+                    param.jump_target = Some(next_addr.wrapping_add_signed(offset as i16));
+                }
+
+                MemValue::SignedByte(offset)
+            },
+            _ => {
+                MemValue::Name(param.get_name())
+            }
+        };
+
+        param.set_param_value(value);
+        return_value.push(param);
+    }
+
+    return_value
 }
 
 impl Param {
@@ -21,7 +65,8 @@ impl Param {
         Param {
             name: json_value["name"].as_str().unwrap().to_string(),
             value: MemValue::Null,
-            json_value: json_value
+            json_value,
+            jump_target: None
         }
     }
 
@@ -40,25 +85,11 @@ impl Param {
     // }
     
     pub fn is_decrement(&self) -> bool {
-        match self.json_value["decrement"].as_bool() {
-            Some(value) => {
-                value
-            },
-            None => {
-                false
-            }
-        }
+        self.json_value["decrement"].as_bool().unwrap_or(false)
     }
 
     pub fn is_increment(&self) -> bool {
-        match self.json_value["increment"].as_bool() {
-            Some(value) => {
-                value
-            },
-            None => {
-                false
-            }
-        }
+        self.json_value["increment"].as_bool().unwrap_or(false)
     }
 
     pub fn set_param_value(&mut self, value: MemValue) {
@@ -73,20 +104,29 @@ impl Param {
         self.value.clone()
     }
 
+    pub fn get_jump_target(&self) -> Option<u16> {
+        self.jump_target
+    }
+
     pub fn get_printable(&self) -> String {
         let mut param_text = match &self.value {
             MemValue::Byte(value) => {
                 format!("0x{:02X}", value)
             },
             MemValue::SignedByte(value) => {
-                //TODO : Make this really signed (currently show 0xFB and not negative something)
-                format!("0x{:02X}", value)
+                let sign = if *value < 0 { "-" } else { "+" };
+                let offset = format!("{}0x{:02X}", sign, (*value as i16).abs());
+
+                match self.jump_target {
+                    Some(target) => format!("0x{:04X} ({})", target, offset),
+                    None => offset
+                }
             },
             MemValue::Double(value) => {
                 format!("0x{:04X}", value)
             },
             MemValue::Name(value) => {
-                format!("{}", value)
+                value.to_string()
             },
             _ => panic!("Failed getting printable string for this param")
         };
@@ -94,9 +134,9 @@ impl Param {
         // let mut param_data: String;
         if !self.is_immediate() {
             if self.is_decrement() {
-                param_text = param_text + "-";
+                param_text += "-";
             } else if self.is_increment() {
-                param_text = param_text + "+";
+                param_text += "+";
             }
             param_text = format!("({})", param_text);
         }
@@ -108,24 +148,24 @@ impl Param {
         self.json_value["immediate"] != Value::Null && self.json_value["immediate"].as_bool().unwrap()
     }
 
-    pub fn get_signed_byte(&self) -> i8 {
+    pub fn get_signed_byte(&self) -> Result<i8, String> {
         match self.value {
-            MemValue::SignedByte(value) => value,
-            _ => panic!("Tries getting param value as signed byte, but it is {:?}", self.value)
+            MemValue::SignedByte(value) => Ok(value),
+            _ => Err(format!("Tries getting param value as signed byte, but it is {:?}", self.value))
         }
     }
 
-    pub fn get_double(&self) -> u16 {
+    pub fn get_double(&self) -> Result<u16, String> {
         match self.value {
-            MemValue::Double(value) => value,
-            _ => panic!("Tries getting param value as double, but it is {:?}", self.value)
+            MemValue::Double(value) => Ok(value),
+            _ => Err(format!("Tries getting param value as double, but it is {:?}", self.value))
         }
     }
 
-    pub fn get_byte(&self) -> u8 {
+    pub fn get_byte(&self) -> Result<u8, String> {
         match self.value {
-            MemValue::Byte(value) => value,
-            _ => panic!("Tries getting param value as byte, but it is {:?}", self.value)
+            MemValue::Byte(value) => Ok(value),
+            _ => Err(format!("Tries getting param value as byte, but it is {:?}", self.value))
         }
     }
 
@@ -0,0 +1,291 @@
+use crate::consts::*;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    UnknownFormat,
+    Truncated,
+    SourceCrc { expected: u32, actual: u32 },
+    TargetCrc { expected: u32, actual: u32 },
+    PatchCrc { expected: u32, actual: u32 },
+    SourceSize { expected: usize, actual: usize },
+    TargetTooLarge(usize),
+    InvalidAction(String)
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "Unknown patch format, expected IPS, UPS or BPS"),
+            PatchError::Truncated => write!(f, "Patch file is truncated"),
+            PatchError::SourceCrc { expected, actual } => write!(f, "Patch is for a different rom (expected CRC32 0x{:08X}, rom is 0x{:08X})", expected, actual),
+            PatchError::TargetCrc { expected, actual } => write!(f, "Patched rom is wrong (expected CRC32 0x{:08X}, got 0x{:08X})", expected, actual),
+            PatchError::PatchCrc { expected, actual } => write!(f, "Patch file is corrupt (expected CRC32 0x{:08X}, got 0x{:08X})", expected, actual),
+            PatchError::SourceSize { expected, actual } => write!(f, "Patch is for a different rom (expected {} bytes, rom is {} bytes)", expected, actual),
+            PatchError::TargetTooLarge(size) => write!(f, "Patched rom would be {} bytes, more than the largest rom ({} bytes)", size, PATCH_MAX_TARGET_SIZE),
+            PatchError::InvalidAction(e) => write!(f, "Invalid patch ({})", e)
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+// Apply an IPS, UPS or BPS patch (detected by the magic) to a rom
+pub fn apply_patch(rom_content: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(PATCH_IPS_MAGIC) {
+        return apply_ips(rom_content, patch);
+    } else if patch.starts_with(PATCH_UPS_MAGIC) {
+        return apply_ups(rom_content, patch);
+    } else if patch.starts_with(PATCH_BPS_MAGIC) {
+        return apply_bps(rom_content, patch);
+    }
+
+    return Err(PatchError::UnknownFormat);
+}
+
+// Patch named like the rom (game.gb -> game.ips), like symbol files
+pub fn find_patch_file(rom_path: &Path) -> Option<PathBuf> {
+    PATCH_FILE_EXTENSIONS.iter()
+        .map(|extension| rom_path.with_extension(extension))
+        .find(|patch_path| patch_path.is_file())
+}
+
+// Reads bytes from the patch, failing when it ends too soon
+struct PatchReader<'a> {
+    patch: &'a [u8],
+    pos: usize
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(patch: &'a [u8], pos: usize) -> PatchReader<'a> {
+        PatchReader { patch, pos }
+    }
+
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self.patch.get(self.pos..self.pos + length).ok_or(PatchError::Truncated)?;
+        self.pos += length;
+        return Ok(bytes);
+    }
+
+    fn read_byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_be(&mut self, length: usize) -> Result<usize, PatchError> {
+        Ok(self.read_bytes(length)?.iter().fold(0, |value, b| (value << 8) | *b as usize))
+    }
+
+    fn read_u32_le(&mut self) -> Result<u32, PatchError> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    // UPS and BPS numbers, 7 bits per byte with the last byte marked by bit 7
+    fn read_varint(&mut self) -> Result<usize, PatchError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let b = self.read_byte()?;
+            value = ((b & 0x7F) as usize).checked_mul(shift).and_then(|digit| value.checked_add(digit))
+                .ok_or(PatchError::InvalidAction("number overflow".to_string()))?;
+            if b & 0x80 != 0 {
+                break;
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::InvalidAction("number overflow".to_string()))?;
+            value = value.checked_add(shift).ok_or(PatchError::InvalidAction("number overflow".to_string()))?;
+        }
+        return Ok(value);
+    }
+}
+
+// IPS: records of (3 byte offset, 2 byte size, data) or RLE records with size 0, until "EOF"
+fn apply_ips(rom_content: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut target: Vec<u8> = rom_content.to_vec();
+    let mut reader = PatchReader::new(patch, PATCH_IPS_MAGIC.len());
+
+    loop {
+        let offset = reader.read_be(3)?;
+        if offset == PATCH_IPS_EOF {
+            break;
+        }
+
+        let size = reader.read_be(2)?;
+        let (size, data): (usize, Option<&[u8]>) = if size == 0 {
+            (reader.read_be(2)?, None)
+        } else {
+            (size, Some(reader.read_bytes(size)?))
+        };
+
+        if target.len() < offset + size {
+            target.resize(offset + size, 0x00);
+        }
+
+        match data {
+            Some(data) => target[offset..offset + size].copy_from_slice(data),
+            None => {
+                let value = reader.read_byte()?;
+                target[offset..offset + size].fill(value);
+            }
+        }
+    }
+
+    // Some patches shrink the rom with a 3 byte size after the end marker
+    if let Ok(truncate_size) = reader.read_be(3) {
+        target.truncate(truncate_size);
+    }
+
+    return Ok(target);
+}
+
+// UPS and BPS end with the source, target and patch CRC32s
+fn read_crc_footer(patch: &[u8]) -> Result<(u32, u32), PatchError> {
+    if patch.len() < PATCH_FOOTER_SIZE + 4 {
+        return Err(PatchError::Truncated);
+    }
+
+    let mut reader = PatchReader::new(patch, patch.len() - PATCH_FOOTER_SIZE);
+    let source_crc = reader.read_u32_le()?;
+    let target_crc = reader.read_u32_le()?;
+    let patch_crc = reader.read_u32_le()?;
+
+    let actual_patch_crc = crc32fast::hash(&patch[..patch.len() - 4]);
+    if actual_patch_crc != patch_crc {
+        return Err(PatchError::PatchCrc { expected: patch_crc, actual: actual_patch_crc });
+    }
+
+    return Ok((source_crc, target_crc));
+}
+
+fn verify_source(rom_content: &[u8], source_size: usize, source_crc: u32) -> Result<(), PatchError> {
+    if rom_content.len() != source_size {
+        return Err(PatchError::SourceSize { expected: source_size, actual: rom_content.len() });
+    }
+
+    let actual_source_crc = crc32fast::hash(rom_content);
+    if actual_source_crc != source_crc {
+        return Err(PatchError::SourceCrc { expected: source_crc, actual: actual_source_crc });
+    }
+    return Ok(());
+}
+
+// The size comes from the patch, don't let it allocate more than a rom could ever need
+fn check_target_size(target_size: usize) -> Result<(), PatchError> {
+    if target_size > PATCH_MAX_TARGET_SIZE {
+        return Err(PatchError::TargetTooLarge(target_size));
+    }
+    return Ok(());
+}
+
+fn verify_target(target: &[u8], target_crc: u32) -> Result<(), PatchError> {
+    let actual_target_crc = crc32fast::hash(target);
+    if actual_target_crc != target_crc {
+        return Err(PatchError::TargetCrc { expected: target_crc, actual: actual_target_crc });
+    }
+    return Ok(());
+}
+
+// UPS: hunks of (relative offset, bytes XORed with the rom, 0x00)
+fn apply_ups(rom_content: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (source_crc, target_crc) = read_crc_footer(patch)?;
+    let patch_end = patch.len() - PATCH_FOOTER_SIZE;
+
+    let mut reader = PatchReader::new(&patch[..patch_end], PATCH_UPS_MAGIC.len());
+    let source_size = reader.read_varint()?;
+    let target_size = reader.read_varint()?;
+    verify_source(rom_content, source_size, source_crc)?;
+    check_target_size(target_size)?;
+
+    let mut target: Vec<u8> = rom_content.to_vec();
+    target.resize(target_size, 0x00);
+
+    let mut pos: usize = 0;
+    while reader.pos < patch_end {
+        // Each hunk moves at most a byte per patch byte after this, so only the skip can overflow
+        pos = pos.checked_add(reader.read_varint()?).filter(|pos| *pos <= target_size)
+            .ok_or(PatchError::InvalidAction("hunk starts past the end of the rom".to_string()))?;
+
+        loop {
+            let xor = reader.read_byte()?;
+            if xor == 0x00 {
+                pos += 1;
+                break;
+            }
+
+            if pos < target_size {
+                target[pos] ^= xor;
+            }
+            pos += 1;
+        }
+    }
+
+    verify_target(&target, target_crc)?;
+    return Ok(target);
+}
+
+// BPS: actions that copy from the rom, the patch or the output itself
+fn apply_bps(rom_content: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (source_crc, target_crc) = read_crc_footer(patch)?;
+    let patch_end = patch.len() - PATCH_FOOTER_SIZE;
+
+    let mut reader = PatchReader::new(&patch[..patch_end], PATCH_BPS_MAGIC.len());
+    let source_size = reader.read_varint()?;
+    let target_size = reader.read_varint()?;
+    let metadata_size = reader.read_varint()?;
+    reader.read_bytes(metadata_size)?;
+    verify_source(rom_content, source_size, source_crc)?;
+    check_target_size(target_size)?;
+
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+    let mut source_relative: usize = 0;
+    let mut target_relative: usize = 0;
+
+    let read_offset = |reader: &mut PatchReader, relative: usize| -> Result<usize, PatchError> {
+        let data = reader.read_varint()?;
+        let offset = data >> 1;
+        let relative = if data & 1 != 0 { relative.checked_sub(offset) } else { relative.checked_add(offset) };
+        return relative.ok_or(PatchError::InvalidAction("copy offset out of range".to_string()));
+    };
+
+    while reader.pos < patch_end {
+        let data = reader.read_varint()?;
+        let length = (data >> 2) + 1;
+
+        if target.len() + length > target_size {
+            return Err(PatchError::InvalidAction("writes past the end of the rom".to_string()));
+        }
+
+        match data & 0b11 {
+            0 => { // Source read, same offset as the output
+                let start = target.len();
+                let bytes = rom_content.get(start..start + length).ok_or(PatchError::InvalidAction("source read out of range".to_string()))?;
+                target.extend_from_slice(bytes);
+            },
+            1 => { // Target read, bytes from the patch
+                target.extend_from_slice(reader.read_bytes(length)?);
+            },
+            2 => { // Source copy
+                source_relative = read_offset(&mut reader, source_relative)?;
+                let source_end = source_relative.checked_add(length).ok_or(PatchError::InvalidAction("source copy out of range".to_string()))?;
+                let bytes = rom_content.get(source_relative..source_end).ok_or(PatchError::InvalidAction("source copy out of range".to_string()))?;
+                target.extend_from_slice(bytes);
+                source_relative = source_end;
+            },
+            _ => { // Target copy, one byte at a time since it can overlap what it writes
+                target_relative = read_offset(&mut reader, target_relative)?;
+                for _ in 0..length {
+                    let b = *target.get(target_relative).ok_or(PatchError::InvalidAction("target copy out of range".to_string()))?;
+                    target.push(b);
+                    target_relative += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(PatchError::InvalidAction(format!("patched rom is {} bytes instead of {}", target.len(), target_size)));
+    }
+
+    verify_target(&target, target_crc)?;
+    return Ok(target);
+}
@@ -1,74 +1,108 @@
 use crate::consts::*;
 use crate::ram_memory::RamMemory;
-use std::rc::Rc;
-use std::cell::RefCell;
-use minifb::{Window, WindowOptions, Scale};
-
-use bmp::{Image, Pixel};
 
 type Sprite = [u8; 16]; // Sprite as represented in VRAM
 type SpriteBitmap = [u32; 64]; // Sprite as 64 (8 by 8) pixels - this can be displayed
+type ColorPallete = [u32; 4];
+
+// CGB attributes of a BG map entry, stored in VRAM bank 1 at the same addr as the tile index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileAttributes {
+    pub pallete: u8,
+    pub bank: usize,
+    pub x_flip: bool,
+    pub y_flip: bool,
+    pub priority: bool // BG over objects, objects aren't drawn yet so nothing uses it
+}
+
+impl TileAttributes {
+    pub fn from_byte(value: u8) -> TileAttributes {
+        TileAttributes {
+            pallete: value & CGB_TILE_ATTR_PALETTE_MASK,
+            bank: bit_check(value, CGB_TILE_ATTR_BIT_BANK) as usize,
+            x_flip: bit_check(value, CGB_TILE_ATTR_BIT_X_FLIP),
+            y_flip: bit_check(value, CGB_TILE_ATTR_BIT_Y_FLIP),
+            priority: bit_check(value, CGB_TILE_ATTR_BIT_PRIORITY)
+        }
+    }
+}
+
+// CGB VRAM DMA, set up through HDMA1-HDMA5
+#[derive(Default)]
+struct Hdma {
+    source: u16,
+    destination: u16, // Offset in VRAM
+    remaining_blocks: u8,
+    hblank_active: bool
+}
 
 pub struct PPU {
     buffer: Vec<u32>,
-    window: Window,
-    ram_memory: Rc<RefCell<RamMemory>>,
-    color_pallete: [u32; 4]
+    color_pallete: ColorPallete,
+    bg_pallete_ram: [u8; CGB_PALETTE_RAM_SIZE], // CGB color palletes, written through BCPS/BCPD
+    obj_pallete_ram: [u8; CGB_PALETTE_RAM_SIZE], // CGB color palletes, written through OCPS/OCPD
+    bg_pallete_index: u8, // BCPS
+    obj_pallete_index: u8, // OCPS
+    mode: u8,
+    ly: u8,
+    line_cycles: usize,
+    ly_stub: bool, // LY always reads as PPU_LY_VBLANK_STUB
+    frame_ready: bool,
+    hdma: Hdma,
+    dma_stall_cycles: usize // Cycles the cpu should be halted for because of VRAM DMA
 }
 
 impl PPU {
-    pub fn init(ram_memory_ref: Rc<RefCell<RamMemory>>) -> PPU {
-        // Configure scale
-        let mut window_options: WindowOptions = WindowOptions::default();
-        window_options.scale = Scale::X2;
-
-        let mut window = Window::new(
-            "GBEmulator",
-            SCREEN_WIDTH,
-            SCREEN_HEIGHT,
-            window_options,
-        ).unwrap_or_else(|e| {
-            panic!("Failed creating minifb window ({})", e);
-        });
-
-        // Limit FPS to about 60FPS
-        // window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
-
-        window.limit_update_rate(Some(std::time::Duration::from_micros(1660)));
-
-
-        // Why render before initializing the ppu ?
-        window.update_with_buffer(
-            &get_empty_screen_buffer(), 
-            SCREEN_WIDTH, SCREEN_HEIGHT).unwrap_or_else(|e| {
-            panic!("Failed rendering window due to error ({})", e);
-        });
-
+    // Only draws into the buffer, showing it is up to the frontend
+    pub fn init() -> PPU {
         PPU {
             buffer: get_empty_screen_buffer(),
-            window: window,
-            ram_memory: ram_memory_ref,
-            color_pallete: [0,0,0,0]
+            color_pallete: [0,0,0,0],
+            bg_pallete_ram: [0xFF; CGB_PALETTE_RAM_SIZE],
+            obj_pallete_ram: [0xFF; CGB_PALETTE_RAM_SIZE],
+            bg_pallete_index: 0,
+            obj_pallete_index: 0,
+            mode: PPU_MODE_OAM_SCAN,
+            ly: 0,
+            line_cycles: 0,
+            ly_stub: false,
+            frame_ready: false,
+            hdma: Hdma::default(),
+            dma_stall_cycles: 0
         }
     }
 
+    pub fn get_buffer(&self) -> &[u32] {
+        &self.buffer
+    }
+
 
 
-    // COLOR & BITMAP STUFF
-    fn draw_sprite_in_buffer(&mut self, sprite: Sprite, x:u8, y:u8) {
-        let sprite_bitmap: SpriteBitmap = self.sprite_to_bitmap(sprite);
+        // COLOR & BITMAP STUFF
+    fn draw_sprite_in_buffer(&mut self, sprite: Sprite, x:u8, y:u8, pallete: ColorPallete) {
+        let sprite_bitmap: SpriteBitmap = Self::sprite_to_bitmap(sprite, pallete);
         self.draw_sprite_bitmap_in_buffer(sprite_bitmap, x.into(), y.into());
 
     }
 
+    fn flip_sprite_bitmap(sprite: SpriteBitmap, x_flip: bool, y_flip: bool) -> SpriteBitmap {
+        let mut flipped: SpriteBitmap = [0; 64];
+        for y in 0..8 {
+            for x in 0..8 {
+                let from_x = if x_flip { 7 - x } else { x };
+                let from_y = if y_flip { 7 - y } else { y };
+                flipped[y * 8 + x] = sprite[from_y * 8 + from_x];
+            }
+        }
+        return flipped;
+    }
+
     fn draw_sprite_bitmap_in_buffer(&mut self, sprite: SpriteBitmap, x: usize, y: usize) {
-        let mut new_y = y;
-        for row in sprite.chunks(8) {
+        for (new_y, row) in (y..).zip(sprite.chunks(8)) {
             for (index, pixel) in row.iter().enumerate() {
                 let pixel_index = new_y * SCREEN_WIDTH + x + index;
                 self.buffer[pixel_index] = *pixel;
             }
-            new_y += 1;
         }
     }
 
@@ -82,8 +116,8 @@ impl PPU {
         }
     }
 
-    fn update_color_pallete(&mut self) {
-        let bg_color_pallete = self.get_addr(PPU_BG_COLOR_PALLETE);
+    fn update_color_pallete(&mut self, ram_memory: &RamMemory) {
+        let bg_color_pallete = self.get_addr(ram_memory, PPU_BG_COLOR_PALLETE);
 
         // Update index 0
         let color_0: u8 = (bit_check(bg_color_pallete, 0) as u8) + ((bit_check(bg_color_pallete, 1) as u8) * 2);
@@ -99,13 +133,31 @@ impl PPU {
         ]
     }
 
-    fn get_color_by_color_pallete(&self, color_code: u8) -> u32 {
-        return self.color_pallete[color_code as usize];
+    // CGB colors are 15 bit little endian (0bBBBBBGGGGGRRRRR), each channel is scaled up to 8 bits
+    pub fn get_cgb_color(pallete_ram: &[u8; CGB_PALETTE_RAM_SIZE], pallete: u8, color_code: u8) -> u32 {
+        let index = (pallete as usize * 4 + color_code as usize) * 2;
+        let color: u16 = pallete_ram[index] as u16 | ((pallete_ram[index + 1] as u16) << 8);
+
+        let scale = |channel: u16| -> u32 {
+            let channel = (channel & 0x1F) as u32;
+            return (channel << 3) | (channel >> 2);
+        };
+
+        return (scale(color) << 16) | (scale(color >> 5) << 8) | scale(color >> 10);
+    }
+
+    fn get_cgb_pallete(pallete_ram: &[u8; CGB_PALETTE_RAM_SIZE], pallete: u8) -> ColorPallete {
+        return [
+            Self::get_cgb_color(pallete_ram, pallete, 0),
+            Self::get_cgb_color(pallete_ram, pallete, 1),
+            Self::get_cgb_color(pallete_ram, pallete, 2),
+            Self::get_cgb_color(pallete_ram, pallete, 3)
+        ];
     }
 
-    fn get_sprite_tile(&self, tile_id: u8) -> Sprite {
+    fn get_sprite_tile(&self, ram_memory: &RamMemory, tile_id: u8, bank: usize) -> Sprite {
         let mut tile_addr: u16;
-        if self.get_ppu_config("bg_window_data_area") {
+        if self.get_ppu_config(ram_memory, "bg_window_data_area") {
             trace!("PPU: Setting background and window sprite area to 0x8000");
             tile_addr = 0x8000;
         } else {
@@ -115,15 +167,15 @@ impl PPU {
 
         tile_addr += 16 * tile_id as u16;
 
-        let mut sprite: Sprite = [0 as u8; 16];
-        for x in 0..16 {
-            sprite[x] = self.ram_memory.borrow_mut().get_addr(tile_addr + x as u16);
+        let mut sprite: Sprite = [0; 16];
+        for (x, byte) in sprite.iter_mut().enumerate() {
+            *byte = ram_memory.get_vram_addr(bank, tile_addr + x as u16);
         }
 
         return sprite;
     }
 
-    fn sprite_to_bitmap(&self, sprite: Sprite) -> SpriteBitmap {
+    fn sprite_to_bitmap(sprite: Sprite, pallete: ColorPallete) -> SpriteBitmap {
         let mut sprite_bitmap: SpriteBitmap = [0x00ffffff; 64];
         let mut counter: usize = 0;
         for pair in sprite.chunks(2) {
@@ -135,11 +187,10 @@ impl PPU {
                     [false, false] => 0, 
                     [true, false] => 1,
                     [false, true] => 2,
-                    [true, true] => 3,
-                    _ => panic!("PPU: Invalid types in sprite_to_bitmap")
+                    [true, true] => 3
                 };
 
-                sprite_bitmap[counter] = self.get_color_by_color_pallete(color_code);
+                sprite_bitmap[counter] = pallete[color_code as usize];
 
                 counter += 1;
             }
@@ -155,7 +206,7 @@ impl PPU {
 
 
     // MEMORY STUFF
-    pub fn set_addr(&mut self, addr: u16, value: u8) {
+    pub fn set_addr(&mut self, ram_memory: &mut RamMemory, addr: u16, value: u8) {
         debug!("PPU: Write to ppu addr 0x{:04X} -> 0x{:02X}", addr ,value);
         let mut should_write_to_ram_memory: bool = true;
 
@@ -175,45 +226,56 @@ impl PPU {
             trace!("PPU: 0x{:04X} is wave addr", addr);
         } else if PPU_LCD_ADDR.contains(&addr) {
             trace!("PPU: 0x{:04X} is lcd control addr", addr);
-            self.lcd_control_set_handler(addr, value);
+            self.lcd_control_set_handler(ram_memory, addr, value);
+        } else if CGB_PALETTE_ADDR.contains(&addr) {
+            trace!("PPU: 0x{:04X} is cgb pallete addr", addr);
+            self.cgb_pallete_set_handler(addr, value);
+            should_write_to_ram_memory = false;
+        } else if CGB_HDMA_ADDR.contains(&addr) {
+            trace!("PPU: 0x{:04X} is vram dma addr", addr);
+            self.hdma_set_handler(ram_memory, addr, value);
+            should_write_to_ram_memory = false;
         }
 
         if should_write_to_ram_memory {
-            self.ram_memory.borrow_mut().set_addr(addr, value);
+            ram_memory.set_addr(addr, value);
         }
     }
 
-    pub fn get_addr(&self, addr: u16) -> u8 {
+    pub fn get_addr(&self, ram_memory: &RamMemory, addr: u16) -> u8 {
         // This will return Option with value if we want to return a custom value (not value in ram)
-        let custom_handler_result = self.lcd_control_get_handler(addr);
-        if custom_handler_result.is_some() {
-            return custom_handler_result.unwrap();
+        if let Some(custom_value) = self.lcd_control_get_handler(ram_memory, addr) {
+            return custom_value;
+        }
+
+        if let Some(custom_value) = self.cgb_pallete_get_handler(addr) {
+            return custom_value;
+        }
+
+        if CGB_HDMA_ADDR.contains(&addr) {
+            return self.hdma_get_handler(addr);
         }
 
 
-        let ram_value = self.ram_memory.borrow().get_addr(addr);
+        let ram_value = ram_memory.get_addr(addr);
         return ram_value;
     }
 
-    fn lcd_control_set_handler(&mut self, addr: u16, value: u8) {
+    fn lcd_control_set_handler(&mut self, ram_memory: &mut RamMemory, addr: u16, value: u8) {
         match addr {
             PPU_ADDR_LCD_CONTROL => {
                 // 7 -> Control enable bit
                 if bit_check(value, PPU_LCD_CONTROL_BIT_ENABLE) {
-                    if !self.get_ppu_config("is_enabled") {
+                    if !self.get_ppu_config(ram_memory, "is_enabled") {
                         trace!("PPU: LCD_CONTROL: Enabling lcd display");
 
                         // The default handler also writes to memory
                         // self.set_ppu_config("is_enabled", true);
                     }
                 } else {
-                    if self.get_ppu_config("is_enabled") { // Disable only if screen is enabled
+                    if self.get_ppu_config(ram_memory, "is_enabled") { // Disable only if screen is enabled
                         trace!("PPU: LCD_CONTROL: Disabling lcd display");
-                        self.window.update_with_buffer(
-                            &get_empty_screen_buffer(), 
-                            SCREEN_WIDTH, SCREEN_HEIGHT).unwrap_or_else(|e| {
-                                panic!("PPU: LCD_CONTROL: Failed rendering window due to error ({})", e);
-                        });
+                        self.buffer = get_empty_screen_buffer();
     
                         // The default handler also writes to memory
                         // self.set_ppu_config("is_enabled", false);
@@ -279,8 +341,8 @@ impl PPU {
             PPU_BG_COLOR_PALLETE => { //Updating bg color pallete
                 debug!("Updating bg color pallete");
                 
-                self.ram_memory.borrow_mut().set_addr(addr, value);
-                self.update_color_pallete();
+                ram_memory.set_addr(addr, value);
+                self.update_color_pallete(ram_memory);
             },
             PPU_BG_Y_VIEWPORT => { // Y Viewport
                 debug!("A Change to y viewport : {}", value);
@@ -292,13 +354,208 @@ impl PPU {
         }
     }
 
-    fn lcd_control_get_handler(&self, addr: u16) -> Option<u8> {
-        // This indicates the screen is in VBLANK - cpu can write period
-        // Because the cpu is trying to read this means the ppu is not rendering == VBLANK period
-        if addr == 0xFF44 {
-            return Some(0x90);
+    // BCPS/OCPS select a byte in pallete ram, BCPD/OCPD read or write it.
+    // With bit 7 of the index set, writing the data moves the index to the next byte
+    fn cgb_pallete_set_handler(&mut self, addr: u16, value: u8) {
+        let (pallete_ram, pallete_index) = match addr {
+            CGB_ADDR_BCPS | CGB_ADDR_BCPD => (&mut self.bg_pallete_ram, &mut self.bg_pallete_index),
+            _ => (&mut self.obj_pallete_ram, &mut self.obj_pallete_index)
+        };
+
+        match addr {
+            CGB_ADDR_BCPS | CGB_ADDR_OCPS => {
+                *pallete_index = value & (CGB_PALETTE_INDEX_MASK | (1 << CGB_PALETTE_BIT_AUTO_INCREMENT));
+            },
+            _ => {
+                let index = *pallete_index & CGB_PALETTE_INDEX_MASK;
+                pallete_ram[index as usize] = value;
+                trace!("PPU: Pallete ram 0x{:02X} -> 0x{:02X}", index, value);
+
+                if bit_check(*pallete_index, CGB_PALETTE_BIT_AUTO_INCREMENT) {
+                    *pallete_index = (*pallete_index & !CGB_PALETTE_INDEX_MASK) | ((index + 1) & CGB_PALETTE_INDEX_MASK);
+                }
+            }
         }
-        return None;
+    }
+
+    fn cgb_pallete_get_handler(&self, addr: u16) -> Option<u8> {
+        return match addr {
+            CGB_ADDR_BCPS => Some(self.bg_pallete_index | 0x40),
+            CGB_ADDR_OCPS => Some(self.obj_pallete_index | 0x40),
+            CGB_ADDR_BCPD => Some(self.bg_pallete_ram[(self.bg_pallete_index & CGB_PALETTE_INDEX_MASK) as usize]),
+            CGB_ADDR_OCPD => Some(self.obj_pallete_ram[(self.obj_pallete_index & CGB_PALETTE_INDEX_MASK) as usize]),
+            _ => None
+        };
+    }
+
+    fn lcd_control_get_handler(&self, ram_memory: &RamMemory, addr: u16) -> Option<u8> {
+        match addr {
+            PPU_ADDR_LY => {
+                if self.ly_stub {
+                    return Some(PPU_LY_VBLANK_STUB);
+                }
+                return Some(self.ly);
+            },
+            PPU_ADDR_LCD_STATUS => {
+                let mut value = ram_memory.get_addr(addr) & !(PPU_LCD_STATUS_MODE_MASK | (1 << PPU_LCD_STATUS_BIT_LYC_EQUAL));
+                value |= self.mode;
+                if self.ly == ram_memory.get_addr(PPU_ADDR_LYC) {
+                    value |= 1 << PPU_LCD_STATUS_BIT_LYC_EQUAL;
+                }
+                return Some(value | 0x80);
+            },
+            _ => return None
+        }
+    }
+
+    // Gameboy Doctor logs expect LY to always be 0x90
+    pub fn set_ly_stub(&mut self, ly_stub: bool) {
+        self.ly_stub = ly_stub;
+    }
+
+    // VRAM DMA stuff
+    fn hdma_set_handler(&mut self, ram_memory: &mut RamMemory, addr: u16, value: u8) {
+        match addr {
+            CGB_ADDR_HDMA1 => self.hdma.source = (self.hdma.source & 0x00FF) | ((value as u16) << 8),
+            CGB_ADDR_HDMA2 => self.hdma.source = (self.hdma.source & 0xFF00) | (value & 0xF0) as u16,
+            CGB_ADDR_HDMA3 => self.hdma.destination = (self.hdma.destination & 0x00FF) | (((value & 0x1F) as u16) << 8),
+            CGB_ADDR_HDMA4 => self.hdma.destination = (self.hdma.destination & 0xFF00) | (value & 0xF0) as u16,
+            _ => { // HDMA5
+                let blocks = (value & 0x7F) + 1;
+
+                if self.hdma.hblank_active && !bit_check(value, CGB_HDMA5_BIT_HBLANK_MODE) {
+                    debug!("PPU: HBlank DMA cancelled with {} blocks left", self.hdma.remaining_blocks);
+                    self.hdma.hblank_active = false;
+                } else if bit_check(value, CGB_HDMA5_BIT_HBLANK_MODE) {
+                    debug!("PPU: HBlank DMA of {} blocks from 0x{:04X} to 0x{:04X}", blocks, self.hdma.source, CGB_VRAM_START | self.hdma.destination);
+                    self.hdma.remaining_blocks = blocks;
+                    self.hdma.hblank_active = true;
+                } else {
+                    // General purpose DMA copies everything at once while the cpu waits
+                    debug!("PPU: General purpose DMA of {} blocks from 0x{:04X} to 0x{:04X}", blocks, self.hdma.source, CGB_VRAM_START | self.hdma.destination);
+                    self.hdma.remaining_blocks = blocks;
+                    while self.hdma.remaining_blocks > 0 {
+                        self.hdma_copy_block(ram_memory);
+                    }
+                }
+            }
+        }
+    }
+
+    fn hdma_get_handler(&self, addr: u16) -> u8 {
+        if addr != CGB_ADDR_HDMA5 {
+            return 0xFF; // Write only
+        }
+
+        // Blocks left minus one, bit 7 is set when no transfer is active (0xFF once it completed)
+        let remaining = self.hdma.remaining_blocks.wrapping_sub(1) & 0x7F;
+        if self.hdma.hblank_active {
+            return remaining;
+        }
+        return 0x80 | remaining;
+    }
+
+    fn hdma_copy_block(&mut self, ram_memory: &mut RamMemory) {
+        for offset in 0..CGB_HDMA_BLOCK_SIZE {
+            let value = ram_memory.get_addr(self.hdma.source.wrapping_add(offset));
+            ram_memory.set_addr(CGB_VRAM_START | (self.hdma.destination + offset), value);
+        }
+
+        self.hdma.source = self.hdma.source.wrapping_add(CGB_HDMA_BLOCK_SIZE);
+        self.hdma.destination += CGB_HDMA_BLOCK_SIZE;
+        self.hdma.remaining_blocks -= 1;
+        self.dma_stall_cycles += CGB_HDMA_BLOCK_CYCLES;
+
+        // The transfer stops when it gets past the end of VRAM
+        if self.hdma.destination as usize >= CGB_VRAM_BANK_SIZE {
+            self.hdma.destination = 0;
+            self.hdma.remaining_blocks = 0;
+        }
+
+        if self.hdma.remaining_blocks == 0 {
+            self.hdma.hblank_active = false;
+        }
+    }
+
+    // How long the cpu has to wait for VRAM DMA since the last call
+    pub fn take_dma_stall_cycles(&mut self) -> usize {
+        return std::mem::take(&mut self.dma_stall_cycles);
+    }
+
+    // TIMING STUFF
+    // Advance the ppu by the given amount of dots, going through the modes of each line
+    pub fn tick(&mut self, ram_memory: &mut RamMemory, cycles: usize) {
+        if !self.get_ppu_config(ram_memory, "is_enabled") {
+            self.ly = 0;
+            self.line_cycles = 0;
+            self.mode = PPU_MODE_HBLANK;
+            return;
+        }
+
+        // Never step past a mode change, so long ticks (DMA) don't skip any HBlank
+        let mut remaining_cycles = cycles;
+        while remaining_cycles > 0 {
+            let next_mode_change: usize = if self.line_cycles < PPU_CYCLES_OAM_SCAN {
+                PPU_CYCLES_OAM_SCAN
+            } else if self.line_cycles < PPU_CYCLES_OAM_SCAN + PPU_CYCLES_DRAWING {
+                PPU_CYCLES_OAM_SCAN + PPU_CYCLES_DRAWING
+            } else {
+                PPU_CYCLES_PER_LINE
+            };
+
+            let step = usize::min(remaining_cycles, next_mode_change - self.line_cycles);
+            self.line_cycles += step;
+            remaining_cycles -= step;
+
+            if self.line_cycles == PPU_CYCLES_PER_LINE {
+                self.line_cycles = 0;
+                self.ly = (self.ly + 1) % PPU_LINES_PER_FRAME;
+            }
+
+            self.update_mode(ram_memory);
+        }
+    }
+
+    fn update_mode(&mut self, ram_memory: &mut RamMemory) {
+        let new_mode: u8 = if self.ly >= PPU_VISIBLE_LINES {
+            PPU_MODE_VBLANK
+        } else if self.line_cycles < PPU_CYCLES_OAM_SCAN {
+            PPU_MODE_OAM_SCAN
+        } else if self.line_cycles < PPU_CYCLES_OAM_SCAN + PPU_CYCLES_DRAWING {
+            PPU_MODE_DRAWING
+        } else {
+            PPU_MODE_HBLANK
+        };
+
+        if new_mode == self.mode {
+            return;
+        }
+
+        trace!("PPU: Line {} mode {} -> {}", self.ly, self.mode, new_mode);
+        self.mode = new_mode;
+
+        match new_mode {
+            PPU_MODE_HBLANK if self.hdma.hblank_active => {
+                self.hdma_copy_block(ram_memory);
+            },
+            PPU_MODE_VBLANK => {
+                self.frame_ready = true;
+            },
+            _ => ()
+        }
+    }
+
+    pub fn get_mode(&self) -> u8 {
+        self.mode
+    }
+
+    pub fn get_ly(&self) -> u8 {
+        self.ly
+    }
+
+    // True once per frame, when the ppu got to VBlank since the last call
+    pub fn take_frame_ready(&mut self) -> bool {
+        return std::mem::take(&mut self.frame_ready);
     }
 
 
@@ -314,28 +571,28 @@ impl PPU {
         } 
     }
 
-    fn get_ppu_config(&self, config: &str) -> bool {
+    fn get_ppu_config(&self, ram_memory: &RamMemory, config: &str) -> bool {
         let (addr, bit) = Self::get_addr_and_bit(config);
-        return bit_check(self.get_addr(addr), bit);
+        return bit_check(self.get_addr(ram_memory, addr), bit);
     }    
 
-    fn set_ppu_config(&mut self, config: &str, value: bool) {
+    #[allow(dead_code)] // Kept for the LCD on/off handling, see the commented out calls above
+    fn set_ppu_config(&mut self, ram_memory: &mut RamMemory, config: &str, value: bool) {
         let (addr, bit) = Self::get_addr_and_bit(config);
 
-        let old_value: u8 = self.get_addr(addr);
+        let old_value: u8 = self.get_addr(ram_memory, addr);
 
         let mut mask: u8 = 0b00000001;
-        mask = mask << (bit - 1);
+        mask <<= bit - 1 ;
 
-        let new_value: u8;
-        if value {
-            new_value = old_value | mask;
+        let new_value: u8 = if value {
+            old_value | mask
         } else {
-            mask = mask ^ 0xff;
-            new_value = old_value & mask;
-        }
+            mask ^= 0xff;
+            old_value & mask
+        };
 
-        self.set_addr(addr, new_value);
+        self.set_addr(ram_memory, addr, new_value);
     }
 
     
@@ -344,12 +601,12 @@ impl PPU {
 
 
 
-    pub fn render(&mut self){
-        if self.get_ppu_config("is_enabled") && !PPU_DISABLE {
+    pub fn render(&mut self, ram_memory: &RamMemory) {
+        if self.get_ppu_config(ram_memory, "is_enabled") && !PPU_DISABLE {
             if PPU_DUMP_SPRITES { // Render all frames
                 debug!("PPU: Dumping sprites to screen");
                 for sprite_id in 0..=0xff {
-                    let sprite: Sprite = self.get_sprite_tile(sprite_id);
+                    let sprite: Sprite = self.get_sprite_tile(ram_memory, sprite_id, 0);
                     // let sprite: Sprite = self.get_sprite_tile(25); // "Copyright" sprite of the nintendo logo in the boot rom
         
                     let x_pos = (sprite_id % 32) * 8;
@@ -358,27 +615,23 @@ impl PPU {
                     // let x_pos = 80;
                     // let y_pos = 80;
                     
-                    self.draw_sprite_in_buffer(sprite, x_pos, y_pos)
+                    self.draw_sprite_in_buffer(sprite, x_pos, y_pos, self.color_pallete)
                 }
     
                 debug!("PPU: Rendering frame");
-                self.window.update_with_buffer(&self.buffer, SCREEN_WIDTH, SCREEN_HEIGHT).unwrap_or_else(|e| {
-                    panic!("Failed rendering window due to error ({})", e);
-                });
             } else { // Actually render the image that should be displayed
 
                 // Display background
-                let bg_addr_init: u16;
-                if self.get_ppu_config("bg_tile_map") {
-                    bg_addr_init = 0x9C00;
+                let bg_addr_init: u16 = if self.get_ppu_config(ram_memory, "bg_tile_map") {
+                    0x9C00
                 } else {
-                    bg_addr_init = 0x9800;
-                }
+                    0x9800
+                };
                 let mut bg_addr: u16 = bg_addr_init;
 
                 // Get background viewport
-                let y_viewport = self.get_addr(PPU_BG_Y_VIEWPORT);
-                let x_viewport = self.get_addr(PPU_BG_X_VIEWPORT);
+                let y_viewport = self.get_addr(ram_memory, PPU_BG_Y_VIEWPORT);
+                let x_viewport = self.get_addr(ram_memory, PPU_BG_X_VIEWPORT);
 
                 // Render sprites
                 while bg_addr < (bg_addr_init + 0x0400) {
@@ -387,7 +640,7 @@ impl PPU {
                     let (y_pos, _) = u8::overflowing_add((map_entry_index / 32) * 8, y_viewport);
                     
                     // Find tile index
-                    let tile_index = self.get_addr(bg_addr);
+                    let tile_index = self.get_addr(ram_memory, bg_addr);
 
                     // Ignore tile index 0 - For some reason only 1 in 4 renders actually renders the real tile
                     // The rest render tile 0 - For example
@@ -402,8 +655,18 @@ impl PPU {
                         continue;
                     }
 
-                    let sprite = self.get_sprite_tile(tile_index);
-                    self.draw_sprite_in_buffer(sprite, x_pos, y_pos);
+                    if ram_memory.is_cgb_mode() {
+                        // Attributes pick the pallete and bank of the tile and how to flip it
+                        let attributes = TileAttributes::from_byte(ram_memory.get_vram_addr(1, bg_addr));
+                        let sprite = self.get_sprite_tile(ram_memory, tile_index, attributes.bank);
+                        let pallete = Self::get_cgb_pallete(&self.bg_pallete_ram, attributes.pallete);
+
+                        let sprite_bitmap = Self::flip_sprite_bitmap(Self::sprite_to_bitmap(sprite, pallete), attributes.x_flip, attributes.y_flip);
+                        self.draw_sprite_bitmap_in_buffer(sprite_bitmap, x_pos.into(), y_pos.into());
+                    } else {
+                        let sprite = self.get_sprite_tile(ram_memory, tile_index, 0);
+                        self.draw_sprite_in_buffer(sprite, x_pos, y_pos, self.color_pallete);
+                    }
                     
                     trace!("Drawing sprite id {} in ({}, {})", tile_index, x_pos, y_pos);
 
@@ -415,9 +678,6 @@ impl PPU {
 
                 // Print buffer vector
                 debug!("PPU: Rendering frame");
-                self.window.update_with_buffer(&self.buffer, SCREEN_WIDTH, SCREEN_HEIGHT).unwrap_or_else(|e| {
-                    panic!("Failed rendering window due to error ({})", e);
-                });
             } 
         } else {
             if PPU_DISABLE {
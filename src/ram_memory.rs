@@ -2,7 +2,13 @@ use crate::rom_parser::Rom;
 use crate::consts::*;
 
 pub struct RamMemory {
-    memory: Vec<u8>
+    memory: Vec<u8>,
+    boot_rom: Option<Vec<u8>>, // Mapped over the cartridge until 0xFF50 is written to
+    cgb_mode: bool,
+    vram_bank: usize,
+    vram_bank_1: Vec<u8>, // Bank 0 lives in memory
+    wram_bank: usize,
+    wram_banks: Vec<u8> // Banks 2-7, bank 1 lives in memory
 }
 
 impl RamMemory {
@@ -18,23 +24,117 @@ impl RamMemory {
         }
 
         RamMemory { 
-            memory
+            memory,
+            boot_rom: None,
+            cgb_mode: false,
+            vram_bank: 0,
+            vram_bank_1: vec![0x00; CGB_VRAM_BANK_SIZE],
+            wram_bank: 1,
+            wram_banks: vec![0x00; CGB_WRAM_BANK_SIZE * (CGB_WRAM_BANK_COUNT - 2)]
         }
     }
 
+    // DMG, MGB and SGB boot roms are 0x100 bytes, CGB boot roms are 0x900 bytes
+    // and leave 0x0100-0x01FF (the cartridge header) visible
+    pub fn set_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<(), String> {
+        if boot_rom.len() != BOOT_ROM_SIZE && boot_rom.len() != BOOT_ROM_SIZE_CGB {
+            return Err(format!("Invalid boot rom size 0x{:X}, expected 0x{:X} or 0x{:X}", boot_rom.len(), BOOT_ROM_SIZE, BOOT_ROM_SIZE_CGB));
+        }
+
+        self.boot_rom = Some(boot_rom);
+        return Ok(());
+    }
+
+    pub fn is_boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    fn get_boot_rom_addr(&self, addr: u16) -> Option<u8> {
+        let boot_rom = self.boot_rom.as_ref()?;
+
+        if (addr as usize) < BOOT_ROM_SIZE || ((addr as usize) >= BOOT_ROM_CGB_UPPER_START && (addr as usize) < boot_rom.len()) {
+            return Some(boot_rom[addr as usize]);
+        }
+        return None;
+    }
+
+    // CGB banking stuff, without cgb mode VBK/SVBK are just ram and bank 0/1 are always mapped
+    pub fn set_cgb_mode(&mut self, cgb_mode: bool) {
+        self.cgb_mode = cgb_mode;
+    }
+
+    pub fn is_cgb_mode(&self) -> bool {
+        self.cgb_mode
+    }
+
+    // Read from a specific VRAM bank regardless of VBK, the ppu needs both banks for a single tile
+    pub fn get_vram_addr(&self, bank: usize, addr: u16) -> u8 {
+        if bank == 1 {
+            return self.vram_bank_1[(addr - CGB_VRAM_START) as usize];
+        }
+        return self.memory[addr as usize];
+    }
+
+    fn get_banked_index(&self, addr: u16) -> Option<(bool, usize)> {
+        if !self.cgb_mode {
+            return None;
+        }
+
+        match addr {
+            0x8000..=0x9FFF if self.vram_bank == 1 => Some((true, (addr - CGB_VRAM_START) as usize)),
+            0xD000..=0xDFFF if self.wram_bank >= 2 => Some((false, (self.wram_bank - 2) * CGB_WRAM_BANK_SIZE + (addr - CGB_WRAM_BANKED_START) as usize)),
+            _ => None
+        }
+    }
+
+    // Memory is padded to the whole address space when created, so every addr is in range
     pub fn get_addr(&self, addr: u16) -> u8 {
-        if addr as usize >= self.memory.len() {
-            panic!("Requested invalid memory addr ({:04X})", addr);
+        if let Some(value) = self.get_boot_rom_addr(addr) {
+            return value;
         }
 
-        *self.memory.get(addr as usize).unwrap()
+        if self.cgb_mode {
+            match addr {
+                CGB_ADDR_VBK => return 0xFE | self.vram_bank as u8,
+                CGB_ADDR_SVBK => return 0xF8 | self.wram_bank as u8,
+                _ => ()
+            }
+        }
+
+        match self.get_banked_index(addr) {
+            Some((true, index)) => self.vram_bank_1[index],
+            Some((false, index)) => self.wram_banks[index],
+            None => self.memory[addr as usize]
+        }
     }
 
+    // Memory is padded to the whole address space when created, so every addr is in range
     pub fn set_addr(&mut self, addr: u16, value: u8) {
-        if addr as usize >= self.memory.len() {
-            panic!("Requested invalid memory addr ({:04X})", addr);
+        // Any non zero write unmaps the boot rom, it can't be mapped back
+        if addr == BOOT_ROM_DISABLE_ADDR && value != 0 && self.boot_rom.is_some() {
+            info!("Boot rom finished, unmapping it");
+            self.boot_rom = None;
         }
 
-        self.memory[addr as usize] = value;
+        if self.cgb_mode {
+            match addr {
+                CGB_ADDR_VBK => {
+                    self.vram_bank = (value & 0x01) as usize;
+                    trace!("Switched to VRAM bank {}", self.vram_bank);
+                },
+                CGB_ADDR_SVBK => {
+                    // Bank 0 can't be mapped at 0xD000, selecting it gives bank 1
+                    self.wram_bank = usize::max((value & 0x07) as usize, 1);
+                    trace!("Switched to WRAM bank {}", self.wram_bank);
+                },
+                _ => ()
+            }
+        }
+
+        match self.get_banked_index(addr) {
+            Some((true, index)) => self.vram_bank_1[index] = value,
+            Some((false, index)) => self.wram_banks[index] = value,
+            None => self.memory[addr as usize] = value
+        }
     }
-}
\ No newline at end of file
+}
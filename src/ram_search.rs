@@ -0,0 +1,104 @@
+use crate::consts::*;
+use crate::ram_memory::RamMemory;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchFilter {
+    Equal, // Same as the last snapshot
+    Changed,
+    Increased,
+    Decreased,
+    Value(u8)
+}
+
+impl SearchFilter {
+    pub fn parse(name: &str, value: Option<&str>) -> Option<SearchFilter> {
+        match (name, value) {
+            ("eq", None) | ("equal", None) => Some(SearchFilter::Equal),
+            ("changed", None) => Some(SearchFilter::Changed),
+            ("inc", None) | ("increased", None) => Some(SearchFilter::Increased),
+            ("dec", None) | ("decreased", None) => Some(SearchFilter::Decreased),
+            ("value", Some(value)) => {
+                let parsed = match value.strip_prefix("0x") {
+                    Some(hex) => u8::from_str_radix(hex, 16).ok(),
+                    None => value.parse::<u8>().ok()
+                };
+                parsed.map(SearchFilter::Value)
+            },
+            _ => None
+        }
+    }
+
+    fn matches(&self, previous: u8, current: u8) -> bool {
+        match self {
+            SearchFilter::Equal => current == previous,
+            SearchFilter::Changed => current != previous,
+            SearchFilter::Increased => current > previous,
+            SearchFilter::Decreased => current < previous,
+            SearchFilter::Value(value) => current == *value
+        }
+    }
+}
+
+impl fmt::Display for SearchFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchFilter::Value(value) => write!(f, "value 0x{:02X}", value),
+            _ => write!(f, "{:?}", self)
+        }
+    }
+}
+
+// Narrows down which addresses hold some value (lives, health...) by comparing snapshots of
+// work ram, high ram and cartridge ram between frames
+pub struct RamSearch {
+    candidates: Vec<(u16, u8)> // Address and its value in the last snapshot
+}
+
+impl RamSearch {
+    // Every searchable address is a candidate at first
+    pub fn new(ram_memory: &RamMemory) -> RamSearch {
+        let candidates = RAM_SEARCH_RANGES.iter()
+            .flat_map(|(start, end)| *start..=*end)
+            .map(|addr| (addr, ram_memory.get_addr(addr)))
+            .collect();
+
+        RamSearch { candidates }
+    }
+
+    // Keep the candidates that match the filter and snapshot them again, returns how many are left
+    pub fn filter(&mut self, ram_memory: &RamMemory, filter: SearchFilter) -> usize {
+        self.candidates = self.candidates.iter()
+            .map(|(addr, previous)| (*addr, *previous, ram_memory.get_addr(*addr)))
+            .filter(|(_, previous, current)| filter.matches(*previous, *current))
+            .map(|(addr, _, current)| (addr, current))
+            .collect();
+
+        debug!("RAM search: {} candidates left after {}", self.candidates.len(), filter);
+        return self.candidates.len();
+    }
+
+    pub fn get_candidates(&self) -> &[(u16, u8)] {
+        &self.candidates
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    // "0xC0A2 = 0x03", only the first few when there are too many
+    pub fn get_lines(&self, max_lines: usize) -> Vec<String> {
+        let mut lines: Vec<String> = vec![format!("{} candidates", self.candidates.len())];
+        lines.extend(self.candidates.iter().take(max_lines).map(|(addr, value)| format!("0x{:04X} = 0x{:02X}", addr, value)));
+
+        if self.candidates.len() > max_lines {
+            lines.push("...".to_string());
+        }
+        return lines;
+    }
+}
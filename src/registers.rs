@@ -0,0 +1,89 @@
+use crate::consts::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg8 {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L
+}
+
+impl Reg8 {
+    pub const ALL: [Reg8; 8] = [Reg8::A, Reg8::F, Reg8::B, Reg8::C, Reg8::D, Reg8::E, Reg8::H, Reg8::L];
+
+    // Names as they appear in the opcode table
+    pub fn from_name(name: &str) -> Option<Reg8> {
+        match name.to_uppercase().as_str() {
+            "A" => Some(Reg8::A),
+            "F" => Some(Reg8::F),
+            "B" => Some(Reg8::B),
+            "C" => Some(Reg8::C),
+            "D" => Some(Reg8::D),
+            "E" => Some(Reg8::E),
+            "H" => Some(Reg8::H),
+            "L" => Some(Reg8::L),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg16 {
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC
+}
+
+impl Reg16 {
+    pub const ALL: [Reg16; 6] = [Reg16::AF, Reg16::BC, Reg16::DE, Reg16::HL, Reg16::SP, Reg16::PC];
+
+    pub fn from_name(name: &str) -> Option<Reg16> {
+        match name.to_uppercase().as_str() {
+            "AF" => Some(Reg16::AF),
+            "BC" => Some(Reg16::BC),
+            "DE" => Some(Reg16::DE),
+            "HL" => Some(Reg16::HL),
+            "SP" => Some(Reg16::SP),
+            "PC" => Some(Reg16::PC),
+            _ => None
+        }
+    }
+
+    // The (msb, lsb) registers of a pair, SP and PC are registers of their own
+    pub fn get_halves(&self) -> Option<(Reg8, Reg8)> {
+        match self {
+            Reg16::AF => Some((Reg8::A, Reg8::F)),
+            Reg16::BC => Some((Reg8::B, Reg8::C)),
+            Reg16::DE => Some((Reg8::D, Reg8::E)),
+            Reg16::HL => Some((Reg8::H, Reg8::L)),
+            Reg16::SP | Reg16::PC => None
+        }
+    }
+}
+
+// The bits of the F register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Zero,
+    Sub,
+    HalfCarry,
+    Carry
+}
+
+impl Flag {
+    pub fn get_mask(&self) -> u8 {
+        match self {
+            Flag::Zero => FLAG_ZERO_MASK,
+            Flag::Sub => FLAG_SUB_MASK,
+            Flag::HalfCarry => FLAG_HALF_CARRY_MASK,
+            Flag::Carry => FLAG_CARRY_MASK
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use crate::consts::*;
+use crate::rom_parser::Rom;
+
+use serde_json::{json, Value};
+
+// Human readable cartridge header, used by the info subcommand
+pub struct RomInfo {
+    pub title: String,
+    pub mapper: String,
+    pub cartridge_type: u8,
+    pub rom_size: Option<usize>,
+    pub ram_size: Option<usize>,
+    pub licensee: String,
+    pub old_license_code: u8,
+    pub new_license_code: String,
+    pub destination: String,
+    pub cgb_support: String,
+    pub sgb_support: bool,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub header_checksum_valid: bool,
+    pub global_checksum: u16,
+    pub global_checksum_valid: bool
+}
+
+impl RomInfo {
+    pub fn from_rom(rom: &Rom) -> RomInfo {
+        let cgb_support = match rom.cgb_flag {
+            0xC0 => "CGB only",
+            flag if flag & CGB_FLAG_SUPPORTED != 0 => "CGB enhanced",
+            _ => "DMG only"
+        };
+
+        let destination = match rom.destination_code {
+            0x00 => "Japan",
+            0x01 => "Overseas",
+            _ => "Unknown"
+        };
+
+        RomInfo {
+            title: rom.title.clone(),
+            mapper: rom.get_mapper_name().unwrap_or("Unknown").to_string(),
+            cartridge_type: rom.cartridge_type,
+            rom_size: rom.get_rom_size_bytes(),
+            ram_size: rom.get_ram_size_bytes(),
+            licensee: rom.get_licensee().unwrap_or("Unknown").to_string(),
+            old_license_code: rom.old_license_code,
+            new_license_code: rom.new_license_code.iter().map(|c| *c as char).collect(),
+            destination: destination.to_string(),
+            cgb_support: cgb_support.to_string(),
+            // The SGB functions are only enabled when the old licensee code is 0x33 too
+            sgb_support: rom.sgb_flag == 0x03 && rom.old_license_code == OLD_LICENSEE_CODE_USE_NEW,
+            version: rom.mask_rom_version_number,
+            header_checksum: rom.header_checksum,
+            header_checksum_valid: rom.verify_header_checksum().is_ok(),
+            global_checksum: rom.global_checksum,
+            global_checksum_valid: rom.verify_global_checksum().is_ok()
+        }
+    }
+
+    fn format_size(size: Option<usize>) -> String {
+        match size {
+            Some(0) => "None".to_string(),
+            Some(size) if size >= 1024 => format!("{} KiB ({} bytes)", size / 1024, size),
+            Some(size) => format!("{} bytes", size),
+            None => "Unknown".to_string()
+        }
+    }
+
+    fn format_valid(valid: bool) -> &'static str {
+        if valid { "OK" } else { "BAD" }
+    }
+
+    pub fn get_lines(&self) -> Vec<String> {
+        vec![
+            format!("Title:           {}", self.title),
+            format!("Mapper:          {} (0x{:02X})", self.mapper, self.cartridge_type),
+            format!("ROM size:        {}", Self::format_size(self.rom_size)),
+            format!("RAM size:        {}", Self::format_size(self.ram_size)),
+            format!("Licensee:        {} (old 0x{:02X}, new \"{}\")", self.licensee, self.old_license_code, self.new_license_code),
+            format!("Destination:     {}", self.destination),
+            format!("CGB:             {}", self.cgb_support),
+            format!("SGB:             {}", if self.sgb_support { "Supported" } else { "Not supported" }),
+            format!("Version:         {}", self.version),
+            format!("Header checksum: 0x{:02X} {}", self.header_checksum, Self::format_valid(self.header_checksum_valid)),
+            format!("Global checksum: 0x{:04X} {}", self.global_checksum, Self::format_valid(self.global_checksum_valid))
+        ]
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "title": self.title,
+            "mapper": self.mapper,
+            "cartridge_type": self.cartridge_type,
+            "rom_size": self.rom_size,
+            "ram_size": self.ram_size,
+            "licensee": self.licensee,
+            "old_license_code": self.old_license_code,
+            "new_license_code": self.new_license_code,
+            "destination": self.destination,
+            "cgb_support": self.cgb_support,
+            "sgb_support": self.sgb_support,
+            "version": self.version,
+            "header_checksum": self.header_checksum,
+            "header_checksum_valid": self.header_checksum_valid,
+            "global_checksum": self.global_checksum,
+            "global_checksum_valid": self.global_checksum_valid
+        })
+    }
+}
@@ -0,0 +1,87 @@
+use crate::consts::*;
+
+use flate2::read::GzDecoder;
+use std::fmt;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum RomLoadError {
+    Io(io::Error),
+    Archive(String),
+    NoRomInArchive,
+    EntryNotFound(String),
+    TooLarge
+}
+
+impl fmt::Display for RomLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomLoadError::Io(e) => write!(f, "{}", e),
+            RomLoadError::Archive(e) => write!(f, "Invalid archive ({})", e),
+            RomLoadError::NoRomInArchive => write!(f, "Archive doesn't contain a .gb or .gbc file"),
+            RomLoadError::EntryNotFound(name) => write!(f, "Archive doesn't contain \"{}\"", name),
+            RomLoadError::TooLarge => write!(f, "Decompressed rom is larger than the largest rom ({} bytes)", ROM_MAX_SIZE)
+        }
+    }
+}
+
+impl std::error::Error for RomLoadError {}
+
+// Read a rom from a file, which might be a zip or gzip archive
+pub fn load_rom_file(path: &Path, entry_name: Option<&str>) -> Result<Vec<u8>, RomLoadError> {
+    let content = std::fs::read(path).map_err(RomLoadError::Io)?;
+    return extract_rom(content, entry_name);
+}
+
+// Archives are detected by their magic bytes, anything else is returned as is.
+// entry_name picks the file in a zip archive, by default the first .gb/.gbc file is used
+pub fn extract_rom(content: Vec<u8>, entry_name: Option<&str>) -> Result<Vec<u8>, RomLoadError> {
+    if content.starts_with(&ZIP_MAGIC) || content.starts_with(&ZIP_EMPTY_MAGIC) {
+        return extract_zip(content, entry_name);
+    } else if content.starts_with(&GZIP_MAGIC) {
+        debug!("Decompressing gzip rom");
+        return decompress(GzDecoder::new(content.as_slice()));
+    }
+
+    return Ok(content);
+}
+
+// A tiny archive can expand to gigabytes, stop reading once it's larger than any rom
+fn decompress(reader: impl Read) -> Result<Vec<u8>, RomLoadError> {
+    let mut rom_content: Vec<u8> = Vec::new();
+    reader.take(ROM_MAX_SIZE as u64 + 1).read_to_end(&mut rom_content).map_err(|e| RomLoadError::Archive(e.to_string()))?;
+    if rom_content.len() > ROM_MAX_SIZE {
+        return Err(RomLoadError::TooLarge);
+    }
+
+    return Ok(rom_content);
+}
+
+fn is_rom_file_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    return ROM_FILE_EXTENSIONS.iter().any(|extension| name.ends_with(extension));
+}
+
+fn extract_zip(content: Vec<u8>, entry_name: Option<&str>) -> Result<Vec<u8>, RomLoadError> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(content)).map_err(|e| RomLoadError::Archive(e.to_string()))?;
+
+    // Entries can be named by their full path in the archive or just the file name
+    let names: Vec<String> = archive.file_names().filter_map(|name| name.ok()).map(|name| name.to_string()).collect();
+    let found_name: Option<String> = names.into_iter()
+        .filter(|name| !name.ends_with('/'))
+        .find(|name| match entry_name {
+            Some(entry_name) => name == entry_name || name.rsplit('/').next() == Some(entry_name),
+            None => is_rom_file_name(name)
+        });
+
+    let found_name = match (found_name, entry_name) {
+        (Some(found_name), _) => found_name,
+        (None, Some(entry_name)) => return Err(RomLoadError::EntryNotFound(entry_name.to_string())),
+        (None, None) => return Err(RomLoadError::NoRomInArchive)
+    };
+
+    debug!("Extracting \"{}\" from zip rom", found_name);
+    let entry = archive.by_name(&found_name).map_err(|e| RomLoadError::Archive(e.to_string()))?;
+    return decompress(entry);
+}
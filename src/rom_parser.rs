@@ -1,4 +1,30 @@
 use crate::consts::*;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    TooShort { length: usize },
+    HeaderChecksum { expected: u8, computed: u8 },
+    GlobalChecksum { expected: u16, computed: u16 },
+    SizeMismatch { header_size: usize, actual_size: usize },
+    UnsupportedCartridgeType(u8),
+    UnknownRomSize(u8)
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::TooShort { length } => write!(f, "Rom is too short to have a header ({} bytes)", length),
+            RomError::HeaderChecksum { expected, computed } => write!(f, "Bad header checksum (header says 0x{:02X}, computed 0x{:02X})", expected, computed),
+            RomError::GlobalChecksum { expected, computed } => write!(f, "Bad global checksum (header says 0x{:04X}, computed 0x{:04X})", expected, computed),
+            RomError::SizeMismatch { header_size, actual_size } => write!(f, "Rom size doesn't match the header (header says {} bytes, got {} bytes)", header_size, actual_size),
+            RomError::UnsupportedCartridgeType(cartridge_type) => write!(f, "Unsupported cartridge type (0x{:02X})", cartridge_type),
+            RomError::UnknownRomSize(rom_size) => write!(f, "Unknown rom size (0x{:02X})", rom_size)
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
 
 #[readonly::make]
 pub struct Rom {
@@ -8,8 +34,8 @@ pub struct Rom {
     pub new_license_code: [u8; 2],
     pub sgb_flag: u8,
     pub cartridge_type: u8,
-    rom_size: u8,
-    ram_size: u8,
+    pub rom_size: u8,
+    pub ram_size: u8,
     pub destination_code: u8,
     pub old_license_code: u8,
     pub mask_rom_version_number: u8,
@@ -19,7 +45,22 @@ pub struct Rom {
 }
 
 impl Rom {
-    pub fn create_from_bytes(rom_content: Vec<u8>) -> Rom {
+    // Parse and validate the whole rom, checksums included
+    pub fn create_from_bytes(rom_content: Vec<u8>) -> Result<Rom, RomError> {
+        let rom = Self::parse(rom_content)?;
+        rom.verify_cartridge_type()?;
+        rom.verify_checksums()?;
+
+        return Ok(rom);
+    }
+
+    // Parse the header without checking the checksums, real hardware only cares about the header checksum
+    // (and only when running the boot rom) so plenty of homebrew gets the global one wrong
+    pub fn parse(rom_content: Vec<u8>) -> Result<Rom, RomError> {
+        if rom_content.len() < ROM_HEADER_END {
+            return Err(RomError::TooShort { length: rom_content.len() });
+        }
+
         let mut title:String = "".to_string();
         for c in &rom_content[0x134..0x0143+1] {
             if *c == 0x00 {
@@ -28,67 +69,158 @@ impl Rom {
             title.push(*c as char);
         }
 
-        let new_licnse_code: [u8; 2] = 
-            [*rom_content.get(0x144).expect("Invalid rom structure (first new_licnse_code)"),
-             *rom_content.get(0x145).expect("Invalid rom structure (second new_licnse_code)")];
+        let new_licnse_code: [u8; 2] = [rom_content[0x144], rom_content[0x145]];
 
-        let global_checksum: u16 = 
-            (*rom_content.get(0x14E).expect("Invalid rom structure (first global_checksum)") as u16) << 8 |
-            (*rom_content.get(0x14F).expect("Invalid rom structure (second global_checksum)") as u16);
+        let global_checksum: u16 = (rom_content[0x14E] as u16) << 8 | (rom_content[0x14F] as u16);
 
         // Validate cartridge type
-        let cartridge_type: u8 = *rom_content.get(0x147).expect("Invalid rom structure (cartridge_type)");
-        // if cartridge_type != CARTRIDGE_TYPE_ROM_ONLY {
-        //     panic!("Unsupported cartridge type (0x{:02X})", cartridge_type);
-        // }
-
-        // Validate rom size
-        let rom_size: u8 = *rom_content.get(0x148).expect("Invalid rom structure (rom_size)");
-        // if rom_size != CARTRIDGE_ROM_SIZE_NO_BANKS {
-        //     panic!("Unsupported rom size (0x{:02X})", rom_size);
-        // }
+        let cartridge_type: u8 = rom_content[0x147];
+        if !CARTRIDGE_TYPES.iter().any(|(code, _)| *code == cartridge_type) {
+            return Err(RomError::UnsupportedCartridgeType(cartridge_type));
+        }
 
         // Validate rom size
-        let ram_size: u8 = *rom_content.get(0x149).expect("Invalid rom structure (ram_size)");
-        if ram_size != CARTRIDGE_RAM_SIZE_NONE {
-            panic!("Unsupported ram size (0x{:02X})", ram_size);
+        let rom_size: u8 = rom_content[0x148];
+        let header_size = Self::decode_rom_size(rom_size).ok_or(RomError::UnknownRomSize(rom_size))?;
+        if header_size != rom_content.len() {
+            return Err(RomError::SizeMismatch { header_size, actual_size: rom_content.len() });
         }
 
-        Rom {
+        let ram_size: u8 = rom_content[0x149];
+
+        Ok(Rom {
             title: title.clone(),
-            manufacturer_code: rom_content[0x13F..0x142+1].try_into().expect("Invalid rom structure (manufacturer_code)"),
-            cgb_flag: *rom_content.get(0x143).expect("Invalid rom structure (cgb_flag)"),
+            manufacturer_code: rom_content[0x13F..0x142+1].try_into().unwrap(),
+            cgb_flag: rom_content[0x143],
             new_license_code: new_licnse_code,
-            sgb_flag: *rom_content.get(0x146).expect("Invalid rom structure (sgb_flag)"),
-            cartridge_type: cartridge_type,
-            rom_size: rom_size,
-            ram_size: ram_size,
-            destination_code: *rom_content.get(0x14A).expect("Invalid rom structure (destination_code)"),
-            old_license_code: *rom_content.get(0x14B).expect("Invalid rom structure (old_license_code)"),
-            mask_rom_version_number: *rom_content.get(0x14C).expect("Invalid rom structure (mask_rom_version_number)"),
-            header_checksum: *rom_content.get(0x14D).expect("Invalid rom structure (header_checksum)"),
-            global_checksum: global_checksum,
-            data: rom_content.clone()
+            sgb_flag: rom_content[0x146],
+            cartridge_type,
+            rom_size,
+            ram_size,
+            destination_code: rom_content[0x14A],
+            old_license_code: rom_content[0x14B],
+            mask_rom_version_number: rom_content[0x14C],
+            header_checksum: rom_content[0x14D],
+            global_checksum,
+            data: rom_content
+        })
+    }
+
+    // Parsing accepts every known cartridge type so they can be inspected, only roms without a mapper
+    // can run (the upper banks of a banked rom would end up over vram and wram)
+    pub fn verify_cartridge_type(&self) -> Result<(), RomError> {
+        if !SUPPORTED_CARTRIDGE_TYPES.contains(&self.cartridge_type) {
+            return Err(RomError::UnsupportedCartridgeType(self.cartridge_type));
+        }
+
+        return Ok(());
+    }
+
+    // Rom size in bytes from the header code
+    pub fn decode_rom_size(rom_size: u8) -> Option<usize> {
+        match rom_size {
+            0x00..=0x08 => Some(CARTRIDGE_ROM_SIZE_DEFAULT << rom_size),
+            0x52 => Some(72 * ROM_BANK_SIZE),
+            0x53 => Some(80 * ROM_BANK_SIZE),
+            0x54 => Some(96 * ROM_BANK_SIZE),
+            _ => None
+        }
+    }
+
+    // External ram size in bytes from the header code (MBC2 has its ram built in and says 0)
+    pub fn decode_ram_size(ram_size: u8) -> Option<usize> {
+        match ram_size {
+            0x00 => Some(0),
+            0x01 => Some(2 * 1024),
+            0x02 => Some(8 * 1024),
+            0x03 => Some(32 * 1024),
+            0x04 => Some(128 * 1024),
+            0x05 => Some(64 * 1024),
+            _ => None
+        }
+    }
+
+    pub fn get_rom_size_bytes(&self) -> Option<usize> {
+        Self::decode_rom_size(self.rom_size)
+    }
+
+    pub fn get_ram_size_bytes(&self) -> Option<usize> {
+        Self::decode_ram_size(self.ram_size)
+    }
+
+    pub fn get_mapper_name(&self) -> Option<&'static str> {
+        CARTRIDGE_TYPES.iter().find(|(code, _)| *code == self.cartridge_type).map(|(_, name)| *name)
+    }
+
+    // Newer games put the licensee in the new (ascii) code and set the old one to 0x33
+    pub fn get_licensee(&self) -> Option<&'static str> {
+        if self.old_license_code == OLD_LICENSEE_CODE_USE_NEW {
+            let new_license_code: String = self.new_license_code.iter().map(|c| *c as char).collect();
+            return NEW_LICENSEE_CODES.iter().find(|(code, _)| *code == new_license_code).map(|(_, name)| *name);
+        }
+
+        OLD_LICENSEE_CODES.iter().find(|(code, _)| *code == self.old_license_code).map(|(_, name)| *name)
+    }
+
+    // x = x - byte - 1 over 0x0134-0x014C, the boot rom locks up if this doesn't match
+    pub fn compute_header_checksum(rom_content: &[u8]) -> u8 {
+        let mut checksum: u8 = 0;
+        for b in &rom_content[ROM_HEADER_CHECKSUM_START..=ROM_HEADER_CHECKSUM_END] {
+            checksum = checksum.wrapping_sub(*b).wrapping_sub(1);
+        }
+        return checksum;
+    }
+
+    // Sum of every byte except the global checksum itself
+    pub fn compute_global_checksum(rom_content: &[u8]) -> u16 {
+        let mut checksum: u16 = 0;
+        for (addr, b) in rom_content.iter().enumerate() {
+            if addr != ROM_GLOBAL_CHECKSUM_ADDR && addr != ROM_GLOBAL_CHECKSUM_ADDR + 1 {
+                checksum = checksum.wrapping_add(*b as u16);
+            }
+        }
+        return checksum;
+    }
+
+    pub fn verify_header_checksum(&self) -> Result<(), RomError> {
+        let computed = Self::compute_header_checksum(&self.data);
+        if computed != self.header_checksum {
+            return Err(RomError::HeaderChecksum { expected: self.header_checksum, computed });
+        }
+        return Ok(());
+    }
+
+    pub fn verify_global_checksum(&self) -> Result<(), RomError> {
+        let computed = Self::compute_global_checksum(&self.data);
+        if computed != self.global_checksum {
+            return Err(RomError::GlobalChecksum { expected: self.global_checksum, computed });
         }
+        return Ok(());
     }
 
-    // pub fn create_test_rom() -> Rom {
-    //     Rom {
-    //         title: "TEST".to_string(),
-    //         manufacturer_code: [0x13, 0x37, 0x23, 0x07],
-    //         cgb_flag: 0xFF,
-    //         new_license_code: [0xAB, 0xCD],
-    //         sgb_flag: 0xFF,
-    //         cartridge_type: 0xFF,
-    //         rom_size: 0x00,
-    //         ram_size: 0x00,
-    //         destination_code: 0x80,
-    //         old_license_code: 0x00,
-    //         mask_rom_version_number: 0x00,
-    //         header_checksum: 0x00,
-    //         global_checksum: 0x0000,
-    //         data: Vec::new()
-    //     }
-    // }
-
-}
\ No newline at end of file
+    pub fn verify_checksums(&self) -> Result<(), RomError> {
+        self.verify_header_checksum()?;
+        self.verify_global_checksum()?;
+        return Ok(());
+    }
+
+    pub fn create_test_rom() -> Rom {
+        Rom {
+            title: "TEST".to_string(),
+            manufacturer_code: [0x13, 0x37, 0x23, 0x07],
+            cgb_flag: 0xFF,
+            new_license_code: [0xAB, 0xCD],
+            sgb_flag: 0xFF,
+            cartridge_type: 0xFF,
+            rom_size: 0x00,
+            ram_size: 0x00,
+            destination_code: 0x80,
+            old_license_code: 0x00,
+            mask_rom_version_number: 0x00,
+            header_checksum: 0x00,
+            global_checksum: 0x0000,
+            data: Vec::new()
+        }
+    }
+
+}
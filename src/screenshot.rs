@@ -0,0 +1,211 @@
+use crate::consts::*;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum ScreenshotError {
+    Io(io::Error),
+    Png(String),
+    SizeMismatch { expected: (usize, usize), actual: (usize, usize) },
+    HashMismatch { expected: String, actual: String },
+    PixelMismatch { differing_pixels: usize }
+}
+
+impl fmt::Display for ScreenshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScreenshotError::Io(e) => write!(f, "{}", e),
+            ScreenshotError::Png(e) => write!(f, "Invalid png ({})", e),
+            ScreenshotError::SizeMismatch { expected, actual } => write!(f, "Screenshot size doesn't match the reference (expected {}x{}, got {}x{})", expected.0, expected.1, actual.0, actual.1),
+            ScreenshotError::HashMismatch { expected, actual } => write!(f, "Screenshot hash doesn't match the reference (expected {}, got {})", expected, actual),
+            ScreenshotError::PixelMismatch { differing_pixels } => write!(f, "Screenshot doesn't match the reference ({} pixels differ)", differing_pixels)
+        }
+    }
+}
+
+impl std::error::Error for ScreenshotError {}
+
+impl From<io::Error> for ScreenshotError {
+    fn from(e: io::Error) -> ScreenshotError {
+        ScreenshotError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for ScreenshotError {
+    fn from(e: png::EncodingError) -> ScreenshotError {
+        ScreenshotError::Png(e.to_string())
+    }
+}
+
+impl From<png::DecodingError> for ScreenshotError {
+    fn from(e: png::DecodingError) -> ScreenshotError {
+        ScreenshotError::Png(e.to_string())
+    }
+}
+
+// What a screenshot is checked against, a png of the expected screen or just its hash
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reference {
+    Png(PathBuf),
+    Hash(String)
+}
+
+// 0xRRGGBB pixels, like the ppu's buffer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screenshot {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>
+}
+
+impl Screenshot {
+    // The ppu's whole SCREEN_WIDTH x SCREEN_HEIGHT buffer
+    pub fn from_framebuffer(framebuffer: &[u32]) -> Screenshot {
+        Screenshot {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            pixels: framebuffer.to_vec()
+        }
+    }
+
+    // The top left corner, where the ppu draws what's on the LCD
+    pub fn crop(&self, width: usize, height: usize) -> Screenshot {
+        let width = width.min(self.width);
+        let height = height.min(self.height);
+        let pixels: Vec<u32> = self.pixels.chunks(self.width).take(height).flat_map(|row| row[..width].to_vec()).collect();
+
+        return Screenshot { width, height, pixels };
+    }
+
+    // Every pixel becomes a factor by factor square, so the pixels stay sharp
+    pub fn upscale(&self, factor: usize) -> Screenshot {
+        let factor = factor.max(1);
+        let mut pixels: Vec<u32> = Vec::with_capacity(self.pixels.len() * factor * factor);
+        for row in self.pixels.chunks(self.width) {
+            let scaled_row: Vec<u32> = row.iter().flat_map(|pixel| std::iter::repeat_n(*pixel, factor)).collect();
+            for _ in 0..factor {
+                pixels.extend_from_slice(&scaled_row);
+            }
+        }
+
+        return Screenshot { width: self.width * factor, height: self.height * factor, pixels };
+    }
+
+    // CRC32 of the pixels, short enough to keep in a test
+    pub fn get_hash(&self) -> String {
+        let mut hasher = crc32fast::Hasher::new();
+        for pixel in &self.pixels {
+            hasher.update(&pixel.to_le_bytes()[0..3]);
+        }
+        return format!("{:08x}", hasher.finalize());
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), ScreenshotError> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = self.pixels.iter().flat_map(|pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, *pixel as u8]).collect();
+        encoder.write_header()?.write_image_data(&data)?;
+        return Ok(());
+    }
+
+    // Any 8 bit (or smaller) png, alpha is dropped
+    pub fn load_png(path: &Path) -> Result<Screenshot, ScreenshotError> {
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+
+        let mut data: Vec<u8> = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data)?;
+        let channels = info.color_type.samples();
+
+        let pixels: Vec<u32> = data[..info.buffer_size()].chunks(channels).map(|pixel| {
+            match pixel.len() {
+                1 | 2 => u32::from_be_bytes([0, pixel[0], pixel[0], pixel[0]]),
+                _ => u32::from_be_bytes([0, pixel[0], pixel[1], pixel[2]])
+            }
+        }).collect();
+
+        return Ok(Screenshot {
+            width: info.width as usize,
+            height: info.height as usize,
+            pixels
+        });
+    }
+
+    // The number of pixels that differ and an image of them, red where they differ and
+    // a faded copy of this screenshot where they don't
+    pub fn diff(&self, other: &Screenshot) -> (usize, Screenshot) {
+        let mut differing_pixels: usize = 0;
+        let pixels: Vec<u32> = self.pixels.iter().zip(other.pixels.iter()).map(|(pixel, other_pixel)| {
+            if pixel == other_pixel {
+                (pixel >> 2) & 0x3F3F3F | 0xC0C0C0
+            } else {
+                differing_pixels += 1;
+                SCREENSHOT_DIFF_COLOR
+            }
+        }).collect();
+
+        return (differing_pixels, Screenshot { width: self.width, height: self.height, pixels });
+    }
+
+    // On a mismatch the diff image is written to diff_path, or this screenshot when only a hash is known
+    pub fn compare(&self, reference: &Reference, diff_path: &Path) -> Result<(), ScreenshotError> {
+        match reference {
+            Reference::Hash(expected) => {
+                let actual = self.get_hash();
+                if !actual.eq_ignore_ascii_case(expected) {
+                    self.save_png(diff_path)?;
+                    return Err(ScreenshotError::HashMismatch { expected: expected.clone(), actual });
+                }
+            },
+            Reference::Png(path) => {
+                let expected = Screenshot::load_png(path)?;
+                if (expected.width, expected.height) != (self.width, self.height) {
+                    return Err(ScreenshotError::SizeMismatch {
+                        expected: (expected.width, expected.height),
+                        actual: (self.width, self.height)
+                    });
+                }
+
+                let (differing_pixels, diff) = self.diff(&expected);
+                if differing_pixels > 0 {
+                    diff.save_png(diff_path)?;
+                    return Err(ScreenshotError::PixelMismatch { differing_pixels });
+                }
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+// <prefix>_<YYYYMMDD>_<HHMMSS>_<millis>.png in the directory, the time is UTC
+pub fn get_timestamped_path(dir: &Path, prefix: &str) -> PathBuf {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    return dir.join(format!("{}_{}.png", prefix, format_timestamp(since_epoch.as_millis() as u64)));
+}
+
+// Days to a civil date (http://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+pub(crate) fn format_timestamp(millis: u64) -> String {
+    let seconds = millis / 1000;
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    let time_of_day = seconds % 86400;
+    return format!("{:04}{:02}{:02}_{:02}{:02}{:02}_{:03}", year, month, day,
+        time_of_day / 3600, (time_of_day / 60) % 60, time_of_day % 60, millis % 1000);
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Labels loaded from a RGBDS .sym file, each line is "BANK:ADDR LABEL" (both in hex), comments start with ';'
+#[derive(Clone)]
+pub struct SymbolTable {
+    labels: HashMap<(u16, u16), String>,
+    addrs: HashMap<String, (u16, u16)>,
+    sorted_labels: Vec<(u16, u16, String)>
+}
+
+impl SymbolTable {
+    pub fn parse(content: &str) -> SymbolTable {
+        let mut symbols = SymbolTable {
+            labels: HashMap::new(),
+            addrs: HashMap::new(),
+            sorted_labels: Vec::new()
+        };
+
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.split(';').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parsed = line.split_once(char::is_whitespace).and_then(|(location, name)| {
+                let (bank, addr) = location.split_once(':')?;
+                Some((
+                    u16::from_str_radix(bank, 16).ok()?,
+                    u16::from_str_radix(addr, 16).ok()?,
+                    name.trim().to_string()
+                ))
+            });
+
+            match parsed {
+                Some((bank, addr, name)) => {
+                    // Keep the first label for an address, those are usually the global ones
+                    symbols.labels.entry((bank, addr)).or_insert(name.clone());
+                    symbols.addrs.insert(name.clone(), (bank, addr));
+                    symbols.sorted_labels.push((bank, addr, name));
+                },
+                None => warn!("SYMBOLS: Ignoring invalid line {} (\"{}\")", line_number + 1, line)
+            }
+        }
+
+        symbols.sorted_labels.sort_by_key(|(bank, addr, _)| (*bank, *addr));
+        return symbols;
+    }
+
+    pub fn load(path: &Path) -> io::Result<SymbolTable> {
+        let content = std::fs::read_to_string(path)?;
+        let symbols = Self::parse(&content);
+        info!("Loaded {} symbols from \"{}\"", symbols.addrs.len(), path.display());
+
+        Ok(symbols)
+    }
+
+    // RGBDS names the symbol file after the rom (game.gb -> game.sym)
+    pub fn find_sym_file(rom_path: &Path) -> Option<PathBuf> {
+        let sym_path = rom_path.with_extension("sym");
+        if sym_path.is_file() {
+            Some(sym_path)
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    // The bank currently mapped at addr. There is no MBC, so the switchable areas are always bank 1
+    fn get_mapped_bank(addr: u16) -> u16 {
+        match addr {
+            0x4000..=0x7FFF => 1,
+            0xD000..=0xDFFF => 1,
+            _ => 0
+        }
+    }
+
+    // Label placed exactly at addr
+    pub fn get_label(&self, addr: u16) -> Option<&str> {
+        if let Some(label) = self.labels.get(&(Self::get_mapped_bank(addr), addr)) {
+            return Some(label);
+        }
+
+        // The sym file might not agree with us on which bank is mapped
+        self.sorted_labels.iter()
+            .find(|(_, label_addr, _)| *label_addr == addr)
+            .map(|(_, _, name)| name.as_str())
+    }
+
+    // Start of the memory area addr is in, labels from other areas are never used as a base
+    fn get_region_start(addr: u16) -> Option<u16> {
+        match addr {
+            0x0000..=0x3FFF => Some(0x0000),
+            0x4000..=0x7FFF => Some(0x4000),
+            0x8000..=0x9FFF => Some(0x8000),
+            0xA000..=0xBFFF => Some(0xA000),
+            0xC000..=0xCFFF => Some(0xC000),
+            0xD000..=0xDFFF => Some(0xD000),
+            0xFF80..=0xFFFE => Some(0xFF80),
+            _ => None
+        }
+    }
+
+    // Closest label at or before addr, and the offset of addr from it
+    pub fn get_nearest_label(&self, addr: u16) -> Option<(&str, u16)> {
+        let bank = Self::get_mapped_bank(addr);
+        let region_start = Self::get_region_start(addr)?;
+
+        self.sorted_labels.iter()
+            .rev()
+            .find(|(label_bank, label_addr, _)| *label_bank == bank && *label_addr <= addr && *label_addr >= region_start)
+            .map(|(_, label_addr, _)| (self.labels[&(bank, *label_addr)].as_str(), addr - label_addr))
+    }
+
+    pub fn get_addr(&self, label: &str) -> Option<u16> {
+        self.addrs.get(label).map(|(_, addr)| *addr)
+    }
+
+    // "Main+0x04" or just the address when there is no label close to it
+    pub fn format_addr(&self, addr: u16) -> String {
+        match self.get_nearest_label(addr) {
+            Some((label, 0)) => format!("0x{:04X} <{}>", addr, label),
+            Some((label, offset)) => format!("0x{:04X} <{}+0x{:X}>", addr, label, offset),
+            None => format!("0x{:04X}", addr)
+        }
+    }
+
+    // Breakpoint targets can be either labels or hex addresses
+    pub fn resolve(symbols: Option<&SymbolTable>, target: &str) -> Option<u16> {
+        if let Some(addr) = symbols.and_then(|symbols| symbols.get_addr(target)) {
+            return Some(addr);
+        }
+
+        u16::from_str_radix(target.trim_start_matches("0x").trim_start_matches("0X"), 16).ok()
+    }
+}
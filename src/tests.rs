@@ -1,56 +1,161 @@
 #[cfg(test)]
 mod rom_parser_tests {
-    use crate::rom_parser::Rom;
-    use std::io::Read;
-    use std::fs::File;
+    use crate::rom_parser::{Rom, RomError};
+    use crate::rom_info::RomInfo;
+    use crate::gameboy::GameBoy;
 
     #[test]
-    fn create_rom_file() {
-        let rom_file: File = File::open("roms/bully.gb").expect("Failed opening rom file");
+    fn validate_rom_values() {
+        let rom_content: Vec<u8> = create_bully_rom_content();
 
-        let rom_content: Vec<u8> = rom_file.bytes().map(|value| {
-            value.expect("Failed reading rom file")
-        }).collect();
+        let test_rom:Rom = Rom::create_from_bytes(rom_content).unwrap();
+
+        assert_eq!(test_rom.title, "BULLYGB");
+        assert_eq!(test_rom.manufacturer_code, [0,0,0,0]);
+        assert_eq!(test_rom.cgb_flag, 0x80);
+    }
 
-        let _:Rom = Rom::create_from_bytes(rom_content);
+    // 32KB rom only cartridge with a valid header
+    fn create_rom_content() -> Vec<u8> {
+        return create_rom_content_with(b"TEST", 0x00);
+    }
+
+    // The header of bully.gb (a CGB compatible test rom), without needing the rom itself
+    pub(super) fn create_bully_rom_content() -> Vec<u8> {
+        return create_rom_content_with(b"BULLYGB", 0x80);
+    }
+
+    fn create_rom_content_with(title: &[u8], cgb_flag: u8) -> Vec<u8> {
+        let mut rom_content: Vec<u8> = vec![0x00; 0x8000];
+        rom_content[0x134..0x134 + title.len()].copy_from_slice(title);
+        rom_content[0x143] = cgb_flag;
+        rom_content[0x14D] = Rom::compute_header_checksum(&rom_content);
+
+        let global_checksum = Rom::compute_global_checksum(&rom_content);
+        rom_content[0x14E] = (global_checksum >> 8) as u8;
+        rom_content[0x14F] = global_checksum as u8;
+        return rom_content;
     }
 
     #[test]
-    fn validate_rom_values() {
-        let rom_file: File = File::open("roms/bully.gb").expect("Failed opening rom file");
+    fn test_valid_rom() {
+        let rom = Rom::create_from_bytes(create_rom_content()).unwrap();
+        assert_eq!(rom.title, "TEST");
+        assert_eq!(rom.header_checksum, 0xA7);
+    }
 
-        let rom_content: Vec<u8> = rom_file.bytes().map(|value| {
-            value.expect("Failed reading rom file")
-        }).collect();
+    #[test]
+    fn test_too_short() {
+        assert_eq!(Rom::create_from_bytes(vec![0x00; 0x14F]).err(), Some(RomError::TooShort { length: 0x14F }));
+        assert_eq!(Rom::create_from_bytes(Vec::new()).err(), Some(RomError::TooShort { length: 0 }));
+    }
 
-        let test_rom:Rom = Rom::create_from_bytes(rom_content);
+    #[test]
+    fn test_bad_checksums() {
+        let mut rom_content = create_rom_content();
+        rom_content[0x14D] = 0x42;
+        assert_eq!(Rom::create_from_bytes(rom_content).err(), Some(RomError::HeaderChecksum { expected: 0x42, computed: 0xA7 }));
 
-        assert_eq!(test_rom.title, "BULLYGB");
-        assert_eq!(test_rom.manufacturer_code, [0,0,0,0]);
-        assert_eq!(test_rom.cgb_flag, 0x80);
+        let mut rom_content = create_rom_content();
+        rom_content[0x7FFF] = 0x01;
+        let expected = Rom::compute_global_checksum(&create_rom_content());
+        assert_eq!(Rom::create_from_bytes(rom_content.clone()).err(), Some(RomError::GlobalChecksum { expected, computed: expected + 1 }));
+
+        // Parsing alone doesn't care about checksums
+        assert!(Rom::parse(rom_content).is_ok());
+    }
+
+    #[test]
+    fn test_size_mismatch() {
+        let mut rom_content = create_rom_content();
+        rom_content.truncate(0x4000);
+        assert_eq!(Rom::parse(rom_content).err(), Some(RomError::SizeMismatch { header_size: 0x8000, actual_size: 0x4000 }));
+
+        let mut rom_content = create_rom_content();
+        rom_content[0x148] = 0x01;
+        assert_eq!(Rom::parse(rom_content).err(), Some(RomError::SizeMismatch { header_size: 0x10000, actual_size: 0x8000 }));
+
+        let mut rom_content = create_rom_content();
+        rom_content[0x148] = 0x42;
+        assert_eq!(Rom::parse(rom_content).err(), Some(RomError::UnknownRomSize(0x42)));
+    }
+
+    #[test]
+    fn test_unsupported_cartridge_type() {
+        let mut rom_content = create_rom_content();
+        rom_content[0x147] = 0x04;
+        assert_eq!(Rom::parse(rom_content).err(), Some(RomError::UnsupportedCartridgeType(0x04)));
+
+        // MBC1 is a known type, it parses (for info) but can't be loaded
+        let mut rom_content = create_rom_content();
+        rom_content[0x147] = 0x01;
+        let rom = Rom::parse(rom_content.clone()).unwrap();
+        assert_eq!(rom.get_mapper_name(), Some("MBC1"));
+        assert_eq!(rom.verify_cartridge_type(), Err(RomError::UnsupportedCartridgeType(0x01)));
+        assert_eq!(Rom::create_from_bytes(rom_content.clone()).err(), Some(RomError::UnsupportedCartridgeType(0x01)));
+        assert_eq!(GameBoy::load(rom_content).err(), Some(RomError::UnsupportedCartridgeType(0x01)));
+
+        let mut rom_content = create_rom_content();
+        rom_content[0x147] = 0x08;
+        assert!(Rom::parse(rom_content).unwrap().verify_cartridge_type().is_ok());
+    }
+
+    #[test]
+    fn test_rom_info() {
+        let mut rom_content = create_rom_content();
+        rom_content[0x143] = 0x80;
+        rom_content[0x144..0x146].copy_from_slice(b"01");
+        rom_content[0x146] = 0x03;
+        rom_content[0x147] = 0x03;
+        rom_content[0x149] = 0x03;
+        rom_content[0x14A] = 0x01;
+        rom_content[0x14B] = 0x33;
+
+        let info = RomInfo::from_rom(&Rom::parse(rom_content).unwrap());
+        assert_eq!(info.mapper, "MBC1+RAM+BATTERY");
+        assert_eq!(info.rom_size, Some(32 * 1024));
+        assert_eq!(info.ram_size, Some(32 * 1024));
+        assert_eq!(info.licensee, "Nintendo R&D1");
+        assert_eq!(info.destination, "Overseas");
+        assert_eq!(info.cgb_support, "CGB enhanced");
+        assert!(info.sgb_support);
+        assert!(!info.header_checksum_valid);
+        assert!(!info.global_checksum_valid);
+
+        let json = info.to_json();
+        assert_eq!(json["mapper"], "MBC1+RAM+BATTERY");
+        assert_eq!(json["ram_size"], 32768);
+        assert_eq!(json["header_checksum_valid"], false);
+
+        // Old licensee code
+        let mut rom_content = create_rom_content();
+        rom_content[0x14B] = 0x01;
+        let info = RomInfo::from_rom(&Rom::parse(rom_content).unwrap());
+        assert_eq!(info.licensee, "Nintendo");
+        assert_eq!(info.mapper, "ROM ONLY");
+        assert_eq!(info.ram_size, Some(0));
+        assert_eq!(info.cgb_support, "DMG only");
+        assert!(!info.sgb_support);
+        assert_eq!(info.get_lines()[2], "ROM size:        32 KiB (32768 bytes)");
     }
 }
 
 
 #[cfg(test)]
 mod cpu_tests {
-    use crate::cpu::{CPU, get_opcodes};
+    use crate::cpu::{CPU, CpuError, CpuErrorReason, get_opcodes};
+    use crate::joypad::Button;
+    use crate::registers::{Reg8, Reg16, Flag};
+    use crate::bus::{Bus, MemoryBus};
     use crate::rom_parser::Rom;
     use crate::ram_memory::{RamMemory};
-    
-    use std::fs::File;
-    use std::io::Read;
-    use simplelog::*;
+    use crate::model::Model;
 
     #[test]
     fn test_ram_memory() {
-        let rom_file: File = File::open("roms/bully.gb").expect("Failed opening rom file");
-
-        let rom_content: Vec<u8> = rom_file.bytes().map(|value| {
-            value.expect("Failed reading rom file")
-        }).collect();
+        let rom_content: Vec<u8> = super::rom_parser_tests::create_bully_rom_content();
 
-        let rom: Rom = Rom::create_from_bytes(rom_content);
+        let rom: Rom = Rom::create_from_bytes(rom_content).unwrap();
         let ram: RamMemory = RamMemory::init_from_rom(&rom);
 
         assert_eq!(ram.get_addr(0x0147), 0x00); // Cartridge Type
@@ -67,7 +172,7 @@ mod cpu_tests {
     #[test]
     fn test_cpu_jp_ld_cp() {
         let test_rom = Rom::create_test_rom();
-        let ram_memory = &mut RamMemory::init_from_rom(&test_rom);
+        let mut ram_memory = RamMemory::init_from_rom(&test_rom);
 
         // Jump instruction setup
         //      Jump to 0x0200
@@ -85,22 +190,1710 @@ mod cpu_tests {
         ram_memory.set_addr(0x0202, 0xFE);
         ram_memory.set_addr(0x0203, 0x0F);
 
-        let mut cpu: CPU = CPU::init_with_ram_ppu(ram_memory);
+        let mut bus = MemoryBus::new(ram_memory);
+        let mut cpu: CPU = CPU::init(false);
 
         // Check Jump
-        cpu.execute_instruction();
+        cpu.execute_instruction(&mut bus).unwrap();
         assert_eq!(cpu.get_program_counter(), 0x0200);
 
         // Check Load
-        cpu.execute_instruction();
-        assert_eq!(cpu.get_register("A".to_string()), 0x11);
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_register(Reg8::A), 0x11);
 
         // Check Compare
-        cpu.execute_instruction();
-        assert_eq!(cpu.get_sub_flag(), true);
-        assert_eq!(cpu.get_half_carry_flag(), true);
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert!(cpu.get_sub_flag());
+        assert!(cpu.get_half_carry_flag());
 
         // Check Compare
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_cpu_error() {
+        let mut ram_memory = RamMemory::init_from_rom(&Rom::create_test_rom());
+
+        // NOP / DAA
+        ram_memory.set_addr(0x0100, 0x00);
+        ram_memory.set_addr(0x0101, 0x27);
+
+        let mut bus = MemoryBus::new(ram_memory);
+        let mut cpu: CPU = CPU::init(false);
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        let error = cpu.execute_instruction(&mut bus).unwrap_err();
+        assert_eq!(error, CpuError {
+            pc: 0x0101,
+            opcode: 0x27,
+            reason: CpuErrorReason::Unimplemented("Opcode name (\"DAA\")".to_string())
+        });
+        assert_eq!(error.to_string(), "CPU error at 0x0101 (opcode 0x27): Unimplemented (Opcode name (\"DAA\"))");
+
+        // Nothing moved, so it fails the same way again
+        assert_eq!(cpu.get_program_counter(), 0x0101);
+        assert!(cpu.execute_instruction(&mut bus).is_err());
+    }
+
+    #[test]
+    fn test_cpu_illegal_opcode_locks_up() {
+        for opcode in [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD] {
+            let mut ram_memory = RamMemory::init_from_rom(&Rom::create_test_rom());
+            ram_memory.set_addr(0x0100, opcode);
+            ram_memory.set_addr(0x0101, 0x3C); // INC A, never reached
+
+            let mut bus = MemoryBus::new(ram_memory);
+            let mut cpu: CPU = CPU::init(false);
+
+            for _ in 0..3 {
+                cpu.execute_instruction(&mut bus).unwrap();
+            }
+
+            assert!(cpu.is_locked_up(), "0x{:02X} should lock up", opcode);
+            assert_eq!(cpu.get_program_counter(), 0x0100);
+            assert_eq!(cpu.get_register(Reg8::A), 0x00);
+
+            // Time still goes on
+            assert_eq!(bus.get_cycle_counter(), 12);
+        }
+    }
+
+    fn create_cpu(program: &[u8]) -> (CPU, MemoryBus) {
+        let mut ram_memory = RamMemory::init_from_rom(&Rom::create_test_rom());
+        for (i, b) in program.iter().enumerate() {
+            ram_memory.set_addr(0x0100 + i as u16, *b);
+        }
+
+        return (CPU::init(false), MemoryBus::new(ram_memory));
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut cpu: CPU = CPU::init(false);
+
+        for (i, reg) in Reg8::ALL.iter().enumerate() {
+            cpu.set_register(*reg, 0x11 * (i as u8 + 1));
+        }
+        assert_eq!(cpu.get_register(Reg8::A), 0x11);
+        assert_eq!(cpu.get_register(Reg8::F), 0x20); // Low nibble masked
+        assert_eq!(cpu.get_register(Reg8::L), 0x88);
+
+        for (reg, value) in [(Reg16::AF, 0x12FF), (Reg16::BC, 0x3456), (Reg16::DE, 0x789A), (Reg16::HL, 0xBCDE), (Reg16::SP, 0xC000), (Reg16::PC, 0x0150)] {
+            cpu.set_double_register(reg, value);
+            let expected = if reg == Reg16::AF { 0x12F0 } else { value };
+            assert_eq!(cpu.get_double_register(reg), expected, "{:?}", reg);
+
+            if let Some((msb_reg, lsb_reg)) = reg.get_halves() {
+                assert_eq!(cpu.get_register(msb_reg), (expected >> 8) as u8);
+                assert_eq!(cpu.get_register(lsb_reg), (expected & 0xFF) as u8);
+            }
+        }
+
+        // SP and PC are separate registers
+        assert_eq!(cpu.get_stack_pointer(), 0xC000);
+        assert_eq!(cpu.get_program_counter(), 0x0150);
+
+        for name in ["af", "BC", "de", "HL", "sp", "PC"] {
+            assert!(Reg16::from_name(name).is_some());
+        }
+        assert_eq!(Reg16::from_name("AB"), None);
+        assert_eq!(Reg8::from_name("X"), None);
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut cpu: CPU = CPU::init(false);
+
+        cpu.set_flag(Flag::Zero, true);
+        cpu.set_flag(Flag::Carry, true);
+        assert_eq!(cpu.get_register(Reg8::F), 0x90);
+        assert!(cpu.get_zero_flag() && cpu.get_flag(Flag::Carry));
+        assert!(!cpu.get_flag(Flag::Sub) && !cpu.get_flag(Flag::HalfCarry));
+
+        cpu.set_flag(Flag::Zero, false);
+        cpu.set_flag(Flag::HalfCarry, true);
+        assert_eq!(cpu.get_register(Reg8::F), 0x30);
+    }
+
+    #[test]
+    fn test_stack_pointer_instructions() {
+        // LD SP, 0xD000 / LD (0xC000), SP / PUSH BC / POP AF
+        let (mut cpu, mut bus) = create_cpu(&[0x31, 0x00, 0xD0, 0x08, 0x00, 0xC0, 0xC5, 0xF1]);
+        cpu.set_double_register(Reg16::BC, 0x12FF);
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_stack_pointer(), 0xD000);
+        assert_eq!(cpu.get_program_counter(), 0x0103);
+
+        // Stores SP, not PC
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(bus.get_addr(0xC000), 0x00);
+        assert_eq!(bus.get_addr(0xC001), 0xD0);
+
+        // POP AF drops the low nibble of F
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_double_register(Reg16::AF), 0x12F0);
+        assert_eq!(cpu.get_stack_pointer(), 0xD000);
+    }
+
+    #[test]
+    fn test_16bit_arithmetic() {
+        type Registers = &'static [(Reg16, u16)];
+
+        // Program, registers before, registers after, cycles
+        let cases: [(&[u8], Registers, Registers, usize); 16] = [
+            // ADD HL, rr leaves Z alone and carries from bits 11 and 15
+            (&[0x09], &[(Reg16::AF, 0x0080), (Reg16::HL, 0x0FFF), (Reg16::BC, 0x0001)], &[(Reg16::HL, 0x1000), (Reg16::AF, 0x00A0)], 8),
+            (&[0x09], &[(Reg16::AF, 0x0000), (Reg16::HL, 0xFFFF), (Reg16::BC, 0x0001)], &[(Reg16::HL, 0x0000), (Reg16::AF, 0x0030)], 8),
+            (&[0x19], &[(Reg16::AF, 0x0040), (Reg16::HL, 0x1234), (Reg16::DE, 0x0101)], &[(Reg16::HL, 0x1335), (Reg16::AF, 0x0000)], 8),
+            (&[0x29], &[(Reg16::AF, 0x0040), (Reg16::HL, 0x8000)], &[(Reg16::HL, 0x0000), (Reg16::AF, 0x0010)], 8),
+            (&[0x39], &[(Reg16::AF, 0x0000), (Reg16::HL, 0x1234), (Reg16::SP, 0x0100)], &[(Reg16::HL, 0x1334), (Reg16::AF, 0x0000)], 8),
+            // ADD SP, r8 clears Z and N, the carries come from the low byte
+            (&[0xE8, 0x01], &[(Reg16::AF, 0x00C0), (Reg16::SP, 0x00FF)], &[(Reg16::SP, 0x0100), (Reg16::AF, 0x0030)], 16),
+            (&[0xE8, 0xFF], &[(Reg16::AF, 0x0000), (Reg16::SP, 0x0000)], &[(Reg16::SP, 0xFFFF), (Reg16::AF, 0x0000)], 16),
+            (&[0xE8, 0xFF], &[(Reg16::AF, 0x0000), (Reg16::SP, 0x0001)], &[(Reg16::SP, 0x0000), (Reg16::AF, 0x0030)], 16),
+            // LD HL, SP+r8 doesn't touch SP
+            (&[0xF8, 0x02], &[(Reg16::AF, 0x0080), (Reg16::SP, 0xFFF8)], &[(Reg16::HL, 0xFFFA), (Reg16::SP, 0xFFF8), (Reg16::AF, 0x0000)], 12),
+            (&[0xF8, 0xF8], &[(Reg16::AF, 0x0000), (Reg16::SP, 0x0008)], &[(Reg16::HL, 0x0000), (Reg16::SP, 0x0008), (Reg16::AF, 0x0030)], 12),
+            // INC/DEC rr don't touch the flags
+            (&[0x03], &[(Reg16::AF, 0x00F0), (Reg16::BC, 0xFFFF)], &[(Reg16::BC, 0x0000), (Reg16::AF, 0x00F0)], 8),
+            (&[0x0B], &[(Reg16::AF, 0x0000), (Reg16::BC, 0x0000)], &[(Reg16::BC, 0xFFFF), (Reg16::AF, 0x0000)], 8),
+            (&[0x1B], &[(Reg16::AF, 0x0000), (Reg16::DE, 0x0100)], &[(Reg16::DE, 0x00FF), (Reg16::AF, 0x0000)], 8),
+            (&[0x23], &[(Reg16::AF, 0x0000), (Reg16::HL, 0x00FF)], &[(Reg16::HL, 0x0100), (Reg16::AF, 0x0000)], 8),
+            (&[0x33], &[(Reg16::AF, 0x0000), (Reg16::SP, 0xFFFF)], &[(Reg16::SP, 0x0000), (Reg16::AF, 0x0000)], 8),
+            (&[0x3B], &[(Reg16::AF, 0x0000), (Reg16::SP, 0x0000)], &[(Reg16::SP, 0xFFFF), (Reg16::AF, 0x0000)], 8)
+        ];
+
+        for (program, before, after, cycles) in cases {
+            let (mut cpu, mut bus) = create_cpu(program);
+            for (reg, value) in before {
+                cpu.set_double_register(*reg, *value);
+            }
+
+            cpu.execute_instruction(&mut bus).unwrap();
+
+            for (reg, value) in after {
+                assert_eq!(cpu.get_double_register(*reg), *value, "{:02X?} {:?}", program, reg);
+            }
+            assert_eq!(cpu.get_program_counter(), 0x0100 + program.len() as u16, "{:02X?}", program);
+            assert_eq!(bus.get_cycle_counter(), cycles, "{:02X?}", program);
+        }
+    }
+
+    #[test]
+    fn test_inc_dec_hl_memory() {
+        // INC (HL) / DEC (HL) / DEC (HL)
+        let (mut cpu, mut bus) = create_cpu(&[0x34, 0x35, 0x35]);
+        cpu.set_double_register(Reg16::HL, 0xC000);
+        bus.set_addr(0xC000, 0x0F);
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(bus.get_addr(0xC000), 0x10);
+        assert_eq!(cpu.get_register(Reg8::F), 0x20);
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(bus.get_addr(0xC000), 0x0F);
+        assert_eq!(cpu.get_register(Reg8::F), 0x60);
+
+        bus.set_addr(0xC000, 0x01);
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(bus.get_addr(0xC000), 0x00);
+        assert_eq!(cpu.get_register(Reg8::F), 0xC0);
+
+        // HL itself stays put
+        assert_eq!(cpu.get_double_register(Reg16::HL), 0xC000);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        type Case = (&'static [u8], u8, u16, u16, u16, usize);
+
+        // Program, F, HL, PC after, SP after, cycles. The stack starts at 0xC000 with 0x1234 on it
+        let cases: [Case; 22] = [
+            (&[0xC3, 0x00, 0x02], 0x00, 0x0000, 0x0200, 0xC000, 16), // JP a16
+            (&[0xC2, 0x00, 0x02], 0x00, 0x0000, 0x0200, 0xC000, 16), // JP NZ taken
+            (&[0xC2, 0x00, 0x02], 0x80, 0x0000, 0x0103, 0xC000, 12), // JP NZ not taken
+            (&[0xDA, 0x00, 0x02], 0x10, 0x0000, 0x0200, 0xC000, 16), // JP C taken
+            (&[0xE9], 0x00, 0x4000, 0x4000, 0xC000, 4), // JP HL
+            (&[0x18, 0xFE], 0x00, 0x0000, 0x0100, 0xC000, 12), // JR r8
+            (&[0x28, 0x05], 0x80, 0x0000, 0x0107, 0xC000, 12), // JR Z taken
+            (&[0x28, 0x05], 0x00, 0x0000, 0x0102, 0xC000, 8), // JR Z not taken
+            (&[0x30, 0xFE], 0x00, 0x0000, 0x0100, 0xC000, 12), // JR NC taken
+            (&[0x38, 0xFE], 0x00, 0x0000, 0x0102, 0xC000, 8), // JR C not taken
+            (&[0xCD, 0x00, 0x02], 0x00, 0x0000, 0x0200, 0xBFFE, 24), // CALL a16
+            (&[0xC4, 0x00, 0x02], 0x00, 0x0000, 0x0200, 0xBFFE, 24), // CALL NZ taken
+            (&[0xC4, 0x00, 0x02], 0x80, 0x0000, 0x0103, 0xC000, 12), // CALL NZ not taken
+            (&[0xDC, 0x00, 0x02], 0x00, 0x0000, 0x0103, 0xC000, 12), // CALL C not taken
+            (&[0xC9], 0x00, 0x0000, 0x1234, 0xC002, 16), // RET
+            (&[0xD0], 0x00, 0x0000, 0x1234, 0xC002, 20), // RET NC taken
+            (&[0xD0], 0x10, 0x0000, 0x0101, 0xC000, 8), // RET NC not taken
+            (&[0xC8], 0x80, 0x0000, 0x1234, 0xC002, 20), // RET Z taken
+            (&[0xC0], 0x80, 0x0000, 0x0101, 0xC000, 8), // RET NZ not taken
+            (&[0xD9], 0x00, 0x0000, 0x1234, 0xC002, 16), // RETI
+            (&[0xFF], 0x00, 0x0000, 0x0038, 0xBFFE, 16), // RST 38H
+            (&[0xCF], 0x00, 0x0000, 0x0008, 0xBFFE, 16) // RST 08H
+        ];
+
+        for (program, f, hl, pc, sp, cycles) in cases {
+            let (mut cpu, mut bus) = create_cpu(program);
+            cpu.set_register(Reg8::F, f);
+            cpu.set_double_register(Reg16::HL, hl);
+            cpu.set_stack_pointer(0xC000);
+            bus.set_addr(0xC000, 0x34);
+            bus.set_addr(0xC001, 0x12);
+
+            cpu.execute_instruction(&mut bus).unwrap();
+
+            assert_eq!(cpu.get_program_counter(), pc, "{:02X?}", program);
+            assert_eq!(cpu.get_stack_pointer(), sp, "{:02X?}", program);
+            assert_eq!(bus.get_cycle_counter(), cycles, "{:02X?}", program);
+
+            // Calls push the address of the next instruction
+            if sp == 0xBFFE {
+                let return_addr = bus.get_addr(0xBFFE) as u16 | ((bus.get_addr(0xBFFF) as u16) << 8);
+                assert_eq!(return_addr, 0x0100 + program.len() as u16, "{:02X?}", program);
+            }
+        }
+    }
+
+    #[test]
+    fn test_call_stack_and_reti() {
+        let mut program = vec![0x00; 0x110];
+        program[0x00..0x03].copy_from_slice(&[0xCD, 0x00, 0x02]); // CALL 0x0200
+        program[0x03..0x06].copy_from_slice(&[0xCD, 0x08, 0x02]); // CALL 0x0208
+        program[0x100] = 0xC8; // RET Z
+        program[0x108] = 0xD9; // RETI
+        let (mut cpu, mut bus) = create_cpu(&program);
+        cpu.set_register(Reg8::F, 0x80);
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_backtrace().len(), 2);
+
+        // Conditional returns leave the call stack too
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_program_counter(), 0x0103);
+        assert_eq!(cpu.get_backtrace().len(), 1);
+
+        // RETI enables interrupts without EI's delay
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_backtrace().len(), 2);
+        assert!(!cpu.is_ime_enabled());
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_program_counter(), 0x0106);
+        assert_eq!(cpu.get_backtrace().len(), 1);
+        assert!(cpu.is_ime_enabled());
+    }
+
+    #[test]
+    fn test_halt_and_interrupts() {
+        // EI / HALT / INC A
+        let (mut cpu, mut bus) = create_cpu(&[0xFB, 0x76, 0x3C]);
+        bus.set_addr(0xFFFF, 0x01);
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert!(cpu.is_halted());
+        assert!(cpu.is_ime_enabled());
+
+        // Sleeps while the rest keeps going
+        let cycles = bus.get_cycle_counter();
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_program_counter(), 0x0102);
+        assert_eq!(bus.get_cycle_counter(), cycles + 8);
+
+        // VBlank wakes it up and gets serviced
+        bus.request_interrupt(0);
+        assert_eq!(bus.get_addr(0xFF0F), 0xE1);
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert!(!cpu.is_halted());
+        assert!(!cpu.is_ime_enabled());
+        assert_eq!(cpu.get_program_counter(), 0x0040);
+        assert_eq!(bus.get_addr(0xFF0F), 0xE0);
+        assert_eq!(cpu.get_stack_pointer(), 0xFFFC);
+        assert_eq!(bus.get_addr(0xFFFC), 0x02);
+        assert_eq!(bus.get_addr(0xFFFD), 0x01);
+    }
+
+    #[test]
+    fn test_interrupt_priority_and_ei_delay() {
+        // EI / NOP / NOP
+        let (mut cpu, mut bus) = create_cpu(&[0xFB, 0x00, 0x00]);
+        bus.set_addr(0xFFFF, 0x1F);
+        bus.set_addr(0xFF0F, 0x14); // Timer and joypad
+
+        // The interrupt waits for the instruction after EI
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_program_counter(), 0x0102);
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_program_counter(), 0x0050);
+        assert_eq!(bus.get_addr(0xFF0F), 0xF0);
+    }
+
+    #[test]
+    fn test_halt_without_ime() {
+        // HALT / INC A
+        let (mut cpu, mut bus) = create_cpu(&[0x76, 0x3C]);
+        bus.set_addr(0xFFFF, 0x01);
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert!(cpu.is_halted());
+
+        // Wakes up without servicing the interrupt
+        bus.request_interrupt(0);
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.get_program_counter(), 0x0102);
+        assert_eq!(cpu.get_register(Reg8::A), 0x01);
+        assert_eq!(bus.get_addr(0xFF0F), 0xE1);
+    }
+
+    #[test]
+    fn test_halt_bug() {
+        // HALT / INC A / LD B, 0x05
+        let (mut cpu, mut bus) = create_cpu(&[0x76, 0x3C, 0x06, 0x05]);
+        bus.set_addr(0xFFFF, 0x01);
+        bus.set_addr(0xFF0F, 0x01);
+
+        // With IME off and an interrupt pending HALT doesn't sleep, and INC A runs twice
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert!(!cpu.is_halted());
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_program_counter(), 0x0101);
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_program_counter(), 0x0102);
+        assert_eq!(cpu.get_register(Reg8::A), 0x02);
+
+        // Multi byte instructions read their opcode again as the operand
+        let (mut cpu, mut bus) = create_cpu(&[0x76, 0x06, 0x05]);
+        bus.set_addr(0xFFFF, 0x01);
+        bus.set_addr(0xFF0F, 0x01);
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_register(Reg8::B), 0x06);
+        assert_eq!(cpu.get_program_counter(), 0x0102);
+
+        // Calls push where the pc would have gone, so RST returns to itself and runs again
+        let (mut cpu, mut bus) = create_cpu(&[0x76, 0xCF]); // HALT / RST 08H
+        bus.set_addr(0xFFFF, 0x01);
+        bus.set_addr(0xFF0F, 0x01);
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+        let sp = cpu.get_stack_pointer();
+        assert_eq!(cpu.get_program_counter(), 0x0008);
+        assert_eq!(bus.get_addr(sp) as u16 | ((bus.get_addr(sp.wrapping_add(1)) as u16) << 8), 0x0101);
+
+        let (mut cpu, mut bus) = create_cpu(&[0x76, 0xCD, 0x00, 0x02]); // HALT / CALL 0x0200
+        bus.set_addr(0xFFFF, 0x01);
+        bus.set_addr(0xFF0F, 0x01);
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+        let sp = cpu.get_stack_pointer();
+        assert_eq!(cpu.get_program_counter(), 0x00CD);
+        assert_eq!(bus.get_addr(sp) as u16 | ((bus.get_addr(sp.wrapping_add(1)) as u16) << 8), 0x0103);
+    }
+
+    #[test]
+    fn test_stop_until_button() {
+        // STOP / INC A
+        let (mut cpu, mut bus) = create_cpu(&[0x10, 0x00, 0x3C]);
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert!(bus.is_stopped());
+
+        let cycles = bus.get_cycle_counter();
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_program_counter(), 0x0102);
+        assert_eq!(bus.get_cycle_counter(), cycles + 4);
+
+        bus.set_button(Button::Start, true);
+        assert!(!bus.is_stopped());
+        assert_eq!(bus.get_addr(0xFF0F), 0xF0);
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert_eq!(cpu.get_register(Reg8::A), 0x01);
+    }
+
+    #[test]
+    fn test_trace_log() {
+        let mut ram_memory = RamMemory::init_from_rom(&Rom::create_test_rom());
+
+        // LD A, 0x11 / LD BC, 0x2233 / NOP
+        for (i, b) in [0x3E, 0x11, 0x01, 0x33, 0x22, 0x00].iter().enumerate() {
+            ram_memory.set_addr(0x0100 + i as u16, *b);
+        }
+
+        let mut bus = MemoryBus::new(ram_memory);
+        let mut cpu: CPU = CPU::init(false);
+
+        let trace_log_path = std::env::temp_dir().join("gbemulator_test_trace.log");
+        cpu.enable_trace_log(trace_log_path.to_str().unwrap()).unwrap();
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+
+        let trace_log = std::fs::read_to_string(&trace_log_path).unwrap();
+        assert_eq!(trace_log.lines().collect::<Vec<&str>>(), vec![
+            "A:00 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0100 PCMEM:3E,11,01,33",
+            "A:11 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0102 PCMEM:01,33,22,00",
+            "A:11 F:00 B:22 C:33 D:00 E:00 H:00 L:00 SP:FFFE PC:0105 PCMEM:00,00,00,00",
+        ]);
+        assert_eq!(cpu.get_state_line(&bus), "A:11 F:00 B:22 C:33 D:00 E:00 H:00 L:00 SP:FFFE PC:0106 PCMEM:00,00,00,00");
+        assert!(cpu.is_trace_log_enabled());
+
+        // Failed writes turn tracing off instead of stopping the emulator
+        if std::path::Path::new("/dev/full").exists() {
+            cpu.enable_trace_log("/dev/full").unwrap();
+            cpu.execute_instruction(&mut bus).unwrap();
+            assert!(!cpu.is_trace_log_enabled());
+            cpu.execute_instruction(&mut bus).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_post_boot_state() {
+        let create_cpu = |header_checksum: u8, cgb_flag: u8, model: Model| {
+            let mut ram_memory = RamMemory::init_from_rom(&Rom::create_test_rom());
+            ram_memory.set_addr(0x014D, header_checksum);
+            ram_memory.set_addr(0x0143, cgb_flag);
+            ram_memory.set_cgb_mode(model.is_cgb_mode(cgb_flag));
+
+            let mut cpu = CPU::init(true);
+            let mut bus = MemoryBus::new(ram_memory);
+            cpu.apply_post_boot_state(&bus, model);
+            bus.apply_post_boot_state(model);
+            return (cpu, bus);
+        };
+
+        let (cpu, bus) = create_cpu(0x42, 0x00, Model::DMG);
+        assert_eq!(cpu.get_state_line(&bus), "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,00,00,00");
+        assert_eq!(bus.get_addr(0xFF40), 0x91);
+        assert_eq!(bus.get_addr(0xFF47), 0xFC);
+        assert_eq!(bus.get_addr(0xFF04), 0xAB);
+        assert_eq!(bus.get_addr(0xFF26), 0xF1);
+        assert_eq!(bus.get_addr(0xFF46), 0xFF);
+
+        // Header checksum of 0 leaves H and C clear
+        let (cpu, bus) = create_cpu(0x00, 0x00, Model::DMG);
+        assert_eq!(&cpu.get_state_line(&bus)[..9], "A:01 F:80");
+
+        let (cpu, bus) = create_cpu(0x42, 0x00, Model::MGB);
+        assert_eq!(&cpu.get_state_line(&bus)[..9], "A:FF F:B0");
+
+        let (cpu, bus) = create_cpu(0x42, 0x80, Model::CGB);
+        assert_eq!(cpu.get_state_line(&bus), "A:11 F:80 B:00 C:00 D:FF E:56 H:00 L:0D SP:FFFE PC:0100 PCMEM:00,00,00,00");
+        assert_eq!(bus.get_addr(0xFF4D), 0x7E);
+        assert_eq!(bus.get_addr(0xFF4F), 0xFE);
+
+        // A DMG game on a CGB doesn't get the CGB only registers
+        let (cpu, bus) = create_cpu(0x42, 0x00, Model::CGB);
+        assert_eq!(cpu.get_state_line(&bus), "A:11 F:80 B:00 C:00 D:00 E:08 H:00 L:7C SP:FFFE PC:0100 PCMEM:00,00,00,00");
+        assert_eq!(bus.get_ram_memory().get_addr(0xFF4D), 0x00);
+        assert_eq!(bus.get_ram_memory().get_addr(0xFF4F), 0x00);
+        assert_eq!(bus.get_ram_memory().get_addr(0xFF70), 0x00);
+        assert_eq!(bus.get_addr(0xFF40), 0x91);
+    }
+
+    #[test]
+    fn test_boot_rom_overlay() {
+        let mut ram_memory = RamMemory::init_from_rom(&Rom::create_test_rom());
+        ram_memory.set_addr(0x0000, 0x11);
+        ram_memory.set_addr(0x0150, 0x22);
+
+        assert!(ram_memory.set_boot_rom(vec![0x00; 0x200]).is_err());
+
+        // Boot rom: LD A, 0x01 / LDH (0x50), A
+        let mut boot_rom = vec![0x00; 0x100];
+        boot_rom[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        ram_memory.set_boot_rom(boot_rom).unwrap();
+
+        let mut bus = MemoryBus::new(ram_memory);
+        let mut cpu: CPU = CPU::init(true);
+
+        assert_eq!(bus.get_addr(0x0000), 0x00);
+        assert_eq!(bus.get_addr(0x0150), 0x22);
+
+        cpu.set_program_counter(0x00FC);
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+
+        // The cartridge is visible again and execution continues at 0x0100
+        assert!(!bus.get_ram_memory().is_boot_rom_mapped());
+        assert_eq!(cpu.get_program_counter(), 0x0100);
+        assert_eq!(bus.get_addr(0x0000), 0x11);
+    }
+
+    #[test]
+    fn test_cgb_boot_rom_overlay() {
+        let mut ram_memory = RamMemory::init_from_rom(&Rom::create_test_rom());
+        ram_memory.set_addr(0x0104, 0xCE);
+
+        ram_memory.set_boot_rom(vec![0xAA; 0x900]).unwrap();
+
+        // The cartridge header stays visible between the two parts of a CGB boot rom
+        assert_eq!(ram_memory.get_addr(0x00FF), 0xAA);
+        assert_eq!(ram_memory.get_addr(0x0104), 0xCE);
+        assert_eq!(ram_memory.get_addr(0x0200), 0xAA);
+        assert_eq!(ram_memory.get_addr(0x08FF), 0xAA);
+        assert_eq!(ram_memory.get_addr(0x0900), 0x00);
+
+        // Writing 0 doesn't unmap it
+        ram_memory.set_addr(0xFF50, 0x00);
+        assert!(ram_memory.is_boot_rom_mapped());
+        ram_memory.set_addr(0xFF50, 0x11);
+        assert!(!ram_memory.is_boot_rom_mapped());
+    }
+}
+
+
+#[cfg(test)]
+mod gdb_stub_tests {
+    use crate::cpu::CPU;
+    use crate::gdb_stub::GdbStub;
+    use crate::symbols::SymbolTable;
+    use crate::rom_parser::Rom;
+    use crate::ram_memory::RamMemory;
+    use crate::bus::MemoryBus;
+    use crate::model::Model;
+    use crate::GameBoy;
+
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    // Runs a stub on an ephemeral port with the given program loaded at 0x0100
+    fn spawn_stub(program: Vec<u8>, symbols: Option<SymbolTable>) -> (TcpStream, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let mut ram_memory = RamMemory::init_from_rom(&Rom::create_test_rom());
+            for (i, b) in program.iter().enumerate() {
+                ram_memory.set_addr(0x0100 + i as u16, *b);
+            }
+
+            let mut cpu: CPU = CPU::init(false);
+            if let Some(symbols) = symbols {
+                cpu.set_symbols(symbols);
+            }
+            let mut gameboy = GameBoy::from_parts(cpu, MemoryBus::new(ram_memory), Model::DMG);
+
+            let mut gdb_stub = GdbStub::accept(&listener).unwrap();
+            gdb_stub.serve(&mut gameboy, |_| {}).unwrap();
+        });
+
+        (TcpStream::connect(("127.0.0.1", port)).unwrap(), handle)
+    }
+
+    fn read_byte(stream: &mut TcpStream) -> u8 {
+        let mut byte: [u8; 1] = [0];
+        stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn write_packet(stream: &mut TcpStream, command: &str) {
+        let checksum = command.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(stream, "${}#{:02x}", command, checksum).unwrap();
+        assert_eq!(read_byte(stream), b'+', "Stub did not acknowledge \"{}\"", command);
+    }
+
+    fn read_reply(stream: &mut TcpStream) -> String {
+        assert_eq!(read_byte(stream), b'$');
+        let mut reply: Vec<u8> = Vec::new();
+        loop {
+            match read_byte(stream) {
+                b'#' => break,
+                b => reply.push(b)
+            }
+        }
+
+        let reply = String::from_utf8(reply).unwrap();
+        let mut reply_checksum: [u8; 2] = [0; 2];
+        stream.read_exact(&mut reply_checksum).unwrap();
+        assert_eq!(
+            u8::from_str_radix(std::str::from_utf8(&reply_checksum).unwrap(), 16).unwrap(),
+            reply.bytes().fold(0u8, |sum, b| sum.wrapping_add(b)));
+
+        stream.write_all(b"+").unwrap();
+        reply
+    }
+
+    fn send_command(stream: &mut TcpStream, command: &str) -> String {
+        write_packet(stream, command);
+        read_reply(stream)
+    }
+
+    // Returns the console output of a "monitor" command
+    fn send_monitor_command(stream: &mut TcpStream, command: &str) -> String {
+        let hex_command: String = command.bytes().map(|b| format!("{:02x}", b)).collect();
+        write_packet(stream, &format!("qRcmd,{}", hex_command));
+
+        let mut output: String = "".to_string();
+        loop {
+            let reply = read_reply(stream);
+            if reply == "OK" {
+                return output;
+            }
+
+            assert!(reply.starts_with('O'), "Unexpected reply \"{}\"", reply);
+            for i in (1..reply.len()).step_by(2) {
+                output.push(u8::from_str_radix(&reply[i..i + 2], 16).unwrap() as char);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gdb_registers() {
+        let (mut stream, handle) = spawn_stub(vec![0x00], None);
+
+        assert_eq!(send_command(&mut stream, "?"), "S05");
+
+        // AF, BC, DE, HL, SP, PC - little endian
+        assert_eq!(send_command(&mut stream, "g"), "0000000000000000feff0001");
+
+        assert_eq!(send_command(&mut stream, "P1=3412"), "OK");
+        assert_eq!(send_command(&mut stream, "p1"), "3412");
+        assert_eq!(send_command(&mut stream, "p4"), "feff");
+
+        assert_eq!(send_command(&mut stream, "G00000000cdab00000e000002"), "OK");
+        assert_eq!(send_command(&mut stream, "g"), "00000000cdab00000e000002");
+        assert_eq!(send_command(&mut stream, "p9"), "E01");
+
+        assert_eq!(send_command(&mut stream, "D"), "OK");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_gdb_memory() {
+        let (mut stream, handle) = spawn_stub(vec![0x3E, 0x11, 0x00], None);
+
+        assert_eq!(send_command(&mut stream, "m100,3"), "3e1100");
+        assert_eq!(send_command(&mut stream, "MC000,2:beef"), "OK");
+        assert_eq!(send_command(&mut stream, "mc000,2"), "beef");
+
+        // Echo ram mirrors work ram
+        assert_eq!(send_command(&mut stream, "me000,2"), "beef");
+        assert_eq!(send_command(&mut stream, "MC000,2:be"), "E01");
+
+        // Lengths past the address space aren't truncated, the whole of it still works
+        assert_eq!(send_command(&mut stream, "m0,10001"), "E01");
+        assert_eq!(send_command(&mut stream, "m10000,1"), "E01");
+        assert_eq!(send_command(&mut stream, "m0,10000").len(), 0x20000);
+
+        // Packets starting with a multibyte character are unsupported, not a crash
+        assert_eq!(send_command(&mut stream, "é1"), "");
+        assert_eq!(send_command(&mut stream, "mc000,1"), "be");
+
+        assert_eq!(send_command(&mut stream, "D"), "OK");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_gdb_step_and_breakpoints() {
+        // 0x0100: NOP
+        // 0x0101: LD A, 0x11
+        // 0x0103: INC A
+        // 0x0104: JP 0x0101
+        let (mut stream, handle) = spawn_stub(vec![0x00, 0x3E, 0x11, 0x3C, 0xC3, 0x01, 0x01], None);
+
+        // Single step
+        assert_eq!(send_command(&mut stream, "s"), "S05");
+        assert_eq!(send_command(&mut stream, "p5"), "0101");
+
+        // Run until the INC instruction
+        assert_eq!(send_command(&mut stream, "Z0,103,1"), "OK");
+        assert_eq!(send_command(&mut stream, "c"), "S05");
+        assert_eq!(send_command(&mut stream, "p5"), "0301");
+        assert_eq!(send_command(&mut stream, "p0"), "0011");
+
+        // Run the loop once more, and stop at the same breakpoint
+        assert_eq!(send_command(&mut stream, "s"), "S05");
+        assert_eq!(send_command(&mut stream, "p0"), "0012");
+        assert_eq!(send_command(&mut stream, "c"), "S05");
+        assert_eq!(send_command(&mut stream, "p5"), "0301");
+
+        // Continuing after removing the breakpoint only stops on an interrupt
+        assert_eq!(send_command(&mut stream, "z0,103,1"), "OK");
+        write_packet(&mut stream, "c");
+        stream.write_all(&[0x03]).unwrap();
+
+        let mut reply: [u8; 7] = [0; 7];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"$S02#b5");
+        stream.write_all(b"+").unwrap();
+
+        // Resume from an explicit address
+        assert_eq!(send_command(&mut stream, "Z0,104,1"), "OK");
+        assert_eq!(send_command(&mut stream, "c103"), "S05");
+        assert_eq!(send_command(&mut stream, "p5"), "0401");
+
+        // Addresses past 64 KiB are rejected instead of wrapping around, the pc stays put
+        assert_eq!(send_command(&mut stream, "c10103"), "E01");
+        assert_eq!(send_command(&mut stream, "s10100"), "E01");
+        assert_eq!(send_command(&mut stream, "p5"), "0401");
+
+        write_packet(&mut stream, "k");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_gdb_monitor_symbols() {
+        // 0x0100: CALL Init
+        // 0x0103: JR -2
+        // 0x0110: Init: NOP
+        // 0x0111: CALL Init.inner
+        // 0x0114: RET
+        // 0x0118: Init.inner: NOP
+        // 0x0119: RET
+        let mut program: Vec<u8> = vec![0x00; 0x20];
+        program[0x00..0x05].copy_from_slice(&[0xCD, 0x10, 0x01, 0x18, 0xFE]);
+        program[0x10..0x15].copy_from_slice(&[0x00, 0xCD, 0x18, 0x01, 0xC9]);
+        program[0x18..0x1A].copy_from_slice(&[0x00, 0xC9]);
+
+        let symbols = SymbolTable::parse("00:0100 Start\n00:0110 Init\n00:0118 Init.inner\n");
+        let (mut stream, handle) = spawn_stub(program, Some(symbols));
+
+        assert_eq!(send_monitor_command(&mut stream, "break Init.inner"), "Breakpoint at 0x0118\n");
+        assert_eq!(send_monitor_command(&mut stream, "break Missing"), "Unknown label or address \"Missing\"\n");
+        assert_eq!(send_command(&mut stream, "c"), "S05");
+        assert_eq!(send_command(&mut stream, "p5"), "1801");
+
+        assert_eq!(send_monitor_command(&mut stream, "bt"),
+            "#0 0x0118 <Init.inner>\n#1 0x0114 <Init+0x4>\n#2 0x0103 <Start+0x3>\n");
+
+        // Breakpoints by address work without labels too
+        assert_eq!(send_monitor_command(&mut stream, "delete Init.inner"), "Deleted breakpoint at 0x0118\n");
+        assert_eq!(send_monitor_command(&mut stream, "break 0x0103"), "Breakpoint at 0x0103\n");
+        assert_eq!(send_command(&mut stream, "c"), "S05");
+        assert_eq!(send_monitor_command(&mut stream, "backtrace"), "#0 0x0103 <Start+0x3>\n");
+
+        assert_eq!(send_command(&mut stream, "D"), "OK");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_gdb_monitor_cheats() {
+        // 0x0100: LD A, (0x0150)
+        let mut program: Vec<u8> = vec![0x00; 0x60];
+        program[0x00..0x03].copy_from_slice(&[0xFA, 0x50, 0x01]);
+        program[0x50] = 0x05;
+
+        let (mut stream, handle) = spawn_stub(program, None);
+
+        assert_eq!(send_monitor_command(&mut stream, "cheat list"), "No cheats\n");
+        assert_eq!(send_monitor_command(&mut stream, "cheat add 3E1-50F-FAE More lives"), "Added cheat #0\n");
+        assert_eq!(send_monitor_command(&mut stream, "cheat add XYZ"), "Invalid cheat code \"XYZ\", not hex\n");
+        assert_eq!(send_monitor_command(&mut stream, "cheat disable 0"), "#0 [ ] 3E1-50F-FAE More lives\n");
+        assert_eq!(send_monitor_command(&mut stream, "cheat enable 0"), "#0 [x] 3E1-50F-FAE More lives\n");
+        assert_eq!(send_monitor_command(&mut stream, "cheat enable 3"), "No cheat #3\n");
+
+        assert_eq!(send_command(&mut stream, "m0150,1"), "3e");
+        assert_eq!(send_command(&mut stream, "s"), "S05");
+        assert_eq!(send_command(&mut stream, "p0"), "003e");
+
+        assert_eq!(send_command(&mut stream, "D"), "OK");
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_gdb_monitor_search() {
+        // 0x0100: LD A, 0x07; LD (0xC123), A
+        let program: Vec<u8> = vec![0x3E, 0x07, 0xEA, 0x23, 0xC1];
+        let (mut stream, handle) = spawn_stub(program, None);
+
+        assert_eq!(send_monitor_command(&mut stream, "search list"), "No RAM search, use \"search start\" first\n");
+        assert_eq!(send_monitor_command(&mut stream, "search start"), "Started RAM search with 16511 candidates\n");
+
+        assert_eq!(send_command(&mut stream, "s"), "S05");
+        assert_eq!(send_command(&mut stream, "s"), "S05");
+
+        assert_eq!(send_monitor_command(&mut stream, "search inc"), "1 candidates\n0xC123 = 0x07\n");
+        assert_eq!(send_monitor_command(&mut stream, "search value 0x07"), "1 candidates\n0xC123 = 0x07\n");
+        assert_eq!(send_monitor_command(&mut stream, "search value x"), "Invalid search filter \"value x\"\n");
+
+        assert_eq!(send_command(&mut stream, "D"), "OK");
+        handle.join().unwrap();
+    }
+}
+
+
+#[cfg(test)]
+mod disassembler_tests {
+    use crate::disassembler::Disassembler;
+
+    #[test]
+    fn test_disassemble_rom() {
+        let mut rom_content: Vec<u8> = vec![0x00; 0x0200];
+        let program: [u8; 14] = [0x00, 0xC3, 0x50, 0x01, 0x20, 0xFB, 0x18, 0x02, 0xCB, 0x7C, 0xF8, 0xFE, 0xE8, 0x05];
+        rom_content[0x0100..0x0100 + program.len()].copy_from_slice(&program);
+
+        let lines: Vec<String> = Disassembler::new().disassemble_rom(&rom_content, 0x0100, 0x010D)
+            .iter().map(|instruction| instruction.get_line()).collect();
+
+        assert_eq!(lines, vec![
+            "0x0100: 00        NOP",
+            "0x0101: C3 50 01  JP 0x0150",
+            "0x0104: 20 FB     JR NZ, 0x0101 (-0x05)",
+            "0x0106: 18 02     JR 0x010A (+0x02)",
+            "0x0108: CB 7C     BIT 7, H",
+            "0x010A: F8 FE     LD HL, SP-0x02",
+            "0x010C: E8 05     ADD SP, +0x05",
+        ]);
+    }
+
+    #[test]
+    fn test_disassemble_memory_range() {
+        // LD (HL+), A / LD A, (0xC000) / JR -128
+        let memory: [u8; 6] = [0x22, 0xFA, 0x00, 0xC0, 0x18, 0x80];
+        let instructions = Disassembler::new().disassemble(0xC000, 0xC005, |addr| memory[(addr - 0xC000) as usize]);
+
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(instructions[0].get_text(), "LD (HL+), A");
+        assert_eq!(instructions[1].get_text(), "LD A, (0xC000)");
+        assert_eq!(instructions[2].get_text(), "JR 0xBF86 (-0x80)");
+        assert_eq!(instructions[2].params[0].get_jump_target(), Some(0xBF86));
+    }
+}
+
+
+#[cfg(test)]
+mod symbols_tests {
+    use crate::symbols::SymbolTable;
+    use crate::disassembler::Disassembler;
+
+    const SYM_FILE: &str = "; File generated by rgblink\n\
+        00:0000 RST_00\n\
+        00:0150 Main\n\
+        00:0150 Main.loop\n\
+        01:4000 BankedFunc ; comment\n\
+        00:c000 wCounter\n\
+        invalid line\n";
+
+    #[test]
+    fn test_parse_sym_file() {
+        let symbols = SymbolTable::parse(SYM_FILE);
+
+        assert_eq!(symbols.len(), 5);
+        assert_eq!(symbols.get_label(0x0150), Some("Main"));
+        assert_eq!(symbols.get_label(0x4000), Some("BankedFunc"));
+        assert_eq!(symbols.get_label(0x0151), None);
+        assert_eq!(symbols.get_addr("Main.loop"), Some(0x0150));
+        assert_eq!(symbols.get_addr("wCounter"), Some(0xC000));
+
+        assert_eq!(symbols.get_nearest_label(0x0155), Some(("Main", 5)));
+        assert_eq!(symbols.get_nearest_label(0x4010), Some(("BankedFunc", 0x10)));
+        assert_eq!(symbols.get_nearest_label(0xC001), Some(("wCounter", 1)));
+        assert_eq!(symbols.get_nearest_label(0x8000), None);
+
+        assert_eq!(symbols.format_addr(0x0150), "0x0150 <Main>");
+        assert_eq!(symbols.format_addr(0x015A), "0x015A <Main+0xA>");
+        assert_eq!(symbols.format_addr(0x8000), "0x8000");
+    }
+
+    #[test]
+    fn test_resolve_breakpoint_target() {
+        let symbols = SymbolTable::parse(SYM_FILE);
+
+        assert_eq!(SymbolTable::resolve(Some(&symbols), "BankedFunc"), Some(0x4000));
+        assert_eq!(SymbolTable::resolve(Some(&symbols), "0x0200"), Some(0x0200));
+        assert_eq!(SymbolTable::resolve(Some(&symbols), "c010"), Some(0xC010));
+        assert_eq!(SymbolTable::resolve(Some(&symbols), "Missing"), None);
+        assert_eq!(SymbolTable::resolve(None, "150"), Some(0x0150));
+    }
+
+    #[test]
+    fn test_disassemble_with_symbols() {
+        let mut rom_content: Vec<u8> = vec![0x00; 0x0200];
+        // Main: LD A, (wCounter) / JR Main / CALL 0x0000
+        let program: [u8; 8] = [0xFA, 0x00, 0xC0, 0x18, 0xFB, 0xCD, 0x00, 0x00];
+        rom_content[0x0150..0x0150 + program.len()].copy_from_slice(&program);
+
+        let mut disassembler = Disassembler::new();
+        disassembler.set_symbols(SymbolTable::parse(SYM_FILE));
+        let instructions = disassembler.disassemble_rom(&rom_content, 0x0150, 0x0157);
+
+        assert_eq!(instructions[0].label, Some("Main".to_string()));
+        assert_eq!(instructions[0].get_line(), "0x0150: FA 00 C0  LD A, (0xC000) ; wCounter");
+        assert_eq!(instructions[1].label, None);
+        assert_eq!(instructions[1].get_line(), "0x0153: 18 FB     JR 0x0150 (-0x05) ; Main");
+        assert_eq!(instructions[2].get_line(), "0x0155: CD 00 00  CALL 0x0000 ; RST_00");
+    }
+}
+
+
+#[cfg(test)]
+mod cgb_tests {
+    use crate::cpu::CPU;
+    use crate::bus::{Bus, MemoryBus};
+    use crate::rom_parser::Rom;
+    use crate::ram_memory::RamMemory;
+    use crate::ppu::{PPU, TileAttributes};
+    use crate::model::Model;
+
+    fn create_cgb_cpu(program: &[u8]) -> (CPU, MemoryBus) {
+        let mut ram_memory = RamMemory::init_from_rom(&Rom::create_test_rom());
+        for (i, b) in program.iter().enumerate() {
+            ram_memory.set_addr(0x0100 + i as u16, *b);
+        }
+        ram_memory.set_cgb_mode(true);
+
+        return (CPU::init(false), MemoryBus::new(ram_memory));
+    }
+
+    fn setup_hdma(bus: &mut MemoryBus, source: u16, destination: u16) {
+        for i in 0..0x40 {
+            bus.set_addr(source + i, i as u8 + 1);
+        }
+
+        bus.set_addr(0xFF51, (source >> 8) as u8);
+        bus.set_addr(0xFF52, source as u8);
+        bus.set_addr(0xFF53, (destination >> 8) as u8);
+        bus.set_addr(0xFF54, destination as u8);
+    }
+
+    #[test]
+    fn test_cgb_mode_detection() {
+        assert_eq!(Model::detect(0x80), Model::CGB);
+        assert_eq!(Model::detect(0xC0), Model::CGB);
+        assert_eq!(Model::detect(0x00), Model::DMG);
+        assert!(Model::CGB.is_cgb_mode(0xC0));
+        assert!(!Model::CGB.is_cgb_mode(0x00));
+        assert!(!Model::DMG.is_cgb_mode(0x80));
+    }
+
+    #[test]
+    fn test_vram_banks() {
+        let (_, mut bus) = create_cgb_cpu(&[]);
+
+        bus.set_addr(0x8010, 0x11);
+        bus.set_addr(0xFF4F, 0x01);
+        assert_eq!(bus.get_addr(0xFF4F), 0xFF);
+        assert_eq!(bus.get_addr(0x8010), 0x00);
+        bus.set_addr(0x8010, 0x22);
+
+        bus.set_addr(0xFF4F, 0x00);
+        assert_eq!(bus.get_addr(0xFF4F), 0xFE);
+        assert_eq!(bus.get_addr(0x8010), 0x11);
+    }
+
+    #[test]
+    fn test_wram_banks() {
+        let (_, mut bus) = create_cgb_cpu(&[]);
+
+        for bank in 1..8 {
+            bus.set_addr(0xFF70, bank);
+            bus.set_addr(0xD123, bank * 0x10);
+        }
+
+        for bank in 1..8 {
+            bus.set_addr(0xFF70, bank);
+            assert_eq!(bus.get_addr(0xD123), bank * 0x10);
+            assert_eq!(bus.get_addr(0xF123), bank * 0x10); // Echo ram follows the bank
+        }
+
+        // Bank 0 selects bank 1, bank 0 itself is always at 0xC000
+        bus.set_addr(0xFF70, 0x00);
+        assert_eq!(bus.get_addr(0xD123), 0x10);
+    }
+
+    #[test]
+    fn test_pallete_ram() {
+        let (_, mut bus) = create_cgb_cpu(&[]);
+
+        // Pallete 1, color 0 with auto increment
+        bus.set_addr(0xFF68, 0x88);
+        bus.set_addr(0xFF69, 0x1F); // Red
+        bus.set_addr(0xFF69, 0x00);
+        bus.set_addr(0xFF69, 0xE0); // Green
+        bus.set_addr(0xFF69, 0x03);
+        assert_eq!(bus.get_addr(0xFF68), 0xCC);
+
+        // Reading doesn't increment
+        bus.set_addr(0xFF68, 0x0A);
+        assert_eq!(bus.get_addr(0xFF69), 0xE0);
+        assert_eq!(bus.get_addr(0xFF69), 0xE0);
+
+        // Index wraps around
+        bus.set_addr(0xFF6A, 0xBF);
+        bus.set_addr(0xFF6B, 0x7C);
+        assert_eq!(bus.get_addr(0xFF6A), 0xC0);
+        bus.set_addr(0xFF6B, 0x12);
+        bus.set_addr(0xFF6A, 0x00);
+        assert_eq!(bus.get_addr(0xFF6B), 0x12);
+    }
+
+    #[test]
+    fn test_cgb_colors() {
+        let mut pallete_ram = [0x00; 64];
+        pallete_ram[8..16].copy_from_slice(&[0x1F, 0x00, 0xE0, 0x03, 0x00, 0x7C, 0xFF, 0x7F]);
+
+        assert_eq!(PPU::get_cgb_color(&pallete_ram, 0, 0), 0x000000);
+        assert_eq!(PPU::get_cgb_color(&pallete_ram, 1, 0), 0xFF0000);
+        assert_eq!(PPU::get_cgb_color(&pallete_ram, 1, 1), 0x00FF00);
+        assert_eq!(PPU::get_cgb_color(&pallete_ram, 1, 2), 0x0000FF);
+        assert_eq!(PPU::get_cgb_color(&pallete_ram, 1, 3), 0xFFFFFF);
+    }
+
+    #[test]
+    fn test_tile_attributes() {
+        assert_eq!(TileAttributes::from_byte(0b10101101), TileAttributes {
+            pallete: 5,
+            bank: 1,
+            x_flip: true,
+            y_flip: false,
+            priority: true
+        });
+        assert_eq!(TileAttributes::from_byte(0b01000010), TileAttributes {
+            pallete: 2,
+            bank: 0,
+            x_flip: false,
+            y_flip: true,
+            priority: false
+        });
+    }
+
+    #[test]
+    fn test_speed_switch() {
+        // STOP / STOP
+        let (mut cpu, mut bus) = create_cgb_cpu(&[0x10, 0x00, 0x10, 0x00]);
+        assert_eq!(bus.get_addr(0xFF4D), 0x7E);
+
+        bus.set_addr(0xFF4D, 0x01);
+        assert_eq!(bus.get_addr(0xFF4D), 0x7F);
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert!(bus.is_double_speed());
+        assert_eq!(bus.get_addr(0xFF4D), 0xFE);
+        assert_eq!(bus.get_cycle_counter(), 8204);
+        assert!(!bus.is_stopped());
+
+        // Without arming the switch STOP doesn't change the speed, it just stops
+        cpu.execute_instruction(&mut bus).unwrap();
+        assert!(bus.is_double_speed());
+        assert!(bus.is_stopped());
+    }
+
+    #[test]
+    fn test_ppu_timing() {
+        let (_, mut bus) = create_cgb_cpu(&[]);
+        bus.set_addr(0xFF40, 0x91);
+        bus.set_addr(0xFF45, 0x01);
+
+        let get_ly_mode = |bus: &MemoryBus| (bus.get_ppu().get_ly(), bus.get_ppu().get_mode());
+        assert_eq!(get_ly_mode(&bus), (0, 2));
+        bus.tick(80);
+        assert_eq!(get_ly_mode(&bus), (0, 3));
+        bus.tick(172);
+        assert_eq!(get_ly_mode(&bus), (0, 0));
+        assert_eq!(bus.get_addr(0xFF41) & 0b111, 0b000);
+        bus.tick(204);
+        assert_eq!(get_ly_mode(&bus), (1, 2));
+        assert_eq!(bus.get_addr(0xFF44), 1);
+        assert_eq!(bus.get_addr(0xFF41) & 0b111, 0b110); // LY == LYC
+
+        assert_eq!(bus.get_frame_counter(), 0);
+        bus.tick(143 * 456);
+        assert_eq!(get_ly_mode(&bus), (144, 1));
+        assert_eq!(bus.get_frame_counter(), 1);
+
+        bus.tick(10 * 456);
+        assert_eq!(get_ly_mode(&bus), (0, 2));
+        assert_eq!(bus.get_frame_counter(), 1);
+
+        // LY is stuck at 0 while the lcd is off
+        bus.set_addr(0xFF40, 0x00);
+        bus.tick(1000);
+        assert_eq!(bus.get_addr(0xFF44), 0);
+    }
+
+    #[test]
+    fn test_general_purpose_dma() {
+        // LD A, 0x01 / LD (0xFF55), A
+        let (mut cpu, mut bus) = create_cgb_cpu(&[0x3E, 0x01, 0xEA, 0x55, 0xFF]);
+        setup_hdma(&mut bus, 0xC100, 0x8800);
+
+        cpu.execute_instruction(&mut bus).unwrap();
+        cpu.execute_instruction(&mut bus).unwrap();
+
+        // 2 blocks are copied right away and the cpu waits for them
+        for i in 0..0x20 {
+            assert_eq!(bus.get_addr(0x8800 + i), i as u8 + 1);
+        }
+        assert_eq!(bus.get_addr(0x8820), 0x00);
+        assert_eq!(bus.get_addr(0xFF55), 0xFF);
+        assert_eq!(bus.get_cycle_counter(), 8 + 16 + 2 * 32);
+    }
+
+    #[test]
+    fn test_hblank_dma() {
+        let (_, mut bus) = create_cgb_cpu(&[]);
+        bus.set_addr(0xFF40, 0x91);
+        setup_hdma(&mut bus, 0xC100, 0x9000);
+
+        bus.set_addr(0xFF55, 0x82);
+        assert_eq!(bus.get_addr(0xFF55), 0x02);
+        assert_eq!(bus.get_addr(0x9000), 0x00);
+
+        // A single block every HBlank
+        bus.tick(252);
+        assert_eq!(bus.get_addr(0x900F), 0x10);
+        assert_eq!(bus.get_addr(0x9010), 0x00);
+        assert_eq!(bus.get_addr(0xFF55), 0x01);
+
+        bus.tick(456);
+        assert_eq!(bus.get_addr(0x901F), 0x20);
+        assert_eq!(bus.get_addr(0xFF55), 0x00);
+
+        // Cancelling keeps the remaining length
+        bus.set_addr(0xFF55, 0x00);
+        assert_eq!(bus.get_addr(0xFF55), 0x80);
+        bus.tick(456);
+        assert_eq!(bus.get_addr(0x9020), 0x00);
+    }
+}
+
+
+#[cfg(test)]
+mod rom_loader_tests {
+    use crate::consts::ROM_MAX_SIZE;
+    use crate::rom_loader::{extract_rom, RomLoadError};
+
+    use std::io::{Cursor, Write};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use zip::write::{ZipWriter, SimpleFileOptions};
+
+    fn create_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        return zip.finish().unwrap().into_inner();
+    }
+
+    #[test]
+    fn test_plain_rom() {
+        assert_eq!(extract_rom(vec![0x00, 0xC3, 0x50, 0x01], None).unwrap(), vec![0x00, 0xC3, 0x50, 0x01]);
+    }
+
+    #[test]
+    fn test_gzip_rom() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0x11; 0x8000]).unwrap();
+
+        assert_eq!(extract_rom(encoder.finish().unwrap(), None).unwrap(), vec![0x11; 0x8000]);
+    }
+
+    #[test]
+    fn test_zip_rom() {
+        let archive = create_zip(&[
+            ("readme.txt", b"hello"),
+            ("roms/game.GBC", &[0x22; 0x10]),
+            ("other.gb", &[0x33; 0x10])
+        ]);
+
+        // First rom by default, or the named one (full path or just the file name)
+        assert_eq!(extract_rom(archive.clone(), None).unwrap(), vec![0x22; 0x10]);
+        assert_eq!(extract_rom(archive.clone(), Some("other.gb")).unwrap(), vec![0x33; 0x10]);
+        assert_eq!(extract_rom(archive.clone(), Some("game.GBC")).unwrap(), vec![0x22; 0x10]);
+        assert_eq!(extract_rom(archive.clone(), Some("readme.txt")).unwrap(), b"hello".to_vec());
+
+        assert!(matches!(extract_rom(archive, Some("missing.gb")), Err(RomLoadError::EntryNotFound(_))));
+        assert!(matches!(extract_rom(create_zip(&[("readme.txt", b"hello")]), None), Err(RomLoadError::NoRomInArchive)));
+        assert!(matches!(extract_rom(vec![0x50, 0x4B, 0x03, 0x04, 0x00], None), Err(RomLoadError::Archive(_))));
+    }
+
+    #[test]
+    fn test_archive_too_large() {
+        // Compresses down to a few KiB, decompressing stops right after the largest rom size
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&vec![0x00; ROM_MAX_SIZE + 1]).unwrap();
+        assert!(matches!(extract_rom(encoder.finish().unwrap(), None), Err(RomLoadError::TooLarge)));
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&vec![0x00; ROM_MAX_SIZE]).unwrap();
+        assert_eq!(extract_rom(encoder.finish().unwrap(), None).unwrap().len(), ROM_MAX_SIZE);
+
+        let archive = create_zip(&[("huge.gb", &vec![0x00; ROM_MAX_SIZE + 1])]);
+        assert!(matches!(extract_rom(archive, None), Err(RomLoadError::TooLarge)));
+    }
+}
+
+
+#[cfg(test)]
+mod patch_tests {
+    use crate::patch::{apply_patch, PatchError};
+
+    fn source_rom() -> Vec<u8> {
+        (0..16).collect()
+    }
+
+    // Source, target and patch CRC32s
+    fn add_crc_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(&patch).to_le_bytes());
+        return patch;
+    }
+
+    #[test]
+    fn test_ips() {
+        let mut patch: Vec<u8> = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]); // 2 bytes at 0x0002
+        patch.extend_from_slice(&[0x00, 0x00, 0x0E, 0x00, 0x00, 0x00, 0x04, 0xCC]); // 4 times 0xCC at 0x000E, grows the rom
+        patch.extend_from_slice(b"EOF");
+
+        assert_eq!(apply_patch(&source_rom(), &patch).unwrap(),
+            vec![0, 1, 0xAA, 0xBB, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 0xCC, 0xCC, 0xCC, 0xCC]);
+
+        // Truncation after the end marker
+        patch.extend_from_slice(&[0x00, 0x00, 0x04]);
+        assert_eq!(apply_patch(&source_rom(), &patch).unwrap(), vec![0, 1, 0xAA, 0xBB]);
+
+        assert_eq!(apply_patch(&source_rom(), b"PATCH\x00\x00\x02\x00\x05\xAA"), Err(PatchError::Truncated));
+        assert_eq!(apply_patch(&source_rom(), b"NOT A PATCH"), Err(PatchError::UnknownFormat));
+    }
+
+    #[test]
+    fn test_ups() {
+        let mut target = source_rom();
+        target[2] = 0xAA;
+        target[5] = 0xBB;
+        target.extend_from_slice(&[0xCC, 0xDD]);
+
+        let mut patch: Vec<u8> = b"UPS1".to_vec();
+        patch.extend_from_slice(&[0x90, 0x92]); // Source size 16, target size 18
+        patch.extend_from_slice(&[0x82, 0x02 ^ 0xAA, 0x00]); // Skip 2
+        patch.extend_from_slice(&[0x81, 0x05 ^ 0xBB, 0x00]); // Skip 1
+        patch.extend_from_slice(&[0x89, 0xCC, 0xDD, 0x00]); // Skip 9, past the end of the source
+        let patch = add_crc_footer(patch, &source_rom(), &target);
+
+        assert_eq!(apply_patch(&source_rom(), &patch).unwrap(), target);
+
+        // Different rom
+        let mut other_rom = source_rom();
+        other_rom[0] = 0xFF;
+        assert!(matches!(apply_patch(&other_rom, &patch), Err(PatchError::SourceCrc { .. })));
+        assert!(matches!(apply_patch(&source_rom()[..8], &patch), Err(PatchError::SourceSize { expected: 16, actual: 8 })));
+
+        // Corrupt patch
+        let mut corrupt_patch = patch.clone();
+        corrupt_patch[7] ^= 0x01;
+        assert!(matches!(apply_patch(&source_rom(), &corrupt_patch), Err(PatchError::PatchCrc { .. })));
+    }
+
+    #[test]
+    fn test_bps() {
+        let target: Vec<u8> = vec![0, 1, 2, 3, b'X', b'Y', b'Z', 8, 9, 10, 11, 0, 1, 2];
+
+        let mut patch: Vec<u8> = b"BPS1".to_vec();
+        patch.extend_from_slice(&[0x90, 0x8E, 0x80]); // Source size 16, target size 14, no metadata
+        patch.push(0x8C); // Source read 4
+        patch.extend_from_slice(&[0x89, b'X', b'Y', b'Z']); // Target read 3
+        patch.extend_from_slice(&[0x8E, 0x90]); // Source copy 4 from +8
+        patch.extend_from_slice(&[0x8B, 0x80]); // Target copy 3 from +0
+        let patch = add_crc_footer(patch, &source_rom(), &target);
+
+        assert_eq!(apply_patch(&source_rom(), &patch).unwrap(), target);
+
+        // Target CRC is checked after patching
+        let mut bad_target = target.clone();
+        bad_target[0] = 0xFF;
+        let mut patch_without_footer = patch[..patch.len() - 12].to_vec();
+        patch_without_footer = add_crc_footer(patch_without_footer, &source_rom(), &bad_target);
+        assert!(matches!(apply_patch(&source_rom(), &patch_without_footer), Err(PatchError::TargetCrc { .. })));
+    }
+
+    #[test]
+    fn test_malicious_patches() {
+        // Huge target sizes are refused before anything is allocated
+        for magic in [b"UPS1", b"BPS1"] {
+            let mut patch: Vec<u8> = magic.to_vec();
+            patch.extend_from_slice(&[0x90, 0x7F, 0x7F, 0x7F, 0x7F, 0x80, 0x80]); // Source size 16, target size ~34 GB
+            let patch = add_crc_footer(patch, &source_rom(), &[]);
+            assert!(matches!(apply_patch(&source_rom(), &patch), Err(PatchError::TargetTooLarge(_))));
+        }
+
+        // Numbers that don't fit in a usize
+        let mut patch: Vec<u8> = b"UPS1".to_vec();
+        patch.extend_from_slice(&[0x7F; 10]);
+        patch.push(0xFF);
+        let patch = add_crc_footer(patch, &source_rom(), &[]);
+        assert!(matches!(apply_patch(&source_rom(), &patch), Err(PatchError::InvalidAction(_))));
+
+        // A hunk that skips past the end of the rom
+        let mut patch: Vec<u8> = b"UPS1".to_vec();
+        patch.extend_from_slice(&[0x90, 0x90]);
+        patch.extend_from_slice(&[0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x80, 0x01, 0x00]);
+        let patch = add_crc_footer(patch, &source_rom(), &source_rom());
+        assert!(matches!(apply_patch(&source_rom(), &patch), Err(PatchError::InvalidAction(_))));
+
+        // A source copy whose offset wraps around
+        let mut patch: Vec<u8> = b"BPS1".to_vec();
+        patch.extend_from_slice(&[0x90, 0x90, 0x80]);
+        patch.extend_from_slice(&[0x8E, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x7F, 0x81]);
+        let patch = add_crc_footer(patch, &source_rom(), &source_rom());
+        assert!(matches!(apply_patch(&source_rom(), &patch), Err(PatchError::InvalidAction(_))));
+    }
+}
+
+
+#[cfg(test)]
+mod cheats_tests {
+    use crate::cheats::{Cheats, CheatCode};
+    use crate::cpu::CPU;
+    use crate::bus::{Bus, MemoryBus};
+    use crate::rom_parser::Rom;
+    use crate::ram_memory::RamMemory;
+
+    #[test]
+    fn test_parse_codes() {
+        assert_eq!(CheatCode::parse("3E1-23B"), Ok(CheatCode::GameGenie { addr: 0x4123, value: 0x3E, compare: None }));
+        assert_eq!(CheatCode::parse("3e1-23b-fae"), Ok(CheatCode::GameGenie { addr: 0x4123, value: 0x3E, compare: Some(0x05) }));
+        assert_eq!(CheatCode::parse("01FF34C1"), Ok(CheatCode::GameShark { bank: 0x01, addr: 0xC134, value: 0xFF }));
+
+        assert!(CheatCode::parse("3E1-23").is_err());
+        assert!(CheatCode::parse("3E123B").is_err());
+        assert!(CheatCode::parse("01FF34CG").is_err());
+    }
+
+    #[test]
+    fn test_game_genie_decode() {
+        // Compare value 0x3C: 0x3C ^ 0xBA = 0x86, rotated left by 2 is 0x1A, so G is 1 and I is A.
+        // Rotating the wrong way would give 0xD2
+        assert_eq!(CheatCode::parse("C91-23B-18A"), Ok(CheatCode::GameGenie { addr: 0x4123, value: 0xC9, compare: Some(0x3C) }));
+
+        // Address nibble F is xored with 0xF, F -> 0x0
+        assert_eq!(CheatCode::parse("FFD-EAF"), Ok(CheatCode::GameGenie { addr: 0x0DEA, value: 0xFF, compare: None }));
+    }
+
+    #[test]
+    fn test_cheats_file() {
+        let cheats = Cheats::parse("# Comment\n\n3E1-23B-FAE Infinite lives\n01FF34C1\n").unwrap();
+        assert_eq!(cheats.get_lines(), vec!["#0 [x] 3E1-23B-FAE Infinite lives", "#1 [x] 01FF34C1"]);
+
+        assert_eq!(Cheats::parse("01FF34C1\nXYZ").err(), Some("Line 2: Invalid cheat code \"XYZ\", not hex".to_string()));
+    }
+
+    #[test]
+    fn test_game_genie() {
+        let mut cheats = Cheats::new();
+        cheats.add("3E1-23B", "").unwrap();
+        cheats.add("991-24B-FAE", "").unwrap(); // Only when the rom has 0x05 at 0x4124
+
+        assert_eq!(cheats.apply_rom_read(0x4123, 0x00), 0x3E);
+        assert_eq!(cheats.apply_rom_read(0x4122, 0x00), 0x00);
+        assert_eq!(cheats.apply_rom_read(0x4124, 0x05), 0x99);
+        assert_eq!(cheats.apply_rom_read(0x4124, 0x06), 0x06);
+
+        cheats.set_enabled(0, false).unwrap();
+        assert_eq!(cheats.apply_rom_read(0x4123, 0x00), 0x00);
+    }
+
+    #[test]
+    fn test_game_shark_every_frame() {
+        let mut bus = MemoryBus::new(RamMemory::init_from_rom(&Rom::create_test_rom()));
+        let mut cpu: CPU = CPU::init(false);
+        bus.set_addr(0xFF40, 0x91);
+        cpu.set_program_counter(0x0000); // NOPs
+
+        let mut cheats = Cheats::new();
+        cheats.add("0163ABC1", "").unwrap();
+        bus.set_cheats(cheats);
+
+        // A frame is 70224 cycles, VBlank starts after 144 lines
+        while bus.get_frame_counter() == 0 {
+            assert_eq!(bus.get_addr(0xC1AB), 0x00);
+            cpu.execute_instruction(&mut bus).unwrap();
+        }
+        assert_eq!(bus.get_cycle_counter(), 144 * 456);
+        assert_eq!(bus.get_addr(0xC1AB), 0x63);
+
+        // Written again on the next frame
+        bus.set_addr(0xC1AB, 0x01);
+        while bus.get_frame_counter() == 1 {
+            cpu.execute_instruction(&mut bus).unwrap();
+        }
+        assert_eq!(bus.get_addr(0xC1AB), 0x63);
+    }
+}
+
+
+#[cfg(test)]
+mod ram_search_tests {
+    use crate::ram_memory::RamMemory;
+    use crate::ram_search::{RamSearch, SearchFilter};
+    use crate::rom_parser::Rom;
+
+    #[test]
+    fn test_ram_search_filters() {
+        let mut ram_memory = RamMemory::init_from_rom(&Rom::create_test_rom());
+        ram_memory.set_addr(0xC100, 3); // Lives
+        ram_memory.set_addr(0xFF90, 3);
+        ram_memory.set_addr(0xA010, 3);
+
+        let mut ram_search = RamSearch::new(&ram_memory);
+        assert_eq!(ram_search.len(), 0x2000 + 0x2000 + 0x7F);
+
+        assert_eq!(ram_search.filter(&ram_memory, SearchFilter::Value(3)), 3);
+
+        // Lose a life
+        ram_memory.set_addr(0xC100, 2);
+        ram_memory.set_addr(0xFF90, 4);
+        assert_eq!(ram_search.filter(&ram_memory, SearchFilter::Changed), 2);
+        assert_eq!(ram_search.filter(&ram_memory, SearchFilter::Equal), 2);
+
+        ram_memory.set_addr(0xC100, 1);
+        ram_memory.set_addr(0xFF90, 5);
+        assert_eq!(ram_search.filter(&ram_memory, SearchFilter::Decreased), 1);
+        assert_eq!(ram_search.get_candidates(), &[(0xC100, 1)]);
+
+        ram_memory.set_addr(0xC100, 2);
+        assert_eq!(ram_search.filter(&ram_memory, SearchFilter::Increased), 1);
+        assert_eq!(ram_search.get_lines(20), vec!["1 candidates", "0xC100 = 0x02"]);
+    }
+
+    #[test]
+    fn test_ram_search_filter_parse() {
+        assert_eq!(SearchFilter::parse("eq", None), Some(SearchFilter::Equal));
+        assert_eq!(SearchFilter::parse("changed", None), Some(SearchFilter::Changed));
+        assert_eq!(SearchFilter::parse("inc", None), Some(SearchFilter::Increased));
+        assert_eq!(SearchFilter::parse("dec", None), Some(SearchFilter::Decreased));
+        assert_eq!(SearchFilter::parse("value", Some("12")), Some(SearchFilter::Value(12)));
+        assert_eq!(SearchFilter::parse("value", Some("0x1F")), Some(SearchFilter::Value(0x1F)));
+        assert_eq!(SearchFilter::parse("value", Some("300")), None);
+        assert_eq!(SearchFilter::parse("value", None), None);
+        assert_eq!(SearchFilter::parse("eq", Some("1")), None);
+    }
+}
+
+
+#[cfg(test)]
+mod gameboy_tests {
+    use crate::consts::*;
+    use crate::{GameBoy, Button, Model};
+
+    // 0x0100: JR 0x0100
+    fn create_gameboy() -> GameBoy {
+        let mut rom_content: Vec<u8> = vec![0x00; 0x8000];
+        rom_content[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+        return GameBoy::load(rom_content).unwrap();
+    }
+
+    #[test]
+    fn test_gameboy_run_frame() {
+        let mut gameboy = create_gameboy();
+        assert_eq!(gameboy.get_model(), Model::DMG);
+        assert_eq!(gameboy.get_cpu().get_program_counter(), 0x0100);
+
+        gameboy.run_frame().unwrap();
+        assert_eq!(gameboy.get_frame_counter(), 1);
+        gameboy.run_frame().unwrap();
+        assert_eq!(gameboy.get_frame_counter(), 2);
+        assert_eq!(gameboy.get_framebuffer().len(), LCD_WIDTH * LCD_HEIGHT);
+        let screenshot = gameboy.get_screenshot();
+        assert_eq!((screenshot.width, screenshot.height), (LCD_WIDTH, LCD_HEIGHT));
+        assert_eq!(gameboy.get_framebuffer(), screenshot.pixels);
+
+        // No frames with the LCD off, but run_frame still returns
+        gameboy.write_memory(PPU_ADDR_LCD_CONTROL, 0x00);
+        gameboy.run_frame().unwrap();
+        assert_eq!(gameboy.get_frame_counter(), 2);
+    }
+
+    #[test]
+    fn test_gameboy_step_and_memory() {
+        let mut gameboy = create_gameboy();
+        gameboy.write_memory(0xC000, 0x42);
+        assert_eq!(gameboy.read_memory(0xC000), 0x42);
+        assert_eq!(gameboy.read_memory(0xE000), 0x42); // Echo ram
+
+        gameboy.step().unwrap();
+        assert_eq!(gameboy.get_cpu().get_program_counter(), 0x0100);
+    }
+
+    #[test]
+    fn test_gameboy_threads() {
+        fn assert_send<T: Send>() {}
+        assert_send::<GameBoy>();
+
+        // Instances are independent, each on its own thread
+        let handles: Vec<std::thread::JoinHandle<u8>> = (0..4u8).map(|i| {
+            let mut gameboy = create_gameboy();
+            std::thread::spawn(move || {
+                gameboy.write_memory(0xC000, i);
+                gameboy.run_frame().unwrap();
+                gameboy.read_memory(0xC000)
+            })
+        }).collect();
+
+        let values: Vec<u8> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(values, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_joypad() {
+        let mut gameboy = create_gameboy();
+        gameboy.set_button(Button::Down, true);
+        gameboy.set_button(Button::A, true);
+
+        // Directions
+        gameboy.write_memory(JOYPAD_ADDR, 0x20);
+        assert_eq!(gameboy.read_memory(JOYPAD_ADDR), 0xE7);
+
+        // Actions
+        gameboy.write_memory(JOYPAD_ADDR, 0x10);
+        assert_eq!(gameboy.read_memory(JOYPAD_ADDR), 0xDE);
+
+        // Both, or nothing selected
+        gameboy.write_memory(JOYPAD_ADDR, 0x00);
+        assert_eq!(gameboy.read_memory(JOYPAD_ADDR), 0xC6);
+        gameboy.write_memory(JOYPAD_ADDR, 0x30);
+        assert_eq!(gameboy.read_memory(JOYPAD_ADDR), 0xFF);
+
+        gameboy.set_button(Button::Down, false);
+        gameboy.write_memory(JOYPAD_ADDR, 0x20);
+        assert_eq!(gameboy.read_memory(JOYPAD_ADDR), 0xEF);
+
+        assert_eq!(Button::from_name("Start"), Some(Button::Start));
+        assert_eq!(Button::from_name("turbo"), None);
+    }
+
+    #[test]
+    fn test_serial_output() {
+        let mut gameboy = create_gameboy();
+
+        for byte in "Passed".bytes() {
+            gameboy.write_memory(SERIAL_ADDR_DATA, byte);
+            gameboy.write_memory(SERIAL_ADDR_CONTROL, 0x81);
+        }
+        assert_eq!(gameboy.get_serial_output(), b"Passed");
+
+        // Done right away, with 0xFF shifted in and the serial interrupt requested
+        assert_eq!(gameboy.read_memory(SERIAL_ADDR_CONTROL), 0x01);
+        assert_eq!(gameboy.read_memory(SERIAL_ADDR_DATA), 0xFF);
+        assert_eq!(gameboy.read_memory(INTERRUPT_ADDR_FLAG) & (1 << INTERRUPT_BIT_SERIAL), 1 << INTERRUPT_BIT_SERIAL);
+
+        // Waiting for the other side's clock never finishes
+        gameboy.write_memory(SERIAL_ADDR_DATA, 0x42);
+        gameboy.write_memory(SERIAL_ADDR_CONTROL, 0x80);
+        assert_eq!(gameboy.get_serial_output().len(), 6);
+        assert_eq!(gameboy.read_memory(SERIAL_ADDR_CONTROL), 0x80);
+    }
+}
+
+#[cfg(test)]
+mod screenshot_tests {
+    use crate::consts::*;
+    use crate::screenshot::{self, Screenshot, Reference, ScreenshotError};
+    use crate::{GameBoy, Button, InputEvent};
+
+    fn create_screenshot() -> Screenshot {
+        let pixels: Vec<u32> = (0..SCREEN_WIDTH * SCREEN_HEIGHT).map(|i| [COLOR_WHITE, COLOR_LIGHT_GREY, COLOR_DARK_GREY, COLOR_BLACK][i % 4]).collect();
+        return Screenshot::from_framebuffer(&pixels);
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let screenshot = create_screenshot();
+        let path = std::env::temp_dir().join("gbemulator_test_screenshot.png");
+        screenshot.save_png(&path).unwrap();
+
+        let loaded = Screenshot::load_png(&path).unwrap();
+        assert_eq!(loaded, screenshot);
+        assert_eq!(loaded.get_hash(), screenshot.get_hash());
+        assert!(screenshot.compare(&Reference::Png(path.clone()), &path.with_extension("diff.png")).is_ok());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_compare_mismatch() {
+        let screenshot = create_screenshot();
+        let reference_path = std::env::temp_dir().join("gbemulator_test_reference.png");
+        let diff_path = std::env::temp_dir().join("gbemulator_test_reference_diff.png");
+        screenshot.save_png(&reference_path).unwrap();
+
+        let mut changed = screenshot.clone();
+        changed.pixels[0] = COLOR_BLACK;
+        changed.pixels[SCREEN_WIDTH + 1] = COLOR_WHITE;
+
+        match changed.compare(&Reference::Png(reference_path.clone()), &diff_path) {
+            Err(ScreenshotError::PixelMismatch { differing_pixels }) => assert_eq!(differing_pixels, 2),
+            result => panic!("Unexpected result {:?}", result)
+        }
+
+        // Differing pixels are red in the diff, the rest are faded
+        let diff = Screenshot::load_png(&diff_path).unwrap();
+        assert_eq!(diff.pixels[0], SCREENSHOT_DIFF_COLOR);
+        assert_eq!(diff.pixels[SCREEN_WIDTH + 1], SCREENSHOT_DIFF_COLOR);
+        assert_eq!(diff.pixels[2], 0x00d5d5d5);
+        assert_eq!(diff.pixels.iter().filter(|pixel| **pixel == SCREENSHOT_DIFF_COLOR).count(), 2);
+
+        // Only the hash is known, the diff path gets the screenshot itself
+        assert!(changed.compare(&Reference::Hash(screenshot.get_hash().to_uppercase()), &diff_path).is_err());
+        assert_eq!(Screenshot::load_png(&diff_path).unwrap(), changed);
+        assert!(screenshot.compare(&Reference::Hash(screenshot.get_hash().to_uppercase()), &diff_path).is_ok());
+
+        std::fs::remove_file(reference_path).unwrap();
+        std::fs::remove_file(diff_path).unwrap();
+    }
+
+    #[test]
+    fn test_crop_and_upscale() {
+        let screenshot = create_screenshot();
+
+        let lcd = screenshot.crop(LCD_WIDTH, LCD_HEIGHT);
+        assert_eq!((lcd.width, lcd.height, lcd.pixels.len()), (LCD_WIDTH, LCD_HEIGHT, LCD_WIDTH * LCD_HEIGHT));
+        assert_eq!(lcd.pixels[LCD_WIDTH + 1], screenshot.pixels[SCREEN_WIDTH + 1]);
+        assert_eq!(lcd.pixels[LCD_WIDTH * LCD_HEIGHT - 1], screenshot.pixels[(LCD_HEIGHT - 1) * SCREEN_WIDTH + LCD_WIDTH - 1]);
+
+        let scaled = lcd.upscale(3);
+        assert_eq!((scaled.width, scaled.height, scaled.pixels.len()), (LCD_WIDTH * 3, LCD_HEIGHT * 3, LCD_WIDTH * LCD_HEIGHT * 9));
+        for (x, y) in [(0, 0), (2, 2), (3, 0), (5, 7), (479, 431)] {
+            assert_eq!(scaled.pixels[y * scaled.width + x], lcd.pixels[(y / 3) * LCD_WIDTH + x / 3]);
+        }
+        assert_eq!(lcd.upscale(1), lcd);
+    }
+
+    #[test]
+    fn test_save_screenshot() {
+        let mut rom_content: Vec<u8> = vec![0x00; 0x8000];
+        rom_content[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+        let gameboy = GameBoy::load(rom_content).unwrap();
+
+        let path = gameboy.save_screenshot(&std::env::temp_dir(), 2).unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.starts_with(SCREENSHOT_FILE_PREFIX));
+        assert!(name.ends_with(".png"));
+
+        let saved = Screenshot::load_png(&path).unwrap();
+        assert_eq!((saved.width, saved.height), (LCD_WIDTH * 2, LCD_HEIGHT * 2));
+        assert_eq!(saved, gameboy.get_screenshot().upscale(2));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(screenshot::format_timestamp(0), "19700101_000000_000");
+        assert_eq!(screenshot::format_timestamp(951_782_400_123), "20000229_000000_123");
+        assert_eq!(screenshot::format_timestamp(1_792_367_999_999), "20261018_235959_999");
+
+        let path = screenshot::get_timestamped_path(std::path::Path::new("shots"), "tetris");
+        assert_eq!(path.parent().unwrap(), std::path::Path::new("shots"));
+        assert_eq!(path.file_name().unwrap().len(), "tetris_YYYYMMDD_HHMMSS_mmm.png".len());
+    }
+
+    #[test]
+    fn test_input_event_parse() {
+        assert_eq!(InputEvent::parse("120:Start:press"), Ok(InputEvent { frame: 120, button: Button::Start, pressed: true }));
+        assert_eq!(InputEvent::parse("3:a:release"), Ok(InputEvent { frame: 3, button: Button::A, pressed: false }));
+        assert!(InputEvent::parse("3:a").is_err());
+        assert!(InputEvent::parse("x:a:press").is_err());
+        assert!(InputEvent::parse("3:turbo:press").is_err());
+        assert!(InputEvent::parse("3:a:hold").is_err());
+    }
+}
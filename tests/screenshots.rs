@@ -0,0 +1,124 @@
+// Rendering regression tests, roms run headlessly for a number of frames with scripted input and the
+// 160x144 LCD is compared against a reference png (or hash). On a mismatch the diff is written to
+// target/screenshots/<name>-diff.png, run with UPDATE_SCREENSHOTS=1 to accept the new output instead.
+//
+// Our own fixtures are built here and their references are in tests/fixtures/screenshots. Other roms
+// (dmg-acid2...) can be dropped in SCREENSHOT_ROMS_DIR (tests/data/screenshots by default) as
+// <name>.gb next to a <name>.png reference, they get SCREENSHOT_FRAMES frames.
+
+// Explicit returns, like the library
+#![allow(clippy::needless_return)]
+
+use gbemulator::{GameBoy, Button, InputEvent};
+use gbemulator::screenshot::Reference;
+
+use std::path::{Path, PathBuf};
+
+const FIXTURES_DIR: &str = "tests/fixtures/screenshots";
+const DIFF_DIR: &str = "target/screenshots";
+const DEFAULT_ROMS_DIR: &str = "tests/data/screenshots";
+const DEFAULT_FRAMES: usize = 60;
+
+// Fills tile 1 with color 3 and the BG map with alternating tiles 1 and 2 (empty, color 0), then keeps
+// copying P1 (with the action buttons selected) to BGP, so pressing A changes the color of tile 2
+const STRIPES_PROGRAM: [u8; 34] = [
+    0x21, 0x10, 0x80, // LD HL,0x8010
+    0x3E, 0xFF,       // LD A,0xFF
+    0x06, 0x10,       // LD B,16
+    0x22,             // LD (HL+),A
+    0x05,             // DEC B
+    0x20, 0xFC,       // JR NZ,-4
+    0x21, 0x00, 0x98, // LD HL,0x9800
+    0x7D,             // LD A,L
+    0xE6, 0x01,       // AND 0x01
+    0x3C,             // INC A
+    0x22,             // LD (HL+),A
+    0x7C,             // LD A,H
+    0xFE, 0x9C,       // CP 0x9C
+    0x20, 0xF6,       // JR NZ,-10
+    0x3E, 0x10,       // LD A,0x10
+    0xE0, 0x00,       // LDH (0x00),A
+    0xF0, 0x00,       // LDH A,(0x00)
+    0xE0, 0x47,       // LDH (0x47),A
+    0x18, 0xF6        // JR -10
+];
+
+fn create_rom(program: &[u8]) -> Vec<u8> {
+    let mut rom_content: Vec<u8> = vec![0x00; 0x8000];
+    rom_content[0x100..0x100 + program.len()].copy_from_slice(program);
+    return rom_content;
+}
+
+// Panics with the path of the diff image when the screen doesn't match
+fn check_screenshot(name: &str, rom_content: Vec<u8>, frames: usize, inputs: &[InputEvent], reference: Reference) {
+    let mut gameboy = GameBoy::load(rom_content).expect("Failed loading rom");
+    gameboy.run_frames(frames, inputs).unwrap_or_else(|e| panic!("{}: {}", name, e));
+    let screenshot = gameboy.get_screenshot();
+
+    if let Reference::Png(path) = &reference {
+        if std::env::var("UPDATE_SCREENSHOTS").is_ok() {
+            screenshot.save_png(path).expect("Failed updating reference");
+            eprintln!("{}: updated \"{}\"", name, path.display());
+            return;
+        }
+    }
+
+    std::fs::create_dir_all(DIFF_DIR).expect("Failed creating diff directory");
+    let diff_path = Path::new(DIFF_DIR).join(format!("{}-diff.png", name));
+    if let Err(e) = screenshot.compare(&reference, &diff_path) {
+        panic!("{}: {} (see \"{}\")", name, e, diff_path.display());
+    }
+}
+
+fn get_fixture(name: &str) -> Reference {
+    Reference::Png(Path::new(FIXTURES_DIR).join(format!("{}.png", name)))
+}
+
+#[test]
+fn test_stripes() {
+    check_screenshot("stripes", create_rom(&STRIPES_PROGRAM), 3, &[], get_fixture("stripes"));
+}
+
+#[test]
+fn test_stripes_with_input() {
+    let inputs = [
+        InputEvent { frame: 1, button: Button::A, pressed: true }
+    ];
+    check_screenshot("stripes_a", create_rom(&STRIPES_PROGRAM), 3, &inputs, get_fixture("stripes_a"));
+
+    // Released again the screen goes back to the first one
+    let inputs = [
+        InputEvent { frame: 1, button: Button::A, pressed: true },
+        InputEvent { frame: 2, button: Button::A, pressed: false }
+    ];
+    check_screenshot("stripes_released", create_rom(&STRIPES_PROGRAM), 4, &inputs, get_fixture("stripes"));
+}
+
+#[test]
+fn test_stripes_hash() {
+    let reference = gbemulator::screenshot::Screenshot::load_png(&Path::new(FIXTURES_DIR).join("stripes.png")).unwrap();
+    check_screenshot("stripes_hash", create_rom(&STRIPES_PROGRAM), 3, &[], Reference::Hash(reference.get_hash()));
+}
+
+#[test]
+fn test_screenshot_roms() {
+    let roms_dir = PathBuf::from(std::env::var("SCREENSHOT_ROMS_DIR").unwrap_or(DEFAULT_ROMS_DIR.to_string()));
+    let frames: usize = std::env::var("SCREENSHOT_FRAMES").map(|frames| frames.parse().expect("Invalid SCREENSHOT_FRAMES")).unwrap_or(DEFAULT_FRAMES);
+
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(&roms_dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "gb" || extension == "gbc"))
+            .collect(),
+        Err(_) => {
+            eprintln!("No screenshot roms at \"{}\", skipping (set SCREENSHOT_ROMS_DIR)", roms_dir.display());
+            return;
+        }
+    };
+    paths.sort();
+
+    for path in paths {
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        let rom_content = std::fs::read(&path).expect("Failed reading rom");
+        check_screenshot(&name, rom_content, frames, &[], Reference::Png(path.with_extension("png")));
+    }
+}
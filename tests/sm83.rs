@@ -0,0 +1,248 @@
+// Runs the SM83 single step tests (https://github.com/SingleStepTests/sm83), a json file per opcode with
+// the cpu state before and after a single instruction and the bus cycles in between.
+// They are too big for the repository, point SM83_TESTS_DIR to the v1 directory of a checkout
+// (tests/data/sm83/v1 by default), without it the test is skipped.
+//
+// The states are taken after the opcode was fetched, so the opcode is at pc - 1 and the final pc is one
+// past the opcode of the next instruction.
+//
+// Every bus access is recorded and compared with the reads/writes in the test's cycles, in order. The cpu
+// ticks once per instruction, so which M-cycle an access lands in can't be checked, only the total.
+
+// Explicit returns, like the library
+#![allow(clippy::needless_return)]
+
+use gbemulator::bus::Bus;
+use gbemulator::consts::{INTERRUPT_ADDR_ENABLE, INTERRUPT_ADDR_FLAG};
+use gbemulator::cpu::CPU;
+use gbemulator::registers::{Reg8, Reg16};
+
+use serde_json::Value;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_TESTS_DIR: &str = "tests/data/sm83/v1";
+const REGISTERS: [(&str, Reg8); 8] = [
+    ("a", Reg8::A), ("b", Reg8::B), ("c", Reg8::C), ("d", Reg8::D),
+    ("e", Reg8::E), ("f", Reg8::F), ("h", Reg8::H), ("l", Reg8::L)
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Access {
+    Read(u16, u8),
+    Write(u16, u8)
+}
+
+impl Access {
+    fn get_text(&self) -> String {
+        match self {
+            Access::Read(addr, value) => format!("read 0x{:04X}=0x{:02X}", addr, value),
+            Access::Write(addr, value) => format!("write 0x{:04X}=0x{:02X}", addr, value)
+        }
+    }
+}
+
+// All 64 KiB as plain memory, no io registers or echo ram to get in the way
+struct TestBus {
+    memory: Vec<u8>,
+    accesses: RefCell<Vec<Access>>, // Reads only borrow the bus
+    cycles: usize
+}
+
+impl TestBus {
+    fn new() -> TestBus {
+        TestBus {
+            memory: vec![0x00; 0x10000],
+            accesses: RefCell::new(Vec::new()),
+            cycles: 0
+        }
+    }
+
+    // What the instruction did on the bus. The interrupt checks and the opcode fetch (the cpu reads the
+    // opcode more than once) come before it and aren't part of the test's cycles
+    fn take_accesses(&self, opcode_addr: u16) -> Vec<Access> {
+        let accesses = self.accesses.take();
+        let is_opcode_read = |access: &Access| matches!(access, Access::Read(addr, _) if *addr == opcode_addr);
+
+        let prefix = accesses.iter().take_while(|access| {
+            is_opcode_read(access) || matches!(access, Access::Read(INTERRUPT_ADDR_ENABLE | INTERRUPT_ADDR_FLAG, _))
+        }).count();
+        let start = accesses[..prefix].iter().rposition(is_opcode_read).map(|i| i + 1).unwrap_or(0);
+
+        return accesses[start..].to_vec();
+    }
+}
+
+impl Bus for TestBus {
+    fn get_addr(&self, addr: u16) -> u8 {
+        let value = self.memory[addr as usize];
+        self.accesses.borrow_mut().push(Access::Read(addr, value));
+        return value;
+    }
+
+    fn set_addr(&mut self, addr: u16, value: u8) {
+        self.accesses.get_mut().push(Access::Write(addr, value));
+        self.memory[addr as usize] = value;
+    }
+
+    fn tick(&mut self, cycles: usize) {
+        self.cycles += cycles;
+    }
+}
+
+fn get_u16(state: &Value, name: &str) -> u16 {
+    state[name].as_u64().unwrap_or_else(|| panic!("Missing \"{}\" in test state", name)) as u16
+}
+
+fn setup(state: &Value) -> (CPU, TestBus) {
+    let mut cpu = CPU::init(false);
+    let mut bus = TestBus::new();
+
+    for (name, reg) in REGISTERS {
+        cpu.set_register(reg, get_u16(state, name) as u8);
+    }
+    cpu.set_double_register(Reg16::SP, get_u16(state, "sp"));
+    cpu.set_double_register(Reg16::PC, get_u16(state, "pc").wrapping_sub(1));
+    cpu.set_ime(get_u16(state, "ime") != 0);
+
+    for entry in state["ram"].as_array().unwrap() {
+        bus.memory[entry[0].as_u64().unwrap() as usize] = entry[1].as_u64().unwrap() as u8;
+    }
+    if state["ie"].is_u64() {
+        bus.memory[0xFFFF] = get_u16(state, "ie") as u8;
+    }
+
+    return (cpu, bus);
+}
+
+// Everything that doesn't match the final state
+fn compare(cpu: &CPU, bus: &TestBus, state: &Value, cycles: usize) -> Vec<String> {
+    let mut mismatches: Vec<String> = Vec::new();
+
+    for (name, reg) in REGISTERS {
+        let expected = get_u16(state, name) as u8;
+        if cpu.get_register(reg) != expected {
+            mismatches.push(format!("{}: 0x{:02X} != 0x{:02X}", name, cpu.get_register(reg), expected));
+        }
+    }
+
+    let checks = [
+        ("sp", cpu.get_stack_pointer(), get_u16(state, "sp")),
+        ("pc", cpu.get_program_counter().wrapping_add(1), get_u16(state, "pc")),
+        ("ime", cpu.is_ime_enabled() as u16, get_u16(state, "ime")),
+        ("cycles", bus.cycles as u16, cycles as u16)
+    ];
+    for (name, value, expected) in checks {
+        if value != expected {
+            mismatches.push(format!("{}: 0x{:04X} != 0x{:04X}", name, value, expected));
+        }
+    }
+
+    for entry in state["ram"].as_array().unwrap() {
+        let addr = entry[0].as_u64().unwrap() as usize;
+        let expected = entry[1].as_u64().unwrap() as u8;
+        if bus.memory[addr] != expected {
+            mismatches.push(format!("(0x{:04X}): 0x{:02X} != 0x{:02X}", addr, bus.memory[addr], expected));
+        }
+    }
+
+    return mismatches;
+}
+
+// Cycles are [addr, value, pins] with 'r' or 'w' in the pins, or null when the bus is idle
+fn get_expected_accesses(cycles: &[Value]) -> Vec<Access> {
+    return cycles.iter().filter_map(|cycle| {
+        let pins = cycle[2].as_str()?;
+        let addr = cycle[0].as_u64()? as u16;
+        let value = cycle[1].as_u64()? as u8;
+        if pins.contains('w') {
+            Some(Access::Write(addr, value))
+        } else if pins.contains('r') {
+            Some(Access::Read(addr, value))
+        } else {
+            None
+        }
+    }).collect();
+}
+
+// The first access that differs, a missing or extra one included
+fn compare_accesses(accesses: &[Access], expected: &[Access]) -> Option<String> {
+    for i in 0..accesses.len().max(expected.len()) {
+        let (access, expected_access) = (accesses.get(i), expected.get(i));
+        if access != expected_access {
+            let text = |access: Option<&Access>| access.map(|access| access.get_text()).unwrap_or("nothing".to_string());
+            return Some(format!("bus access {}: {} != {}", i, text(access), text(expected_access)));
+        }
+    }
+
+    return None;
+}
+
+// Number of tests in the file and the first failure, if any
+fn run_file(path: &Path) -> (usize, usize, Option<String>) {
+    let content = std::fs::read_to_string(path).expect("Failed reading test file");
+    let tests: Value = serde_json::from_str(&content).expect("Failed parsing test file");
+    let tests = tests.as_array().expect("Test file isn't an array");
+
+    let mut failed: usize = 0;
+    let mut first_failure: Option<String> = None;
+    for test in tests {
+        let (mut cpu, mut bus) = setup(&test["initial"]);
+        let opcode_addr = cpu.get_program_counter();
+
+        // Every cycle is an M-cycle, with or without bus activity
+        let expected_cycles: &[Value] = test["cycles"].as_array().map(|cycles| cycles.as_slice()).unwrap_or(&[]);
+        let cycles = expected_cycles.len() * 4;
+
+        let mismatches = match cpu.execute_instruction(&mut bus) {
+            Ok(()) => {
+                // The last M-cycle fetches the next opcode, the cpu leaves that to the next instruction
+                bus.get_addr(cpu.get_program_counter());
+                let accesses = bus.take_accesses(opcode_addr);
+
+                let mut mismatches = compare(&cpu, &bus, &test["final"], cycles);
+                mismatches.extend(compare_accesses(&accesses, &get_expected_accesses(expected_cycles)));
+                mismatches
+            },
+            Err(e) => vec![e.to_string()]
+        };
+
+        if !mismatches.is_empty() {
+            failed += 1;
+            if first_failure.is_none() {
+                first_failure = Some(format!("\"{}\": {}", test["name"].as_str().unwrap_or("?"), mismatches.join(", ")));
+            }
+        }
+    }
+
+    return (tests.len(), failed, first_failure);
+}
+
+#[test]
+fn sm83_single_step_tests() {
+    let tests_dir = PathBuf::from(std::env::var("SM83_TESTS_DIR").unwrap_or(DEFAULT_TESTS_DIR.to_string()));
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(&tests_dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .collect(),
+        Err(_) => {
+            eprintln!("No SM83 tests at \"{}\", skipping (set SM83_TESTS_DIR)", tests_dir.display());
+            return;
+        }
+    };
+    paths.sort();
+
+    let mut failed_files: Vec<String> = Vec::new();
+    for path in &paths {
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        let (total, failed, first_failure) = run_file(path);
+
+        if let Some(first_failure) = first_failure {
+            eprintln!("{:>6}: {}/{} failed, first {}", name, failed, total, first_failure);
+            failed_files.push(name);
+        }
+    }
+
+    eprintln!("SM83: {}/{} opcodes passed", paths.len() - failed_files.len(), paths.len());
+    assert!(failed_files.is_empty(), "Failing opcodes: {}", failed_files.join(", "));
+}
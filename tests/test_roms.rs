@@ -0,0 +1,239 @@
+// Runs a directory of test roms (Blargg's cpu_instrs, instr_timing, mem_timing, Mooneye's acceptance...)
+// headlessly and reports how many pass, so accuracy can be tracked over time.
+// The roms aren't in the repository, point TEST_ROMS_DIR to them (tests/data/roms by default), without
+// it the test is skipped. Every .gb/.gbc under it is run, grouped by the first directory under it.
+//
+// Blargg's roms print "Passed"/"Failed" over the serial port. Mooneye's load the fibonacci numbers
+// 3/5/8/13/21/34 into B/C/D/E/H/L (0x42 on failure) and execute LD B,B, newer ones send them over
+// serial as well.
+//
+// A failing rom doesn't fail the test, the summary table and the json report (TEST_ROMS_REPORT,
+// target/test_roms_report.json by default) are the result. Each rom gets TEST_ROMS_CYCLES cycles, run it
+// with --release --test test_roms -- --nocapture, a debug build takes minutes per rom.
+
+// Explicit returns, like the library
+#![allow(clippy::needless_return)]
+
+use gbemulator::gameboy::GameBoy;
+use gbemulator::registers::Reg8;
+
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_ROMS_DIR: &str = "tests/data/roms";
+const DEFAULT_REPORT_PATH: &str = "target/test_roms_report.json";
+const DEFAULT_CYCLE_BUDGET: usize = 4_194_304 * 120; // Two minutes, cpu_instrs takes almost one
+const MOONEYE_BREAKPOINT_OPCODE: u8 = 0x40; // LD B,B
+const MOONEYE_REGISTERS: [Reg8; 6] = [Reg8::B, Reg8::C, Reg8::D, Reg8::E, Reg8::H, Reg8::L];
+const MOONEYE_PASS_SIGNATURE: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL_SIGNATURE: [u8; 6] = [0x42; 6];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Passed,
+    Failed,
+    Timeout,
+    Error
+}
+
+impl Status {
+    fn get_name(&self) -> &'static str {
+        match self {
+            Status::Passed => "pass",
+            Status::Failed => "fail",
+            Status::Timeout => "timeout",
+            Status::Error => "error"
+        }
+    }
+}
+
+struct RomResult {
+    suite: String,
+    name: String,
+    status: Status,
+    detail: String,
+    cycles: usize,
+    millis: u128
+}
+
+fn get_env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| panic!("Invalid {} ({})", name, value)),
+        Err(_) => default
+    }
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension == "gb" || extension == "gbc") {
+            roms.push(path);
+        }
+    }
+}
+
+// What the rom reported over serial so far, if it's done
+fn check_serial(output: &[u8]) -> Option<(Status, String)> {
+    if output.ends_with(&MOONEYE_PASS_SIGNATURE) {
+        return Some((Status::Passed, String::new()));
+    }
+    if output.ends_with(&MOONEYE_FAIL_SIGNATURE) {
+        return Some((Status::Failed, "fibonacci signature not found".to_string()));
+    }
+
+    let text = String::from_utf8_lossy(output);
+    if text.contains("Passed") {
+        return Some((Status::Passed, String::new()));
+    }
+    if text.contains("Failed") {
+        // The last lines say what failed
+        let lines: Vec<&str> = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).collect();
+        return Some((Status::Failed, lines[lines.len().saturating_sub(3)..].join(" | ")));
+    }
+
+    return None;
+}
+
+fn check_registers(gameboy: &GameBoy) -> Option<(Status, String)> {
+    let values: Vec<u8> = MOONEYE_REGISTERS.iter().map(|reg| gameboy.get_cpu().get_register(*reg)).collect();
+    if values == MOONEYE_PASS_SIGNATURE {
+        return Some((Status::Passed, String::new()));
+    }
+    if values == MOONEYE_FAIL_SIGNATURE {
+        return Some((Status::Failed, "fibonacci signature not found".to_string()));
+    }
+
+    return None;
+}
+
+fn run_rom(gameboy: &mut GameBoy, cycle_budget: usize) -> (Status, String) {
+    let mut serial_length: usize = 0;
+
+    while gameboy.get_cycle_counter() < cycle_budget {
+        let opcode = gameboy.read_memory(gameboy.get_cpu().get_program_counter());
+        if let Err(e) = gameboy.step() {
+            return (Status::Error, e.to_string());
+        }
+
+        if opcode == MOONEYE_BREAKPOINT_OPCODE {
+            if let Some(result) = check_registers(gameboy) {
+                return result;
+            }
+        }
+
+        if gameboy.get_serial_output().len() != serial_length {
+            serial_length = gameboy.get_serial_output().len();
+            if let Some(result) = check_serial(gameboy.get_serial_output()) {
+                return result;
+            }
+        }
+
+        if gameboy.get_cpu().is_locked_up() {
+            return (Status::Error, format!("CPU locked up at 0x{:04X}", gameboy.get_cpu().get_program_counter()));
+        }
+    }
+
+    return (Status::Timeout, String::from_utf8_lossy(gameboy.get_serial_output()).trim().replace('\n', " | "));
+}
+
+fn run_path(roms_dir: &Path, path: &Path, cycle_budget: usize) -> RomResult {
+    let relative_path = path.strip_prefix(roms_dir).unwrap();
+    let suite = match relative_path.components().count() {
+        1 => "-".to_string(),
+        _ => relative_path.components().next().unwrap().as_os_str().to_string_lossy().to_string()
+    };
+    let name = relative_path.with_extension("").to_string_lossy().to_string();
+
+    let start = Instant::now();
+    let rom_content = std::fs::read(path).expect("Failed reading rom");
+    let (status, detail, cycles) = match GameBoy::load(rom_content) {
+        Ok(mut gameboy) => {
+            let (status, detail) = run_rom(&mut gameboy, cycle_budget);
+            (status, detail, gameboy.get_cycle_counter())
+        },
+        Err(e) => (Status::Error, e.to_string(), 0)
+    };
+
+    return RomResult {
+        suite,
+        name,
+        status,
+        detail,
+        cycles,
+        millis: start.elapsed().as_millis()
+    };
+}
+
+fn print_summary(results: &[RomResult]) {
+    let name_width = results.iter().map(|result| result.name.len()).max().unwrap_or(0).max(4);
+
+    eprintln!("{:<width$}  {:<7}  {:>8}  detail", "rom", "result", "ms", width = name_width);
+    for result in results {
+        eprintln!("{:<width$}  {:<7}  {:>8}  {}", result.name, result.status.get_name(), result.millis, result.detail, width = name_width);
+    }
+
+    let mut suites: Vec<&str> = results.iter().map(|result| result.suite.as_str()).collect();
+    suites.sort();
+    suites.dedup();
+    eprintln!();
+    for suite in suites {
+        let suite_results: Vec<&RomResult> = results.iter().filter(|result| result.suite == suite).collect();
+        let passed = suite_results.iter().filter(|result| result.status == Status::Passed).count();
+        eprintln!("{:<width$}  {}/{} passed", suite, passed, suite_results.len(), width = name_width);
+    }
+
+    let passed = results.iter().filter(|result| result.status == Status::Passed).count();
+    eprintln!("{:<width$}  {}/{} passed", "total", passed, results.len(), width = name_width);
+}
+
+fn create_report(results: &[RomResult], cycle_budget: usize) -> Value {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+    let passed = results.iter().filter(|result| result.status == Status::Passed).count();
+
+    return json!({
+        "timestamp": timestamp,
+        "version": env!("CARGO_PKG_VERSION"),
+        "cycle_budget": cycle_budget,
+        "passed": passed,
+        "total": results.len(),
+        "roms": results.iter().map(|result| json!({
+            "suite": result.suite,
+            "name": result.name,
+            "result": result.status.get_name(),
+            "detail": result.detail,
+            "cycles": result.cycles,
+            "millis": result.millis as u64
+        })).collect::<Vec<Value>>()
+    });
+}
+
+#[test]
+fn test_roms() {
+    let roms_dir = PathBuf::from(get_env_or("TEST_ROMS_DIR", DEFAULT_ROMS_DIR.to_string()));
+    let report_path = PathBuf::from(get_env_or("TEST_ROMS_REPORT", DEFAULT_REPORT_PATH.to_string()));
+    let cycle_budget: usize = get_env_or("TEST_ROMS_CYCLES", DEFAULT_CYCLE_BUDGET);
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    find_roms(&roms_dir, &mut paths);
+    if paths.is_empty() {
+        eprintln!("No test roms at \"{}\", skipping (set TEST_ROMS_DIR)", roms_dir.display());
+        return;
+    }
+    paths.sort();
+
+    let results: Vec<RomResult> = paths.iter().map(|path| run_path(&roms_dir, path, cycle_budget)).collect();
+    print_summary(&results);
+
+    if let Some(parent) = report_path.parent() {
+        std::fs::create_dir_all(parent).expect("Failed creating report directory");
+    }
+    let report = serde_json::to_string_pretty(&create_report(&results, cycle_budget)).unwrap();
+    std::fs::write(&report_path, report).expect("Failed writing report");
+    eprintln!("Report written to \"{}\"", report_path.display());
+}